@@ -0,0 +1,106 @@
+-- Wireshark dissector for the packets exported by `network_initializer pcap`.
+-- Copy it into the personal Lua plugins folder (Help > About > Folders).
+-- The packet layout is documented in src/capture.rs.
+
+local wg = Proto("wg2024", "WG 2024 drone protocol")
+
+local packet_types = {
+    [0] = "Fragment", [1] = "Ack", [2] = "Nack",
+    [3] = "FloodRequest", [4] = "FloodResponse",
+}
+local nack_types = {
+    [0] = "ErrorInRouting", [1] = "DestinationIsDrone",
+    [2] = "Dropped", [3] = "UnexpectedRecipient",
+}
+local node_types = { [0] = "Client", [1] = "Drone", [2] = "Server" }
+
+local f = wg.fields
+f.link_from = ProtoField.uint8("wg2024.link.from", "Link from")
+f.link_to = ProtoField.uint8("wg2024.link.to", "Link to")
+f.type = ProtoField.uint8("wg2024.type", "Packet type", base.DEC, packet_types)
+f.session = ProtoField.uint64("wg2024.session", "Session id")
+f.hop_index = ProtoField.uint16("wg2024.hop_index", "Hop index")
+f.hop_count = ProtoField.uint16("wg2024.hop_count", "Hop count")
+f.hop = ProtoField.uint8("wg2024.hop", "Hop")
+f.fragment_index = ProtoField.uint64("wg2024.fragment_index", "Fragment index")
+f.total_fragments = ProtoField.uint64("wg2024.total_fragments", "Total fragments")
+f.length = ProtoField.uint8("wg2024.length", "Length")
+f.data = ProtoField.bytes("wg2024.data", "Data")
+f.nack_type = ProtoField.uint8("wg2024.nack_type", "Nack type", base.DEC, nack_types)
+f.nack_node = ProtoField.uint8("wg2024.nack_node", "Nack node")
+f.flood_id = ProtoField.uint64("wg2024.flood_id", "Flood id")
+f.initiator = ProtoField.uint8("wg2024.initiator", "Initiator")
+f.trace_node = ProtoField.uint8("wg2024.trace.node", "Trace node")
+f.trace_type = ProtoField.uint8("wg2024.trace.type", "Trace node type", base.DEC, node_types)
+
+local function dissect_trace(buf, off, tree)
+    local len = buf(off, 2):uint()
+    off = off + 2
+    local trace = tree:add(wg, buf(off, len * 2), "Path trace (" .. len .. ")")
+    for _ = 1, len do
+        trace:add(f.trace_node, buf(off, 1))
+        trace:add(f.trace_type, buf(off + 1, 1))
+        off = off + 2
+    end
+    return off
+end
+
+local function dissect_packet(buf, pinfo, tree, off)
+    local kind = buf(off, 1):uint()
+    tree:add(f.type, buf(off, 1))
+    tree:add(f.session, buf(off + 1, 8))
+    tree:add(f.hop_index, buf(off + 9, 2))
+    local hops = buf(off + 11, 2):uint()
+    tree:add(f.hop_count, buf(off + 11, 2))
+    off = off + 13
+    local route = tree:add(wg, buf(off, hops), "Routing header")
+    for i = 0, hops - 1 do
+        route:add(f.hop, buf(off + i, 1))
+    end
+    off = off + hops
+
+    pinfo.cols.info = (packet_types[kind] or "Unknown") ..
+        " session=" .. tostring(buf(off - hops - 12, 8):uint64())
+    if kind == 0 then
+        tree:add(f.fragment_index, buf(off, 8))
+        tree:add(f.total_fragments, buf(off + 8, 8))
+        local len = buf(off + 16, 1):uint()
+        tree:add(f.length, buf(off + 16, 1))
+        if len > 0 then
+            tree:add(f.data, buf(off + 17, len))
+        end
+    elseif kind == 1 then
+        tree:add(f.fragment_index, buf(off, 8))
+    elseif kind == 2 then
+        tree:add(f.fragment_index, buf(off, 8))
+        tree:add(f.nack_type, buf(off + 8, 1))
+        tree:add(f.nack_node, buf(off + 9, 1))
+    elseif kind == 3 then
+        tree:add(f.flood_id, buf(off, 8))
+        tree:add(f.initiator, buf(off + 8, 1))
+        dissect_trace(buf, off + 9, tree)
+    elseif kind == 4 then
+        tree:add(f.flood_id, buf(off, 8))
+        dissect_trace(buf, off + 8, tree)
+    end
+end
+
+-- packets carried over UDP (`--encap udp`)
+function wg.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = "WG2024"
+    dissect_packet(buf, pinfo, tree:add(wg, buf()), 0)
+end
+DissectorTable.get("udp.port"):add(2024, wg)
+
+-- packets on link type USER0 (`--encap user`)
+local wg_user = Proto("wg2024_link", "WG 2024 link")
+function wg_user.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = "WG2024"
+    local t = tree:add(wg, buf())
+    t:add(f.link_from, buf(0, 1))
+    t:add(f.link_to, buf(1, 1))
+    pinfo.cols.src = tostring(buf(0, 1):uint())
+    pinfo.cols.dst = tostring(buf(1, 1):uint())
+    dissect_packet(buf, pinfo, t, 2)
+end
+DissectorTable.get("wtap_encap"):add(wtap.USER0, wg_user)
@@ -0,0 +1,406 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime},
+};
+
+use common::slc_commands::{ServerType, WebClientCommand, WebClientEvent};
+use log::error;
+use serde::{Deserialize, Serialize};
+use wg_2024::{controller::DroneCommand, network::NodeId, packet::PacketType};
+
+use crate::{
+    matrix::{build, Implementations, DEFAULT_TOPOLOGIES, FLOOD_DELAY},
+    network::{drone_factory, spawn_network, Network},
+    recorder::write_line,
+    router::{NodeEvent, ShortcutRouter},
+    tap::{Tap, TapFilter, TapRecord, TapSink},
+};
+
+/// files asked in turn to the text servers
+pub(super) const BENCH_FILES: [&str; 3] = [
+    "./public/file.html",
+    "./public/three.html",
+    "./public/index.html",
+];
+
+/// options of the `bench` subcommand
+#[derive(Debug)]
+pub(super) struct BenchOptions {
+    pub(super) topology: PathBuf,
+    /// indexes in the drone factory, every implementation if empty
+    pub(super) implementations: Vec<usize>,
+    pub(super) pdrs: Vec<f32>,
+    /// files requested by every web client
+    pub(super) requests: usize,
+    /// time given to a whole run
+    pub(super) timeout: Duration,
+    /// JSON lines file the results are appended to
+    pub(super) output: Option<PathBuf>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            topology: PathBuf::from(DEFAULT_TOPOLOGIES[0]),
+            implementations: Vec::new(),
+            pdrs: vec![0., 0.2],
+            requests: 10,
+            timeout: Duration::from_secs(120),
+            output: None,
+        }
+    }
+}
+
+impl BenchOptions {
+    /// arguments of the hidden `bench-case` subcommand running
+    /// `implementation` at `pdr`
+    pub(super) fn case_args(&self, implementation: usize, pdr: f32) -> Vec<String> {
+        vec![
+            "bench-case".to_owned(),
+            "--implementation".to_owned(),
+            implementation.to_string(),
+            "--topology".to_owned(),
+            self.topology.display().to_string(),
+            "--pdr".to_owned(),
+            pdr.to_string(),
+            "--requests".to_owned(),
+            self.requests.to_string(),
+            "--timeout".to_owned(),
+            self.timeout.as_secs_f64().to_string(),
+        ]
+    }
+}
+
+/// options of the hidden `bench-case` subcommand, running one
+/// implementation at one PDR in a process of its own
+#[derive(Debug)]
+pub(super) struct BenchCaseOptions {
+    pub(super) bench: BenchOptions,
+    pub(super) implementation: usize,
+    pub(super) pdr: f32,
+}
+
+/// fragments seen by the tap during a run
+#[derive(Debug, Default)]
+pub(super) struct FragmentStats {
+    /// fragments sent by the node that split the message,
+    /// retransmissions included
+    pub(super) sent: usize,
+    /// distinct `(session, origin, fragment index)` among the sent ones
+    unique: HashSet<(u64, NodeId, u64)>,
+    /// fragments that reached the end of their route
+    pub(super) delivered: usize,
+    /// when fragments reached each node, in the order the tap saw them
+    arrivals: HashMap<NodeId, Vec<SystemTime>>,
+}
+
+impl FragmentStats {
+    pub(super) fn add(&mut self, r: &TapRecord) {
+        let PacketType::MsgFragment(f) = &r.packet.pack_type else {
+            return;
+        };
+        let hops: &[NodeId] = &r.packet.routing_header.hops;
+        if hops.first() == Some(&r.from) {
+            self.sent += 1;
+            self.unique
+                .insert((r.packet.session_id, r.from, f.fragment_index));
+        }
+        if hops.last() == Some(&r.to) {
+            self.delivered += 1;
+            self.arrivals.entry(r.to).or_default().push(r.timestamp);
+        }
+    }
+
+    /// first fragment that reached `node` at or after `since`
+    pub(super) fn first_fragment(&self, node: NodeId, since: SystemTime) -> Option<SystemTime> {
+        self.arrivals
+            .get(&node)?
+            .iter()
+            .filter(|t| **t >= since)
+            .min()
+            .copied()
+    }
+
+    /// retransmitted fragments per fragment actually needed
+    #[allow(clippy::cast_precision_loss)]
+    pub(super) fn overhead(&self) -> f64 {
+        if self.unique.is_empty() {
+            return 0.;
+        }
+        (self.sent - self.unique.len()) as f64 / self.unique.len() as f64
+    }
+}
+
+/// feeds the fragments crossing the network to the shared stats
+struct StatsSink(Arc<Mutex<FragmentStats>>);
+
+impl TapSink for StatsSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        if let Ok(mut stats) = self.0.lock() {
+            stats.add(record);
+        }
+        Ok(())
+    }
+}
+
+/// nearest-rank percentile `p` (0 to 100) of the `sorted` values
+pub(super) fn percentile(sorted: &[Duration], p: usize) -> Option<Duration> {
+    let rank: usize = (sorted.len() * p).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+/// measures of a single run, times are in milliseconds
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct BenchResult {
+    pub(super) implementation: String,
+    pub(super) pdr: f32,
+    pub(super) requested: usize,
+    pub(super) completed: usize,
+    pub(super) failed: usize,
+    pub(super) latency_p50: Option<f64>,
+    pub(super) latency_p90: Option<f64>,
+    pub(super) latency_p99: Option<f64>,
+    /// median time from the request to the first fragment of the answer
+    pub(super) ttfb_p50: Option<f64>,
+    /// fragments that reached their destination, per second
+    pub(super) fragments_per_sec: f64,
+    pub(super) overhead: f64,
+}
+
+/// where a web client is in its sequence of requests
+#[derive(Default)]
+struct ClientState {
+    server: Option<NodeId>,
+    sent: usize,
+    requested: Option<SystemTime>,
+    done: bool,
+}
+
+/// asks the next file of the sequence to the server of `client`
+fn request_next(network: &Network, client: NodeId, state: &mut ClientState) {
+    let Some(server) = state.server else {
+        return;
+    };
+    let file: &str = BENCH_FILES[state.sent % BENCH_FILES.len()];
+    state.sent += 1;
+    state.requested = Some(SystemTime::now());
+    let _ = network.web_clients[&client]
+        .0
+        .send(WebClientCommand::RequestFile(file.to_owned(), server));
+}
+
+fn millis(d: Option<Duration>) -> Option<f64> {
+    d.map(|d| d.as_secs_f64() * 1000.)
+}
+
+/// builds the network, has every web client request `opts.requests` files
+/// one after the other and measures the transfers
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn run_bench_case(
+    opts: &BenchOptions,
+    implementations: Implementations,
+    pdr: f32,
+) -> Result<BenchResult, String> {
+    let (config, assigned) = build(&opts.topology, pdr, implementations)?;
+    let stats: Arc<Mutex<FragmentStats>> = Arc::default();
+    let filter = TapFilter {
+        types: vec!["fragment".to_owned()],
+        ..TapFilter::default()
+    };
+    let tap: Tap = Tap::start(filter, vec![Box::new(StatsSink(Arc::clone(&stats)))]);
+    let network: Network = spawn_network(&config, &assigned, Some(&tap));
+    let router = ShortcutRouter::from_network(&network);
+    router.wait_for(|_| false, FLOOD_DELAY);
+
+    let mut clients: HashMap<NodeId, ClientState> = network
+        .web_clients
+        .keys()
+        .map(|id| (*id, ClientState::default()))
+        .collect();
+    if clients.is_empty() {
+        return Err("no web client".to_owned());
+    }
+    for (commands, ..) in network.web_clients.values() {
+        let _ = commands.send(WebClientCommand::AskServersTypes);
+    }
+    let mut latencies: Vec<Duration> = Vec::new();
+    // client and request time of every file received, the first fragments
+    // are looked up once the tap has caught up
+    let mut answered: Vec<(NodeId, SystemTime)> = Vec::new();
+    let mut failed: usize = 0;
+    let start: Instant = Instant::now();
+    let mut last: Instant = start;
+    router.wait_for(
+        |e: &NodeEvent| {
+            let NodeEvent::WebClient(id, e) = e else {
+                return false;
+            };
+            let Some(state) = clients.get_mut(id) else {
+                return false;
+            };
+            match e {
+                WebClientEvent::ServersTypes(types) if state.server.is_none() => {
+                    state.server = types
+                        .iter()
+                        .filter(|(_, t)| matches!(t, ServerType::FileServer))
+                        .map(|(id, _)| *id)
+                        .min();
+                    if state.server.is_some() && state.sent < opts.requests {
+                        request_next(&network, *id, state);
+                    } else {
+                        state.done = true;
+                    }
+                }
+                WebClientEvent::FileFromClient(..) | WebClientEvent::UnsupportedRequest => {
+                    let now: SystemTime = SystemTime::now();
+                    let requested: Option<SystemTime> = state.requested.take();
+                    match (e, requested) {
+                        (WebClientEvent::FileFromClient(..), Some(t)) => {
+                            latencies.push(now.duration_since(t).unwrap_or_default());
+                            answered.push((*id, t));
+                        }
+                        _ => failed += 1,
+                    }
+                    last = Instant::now();
+                    if state.sent < opts.requests {
+                        request_next(&network, *id, state);
+                    } else {
+                        state.done = true;
+                    }
+                }
+                _ => {}
+            }
+            clients.values().all(|c| c.done)
+        },
+        opts.timeout,
+    );
+    for (commands, ..) in network.drones.values() {
+        let _ = commands.send(DroneCommand::Crash);
+    }
+    tap.drain();
+
+    let stats: MutexGuard<FragmentStats> = stats.lock().map_err(|e| e.to_string())?;
+    // a client asks its next file only once the previous one arrived, so
+    // the first fragment after the request belongs to the answer
+    let mut ttfbs: Vec<Duration> = answered
+        .iter()
+        .filter_map(|(id, t)| stats.first_fragment(*id, *t)?.duration_since(*t).ok())
+        .collect();
+    latencies.sort();
+    ttfbs.sort();
+    let elapsed: f64 = last.duration_since(start).as_secs_f64();
+    let (delivered, overhead): (usize, f64) = (stats.delivered, stats.overhead());
+    Ok(BenchResult {
+        implementation: implementations.to_string(),
+        pdr,
+        // clients that found no file server had nothing to ask for
+        requested: clients.values().filter(|c| c.server.is_some()).count() * opts.requests,
+        completed: latencies.len(),
+        failed,
+        latency_p50: millis(percentile(&latencies, 50)),
+        latency_p90: millis(percentile(&latencies, 90)),
+        latency_p99: millis(percentile(&latencies, 99)),
+        ttfb_p50: millis(percentile(&ttfbs, 50)),
+        fragments_per_sec: if elapsed > 0. {
+            delivered as f64 / elapsed
+        } else {
+            0.
+        },
+        overhead,
+    })
+}
+
+/// runs `run_bench_case` in a child process, so that the nodes of a case
+/// don't slow down the next ones
+pub(super) fn run_isolated_case(
+    opts: &BenchOptions,
+    implementation: usize,
+    pdr: f32,
+) -> Result<BenchResult, String> {
+    let output = env::current_exe()
+        .and_then(|exe| {
+            Command::new(exe)
+                .args(opts.case_args(implementation, pdr))
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output()
+        })
+        .map_err(|e| format!("unable to run the case: {e}"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .last()
+        .and_then(|l| serde_json::from_str(l).ok())
+        .ok_or_else(|| "the bench process failed".to_owned())
+}
+
+fn cell(ms: Option<f64>) -> String {
+    ms.map_or_else(|| "-".to_owned(), |ms| format!("{ms:.1}"))
+}
+
+/// runs the benchmark for every implementation and PDR, printing a row of
+/// the table as soon as it's done
+pub(super) fn run_bench(opts: &BenchOptions) -> Vec<BenchResult> {
+    let implementations: Vec<usize> = if opts.implementations.is_empty() {
+        (0..drone_factory().len()).collect()
+    } else {
+        opts.implementations.clone()
+    };
+    let mut output: Option<BufWriter<_>> = opts.output.as_ref().and_then(|path| {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(e) => {
+                error!("Unable to open {}: {e}", path.display());
+                None
+            }
+        }
+    });
+    println!(
+        "{:<16} {:>5} {:>9} {:>9} {:>9} {:>9} {:>9} {:>8} {:>8}",
+        "implementation",
+        "pdr",
+        "done",
+        "p50 ms",
+        "p90 ms",
+        "p99 ms",
+        "ttfb ms",
+        "frag/s",
+        "overhead"
+    );
+    let mut results: Vec<BenchResult> = Vec::new();
+    for i in implementations {
+        for &pdr in &opts.pdrs {
+            let r: BenchResult = match run_isolated_case(opts, i, pdr) {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Unable to run {}: {e}", drone_factory()[i].0);
+                    continue;
+                }
+            };
+            println!(
+                "{:<16} {:>5.2} {:>9} {:>9} {:>9} {:>9} {:>9} {:>8.1} {:>7.1}%",
+                r.implementation,
+                r.pdr,
+                format!("{}/{}", r.completed, r.requested),
+                cell(r.latency_p50),
+                cell(r.latency_p90),
+                cell(r.latency_p99),
+                cell(r.ttfb_p50),
+                r.fragments_per_sec,
+                r.overhead * 100.
+            );
+            if let Some(out) = &mut output {
+                if let Err(e) = write_line(out, &r).and_then(|()| out.flush()) {
+                    error!("Unable to write bench results: {e}");
+                }
+            }
+            results.push(r);
+        }
+    }
+    results
+}
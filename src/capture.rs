@@ -0,0 +1,337 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use wg_2024::{
+    network::{NodeId, SourceRoutingHeader},
+    packet::{
+        Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
+        FRAGMENT_DSIZE,
+    },
+};
+
+use crate::tap::{TapRecord, TapSink};
+
+/// first bytes of every capture file
+pub(super) const CAPTURE_MAGIC: &[u8; 6] = b"WGCAP\0";
+/// version of the record and packet layout below
+pub(super) const CAPTURE_VERSION: u8 = 1;
+/// longest encoded packet accepted, far above any real one
+const MAX_PACKET: usize = 1 << 20;
+
+/*
+    Capture layout (every integer is big endian):
+
+    header: magic (6 bytes) | version (u8)
+    record: timestamp in µs since UNIX epoch (u64) | from (u8) | to (u8)
+            | packet length (u32) | packet
+
+    packet: type (u8) | session id (u64) | hop index (u16) | hop count (u16)
+            | hops (u8 each) | body
+
+    fragment       (0): fragment index (u64) | total fragments (u64) | length (u8)
+                        | data (`length` bytes)
+    ack            (1): fragment index (u64)
+    nack           (2): fragment index (u64) | nack type (u8) | node id (u8, 0 if unused)
+    flood request  (3): flood id (u64) | initiator (u8) | trace length (u16)
+                        | (node id (u8), node type (u8)) for every hop
+    flood response (4): flood id (u64) | trace length (u16)
+                        | (node id (u8), node type (u8)) for every hop
+
+    nack types: 0 ErrorInRouting, 1 DestinationIsDrone, 2 Dropped, 3 UnexpectedRecipient
+    node types: 0 Client, 1 Drone, 2 Server
+*/
+
+pub(super) const FRAGMENT_TYPE: u8 = 0;
+pub(super) const ACK_TYPE: u8 = 1;
+pub(super) const NACK_TYPE: u8 = 2;
+pub(super) const FLOOD_REQUEST_TYPE: u8 = 3;
+pub(super) const FLOOD_RESPONSE_TYPE: u8 = 4;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn len_u16(len: usize) -> io::Result<u16> {
+    u16::try_from(len).map_err(|_| invalid("sequence too long"))
+}
+
+fn encode_node_type(t: &NodeType) -> u8 {
+    match t {
+        NodeType::Client => 0,
+        NodeType::Drone => 1,
+        NodeType::Server => 2,
+    }
+}
+
+fn encode_trace(trace: &[(NodeId, NodeType)], out: &mut Vec<u8>) -> io::Result<()> {
+    out.extend(len_u16(trace.len())?.to_be_bytes());
+    for (id, t) in trace {
+        out.extend([*id, encode_node_type(t)]);
+    }
+    Ok(())
+}
+
+/// appends the binary form of `packet` to `out`
+pub(super) fn encode_packet(packet: &Packet, out: &mut Vec<u8>) -> io::Result<()> {
+    let hops: &[NodeId] = &packet.routing_header.hops;
+    let kind: u8 = match packet.pack_type {
+        PacketType::MsgFragment(_) => FRAGMENT_TYPE,
+        PacketType::Ack(_) => ACK_TYPE,
+        PacketType::Nack(_) => NACK_TYPE,
+        PacketType::FloodRequest(_) => FLOOD_REQUEST_TYPE,
+        PacketType::FloodResponse(_) => FLOOD_RESPONSE_TYPE,
+    };
+    out.push(kind);
+    out.extend(packet.session_id.to_be_bytes());
+    out.extend(len_u16(packet.routing_header.hop_index)?.to_be_bytes());
+    out.extend(len_u16(hops.len())?.to_be_bytes());
+    out.extend(hops);
+    match &packet.pack_type {
+        PacketType::MsgFragment(f) => {
+            out.extend(f.fragment_index.to_be_bytes());
+            out.extend(f.total_n_fragments.to_be_bytes());
+            out.push(f.length);
+            out.extend(&f.data[..usize::from(f.length).min(FRAGMENT_DSIZE)]);
+        }
+        PacketType::Ack(a) => out.extend(a.fragment_index.to_be_bytes()),
+        PacketType::Nack(n) => {
+            out.extend(n.fragment_index.to_be_bytes());
+            out.extend(match n.nack_type {
+                NackType::ErrorInRouting(id) => [0, id],
+                NackType::DestinationIsDrone => [1, 0],
+                NackType::Dropped => [2, 0],
+                NackType::UnexpectedRecipient(id) => [3, id],
+            });
+        }
+        PacketType::FloodRequest(f) => {
+            out.extend(f.flood_id.to_be_bytes());
+            out.push(f.initiator_id);
+            encode_trace(&f.path_trace, out)?;
+        }
+        PacketType::FloodResponse(f) => {
+            out.extend(f.flood_id.to_be_bytes());
+            encode_trace(&f.path_trace, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// cursor over an encoded packet
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.buf.len() < n {
+            return Err(invalid("truncated packet"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(b))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+
+    fn node_type(&mut self) -> io::Result<NodeType> {
+        match self.u8()? {
+            0 => Ok(NodeType::Client),
+            1 => Ok(NodeType::Drone),
+            2 => Ok(NodeType::Server),
+            _ => Err(invalid("unknown node type")),
+        }
+    }
+
+    fn trace(&mut self) -> io::Result<Vec<(NodeId, NodeType)>> {
+        let len: u16 = self.u16()?;
+        (0..len)
+            .map(|_| Ok((self.u8()?, self.node_type()?)))
+            .collect()
+    }
+}
+
+/// inverse of [`encode_packet`]
+pub(super) fn decode_packet(buf: &[u8]) -> io::Result<Packet> {
+    let mut d = Decoder { buf };
+    let kind: u8 = d.u8()?;
+    let session_id: u64 = d.u64()?;
+    let hop_index: usize = usize::from(d.u16()?);
+    let hop_count: usize = usize::from(d.u16()?);
+    let hops: Vec<NodeId> = d.take(hop_count)?.to_vec();
+    let pack_type: PacketType = match kind {
+        FRAGMENT_TYPE => {
+            let fragment_index: u64 = d.u64()?;
+            let total_n_fragments: u64 = d.u64()?;
+            let length: u8 = d.u8()?;
+            if usize::from(length) > FRAGMENT_DSIZE {
+                return Err(invalid("fragment too long"));
+            }
+            let mut data = [0; FRAGMENT_DSIZE];
+            data[..usize::from(length)].copy_from_slice(d.take(usize::from(length))?);
+            PacketType::MsgFragment(Fragment {
+                fragment_index,
+                total_n_fragments,
+                length,
+                data,
+            })
+        }
+        ACK_TYPE => PacketType::Ack(Ack {
+            fragment_index: d.u64()?,
+        }),
+        NACK_TYPE => {
+            let fragment_index: u64 = d.u64()?;
+            let nack_type: NackType = match (d.u8()?, d.u8()?) {
+                (0, id) => NackType::ErrorInRouting(id),
+                (1, _) => NackType::DestinationIsDrone,
+                (2, _) => NackType::Dropped,
+                (3, id) => NackType::UnexpectedRecipient(id),
+                _ => return Err(invalid("unknown nack type")),
+            };
+            PacketType::Nack(Nack {
+                fragment_index,
+                nack_type,
+            })
+        }
+        FLOOD_REQUEST_TYPE => PacketType::FloodRequest(FloodRequest {
+            flood_id: d.u64()?,
+            initiator_id: d.u8()?,
+            path_trace: d.trace()?,
+        }),
+        FLOOD_RESPONSE_TYPE => PacketType::FloodResponse(FloodResponse {
+            flood_id: d.u64()?,
+            path_trace: d.trace()?,
+        }),
+        _ => return Err(invalid("unknown packet type")),
+    };
+    Ok(Packet {
+        routing_header: SourceRoutingHeader { hop_index, hops },
+        session_id,
+        pack_type,
+    })
+}
+
+/// appends the binary form of `record` to `out`
+pub(super) fn encode_record(record: &TapRecord, out: &mut Vec<u8>) -> io::Result<()> {
+    let mut packet: Vec<u8> = Vec::new();
+    encode_packet(&record.packet, &mut packet)?;
+    out.extend(record.micros().to_be_bytes());
+    out.extend([record.from, record.to]);
+    out.extend(
+        u32::try_from(packet.len())
+            .map_err(|_| invalid("packet too long"))?
+            .to_be_bytes(),
+    );
+    out.extend(packet);
+    Ok(())
+}
+
+/// tap sink writing a capture file
+pub(super) struct CaptureWriter<W: Write + Send> {
+    out: W,
+    buf: Vec<u8>,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    pub(super) fn create(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Send> CaptureWriter<W> {
+    pub(super) fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(CAPTURE_MAGIC)?;
+        out.write_all(&[CAPTURE_VERSION])?;
+        Ok(Self {
+            out,
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl<W: Write + Send> TapSink for CaptureWriter<W> {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        self.buf.clear();
+        encode_record(record, &mut self.buf)?;
+        self.out.write_all(&self.buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// iterator over the records of a capture file
+pub(super) struct CaptureReader<R: Read> {
+    input: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub(super) fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub(super) fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 7];
+        input.read_exact(&mut header)?;
+        if &header[..6] != CAPTURE_MAGIC {
+            return Err(invalid("not a capture file"));
+        }
+        if header[6] != CAPTURE_VERSION {
+            return Err(invalid("unsupported capture version"));
+        }
+        Ok(Self { input })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<TapRecord>> {
+        let mut head = [0; 14];
+        match self.input.read_exact(&mut head[..1]) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            r => r?,
+        }
+        self.input.read_exact(&mut head[1..])?;
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&head[..8]);
+        let mut len = [0; 4];
+        len.copy_from_slice(&head[10..]);
+        let len: usize = usize::try_from(u32::from_be_bytes(len)).unwrap_or(usize::MAX);
+        if len > MAX_PACKET {
+            return Err(invalid("bad packet length"));
+        }
+        let mut packet: Vec<u8> = vec![0; len];
+        self.input.read_exact(&mut packet)?;
+        Ok(Some(TapRecord {
+            timestamp: UNIX_EPOCH + Duration::from_micros(u64::from_be_bytes(micros)),
+            from: head[8],
+            to: head[9],
+            packet: decode_packet(&packet)?,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<TapRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use common::slc_commands::{ChatClientCommand, ServerCommand, WebClientCommand};
+use crossbeam_channel::Sender;
+use log::{error, info};
+use rand::Rng;
+use serde::Serialize;
+use wg_2024::{config::Config, controller::DroneCommand, network::NodeId};
+
+use crate::{
+    network::Network,
+    random::{rng_for, SimRng},
+    recorder::{now_micros, write_line},
+    respawn::Respawner,
+    topology_utils::topology_error,
+};
+
+/// faults tried before giving up on a tick, most of them are rejected only
+/// on small topologies
+const ATTEMPTS: usize = 10;
+
+/// options of the chaos controller
+#[derive(Debug)]
+pub(super) struct ChaosOptions {
+    /// time between two faults
+    pub(super) interval: Duration,
+    /// highest PDR set by `SetPacketDropRate`
+    pub(super) max_pdr: f32,
+    /// JSON lines file receiving every injected fault
+    pub(super) log: Option<PathBuf>,
+}
+
+impl Default for ChaosOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            max_pdr: 0.5,
+            log: None,
+        }
+    }
+}
+
+/// a fault injected in the network
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "fault", rename_all = "snake_case")]
+pub(super) enum Fault {
+    Crash {
+        drone: NodeId,
+    },
+    SetPacketDropRate {
+        drone: NodeId,
+        pdr: f32,
+    },
+    /// both ends of a link between two drones forget each other
+    LinkDown {
+        a: NodeId,
+        b: NodeId,
+    },
+    /// a link taken down on the previous tick is restored
+    LinkUp {
+        a: NodeId,
+        b: NodeId,
+    },
+}
+
+/// line of the chaos log
+#[derive(Debug, Serialize)]
+struct FaultRecord<'a> {
+    /// microseconds since the UNIX epoch, as in the event log
+    timestamp: u64,
+    #[serde(flatten)]
+    fault: &'a Fault,
+}
+
+fn unlink(connected: &mut Vec<NodeId>, id: NodeId) {
+    connected.retain(|&n| n != id);
+}
+
+/// applies `fault` to the topology described by `config`
+pub(super) fn apply(config: &mut Config, fault: &Fault) {
+    match *fault {
+        Fault::Crash { drone } => {
+            config.drone.retain(|d| d.id != drone);
+            for d in &mut config.drone {
+                unlink(&mut d.connected_node_ids, drone);
+            }
+            for c in &mut config.client {
+                unlink(&mut c.connected_drone_ids, drone);
+            }
+            for s in &mut config.server {
+                unlink(&mut s.connected_drone_ids, drone);
+            }
+        }
+        Fault::SetPacketDropRate { drone, pdr } => {
+            if let Some(d) = config.drone.iter_mut().find(|d| d.id == drone) {
+                d.pdr = pdr;
+            }
+        }
+        Fault::LinkDown { a, b } => {
+            for d in &mut config.drone {
+                if d.id == a {
+                    unlink(&mut d.connected_node_ids, b);
+                } else if d.id == b {
+                    unlink(&mut d.connected_node_ids, a);
+                }
+            }
+        }
+        Fault::LinkUp { a, b } => {
+            for d in &mut config.drone {
+                if d.id == a {
+                    d.connected_node_ids.push(b);
+                } else if d.id == b {
+                    d.connected_node_ids.push(a);
+                }
+            }
+        }
+    }
+}
+
+/// the topology as the chaos controller left it
+pub(super) struct ChaosState {
+    pub(super) config: Config,
+    /// links to restore on the next tick
+    down: Vec<(NodeId, NodeId)>,
+    max_pdr: f32,
+}
+
+impl ChaosState {
+    pub(super) fn new(config: Config, max_pdr: f32) -> Self {
+        Self {
+            config,
+            down: Vec::new(),
+            max_pdr,
+        }
+    }
+
+    /// a random fault, not checked yet
+    fn candidate(&self, rng: &mut SimRng) -> Option<Fault> {
+        let drones: Vec<NodeId> = self.config.drone.iter().map(|d| d.id).collect();
+        if drones.is_empty() {
+            return None;
+        }
+        let drone: NodeId = drones[rng.gen_range(0, drones.len())];
+        match rng.gen_range(0, 3) {
+            0 => Some(Fault::Crash { drone }),
+            1 => Some(Fault::SetPacketDropRate {
+                drone,
+                pdr: if self.max_pdr > 0. {
+                    rng.gen_range(0., self.max_pdr)
+                } else {
+                    0.
+                },
+            }),
+            _ => {
+                let links: Vec<NodeId> = self
+                    .config
+                    .drone
+                    .iter()
+                    .find(|d| d.id == drone)?
+                    .connected_node_ids
+                    .iter()
+                    .copied()
+                    .filter(|n| drones.contains(n))
+                    .collect();
+                if links.is_empty() {
+                    return None;
+                }
+                let b: NodeId = links[rng.gen_range(0, links.len())];
+                Some(Fault::LinkDown { a: drone, b })
+            }
+        }
+    }
+
+    /// restores the links taken down on the previous tick and picks a new
+    /// fault that keeps the topology valid; returns the faults to inject,
+    /// already applied to the topology
+    pub(super) fn tick(&mut self, rng: &mut SimRng) -> Vec<Fault> {
+        let alive = |config: &Config, id: NodeId| config.drone.iter().any(|d| d.id == id);
+        let mut faults: Vec<Fault> = self
+            .down
+            .drain(..)
+            .filter(|&(a, b)| alive(&self.config, a) && alive(&self.config, b))
+            .map(|(a, b)| Fault::LinkUp { a, b })
+            .collect();
+        for f in &faults {
+            apply(&mut self.config, f);
+        }
+
+        for _ in 0..ATTEMPTS {
+            let Some(fault) = self.candidate(rng) else {
+                continue;
+            };
+            let mut next: Config = self.config.clone();
+            apply(&mut next, &fault);
+            if topology_error(&next.drone, &next.client, &next.server).is_none() {
+                if let Fault::LinkDown { a, b } = fault {
+                    self.down.push((a, b));
+                }
+                self.config = next;
+                faults.push(fault);
+                break;
+            }
+        }
+        faults
+    }
+}
+
+/// every command sender the chaos controller needs, cloned from the network
+struct Targets {
+    drones: HashMap<NodeId, Sender<DroneCommand>>,
+    web_clients: HashMap<NodeId, Sender<WebClientCommand>>,
+    chat_clients: HashMap<NodeId, Sender<ChatClientCommand>>,
+    servers: HashMap<NodeId, Sender<ServerCommand>>,
+    /// builds the links restored by `LinkUp`
+    respawner: Arc<Respawner>,
+}
+
+impl Targets {
+    fn new(network: &Network, respawner: Arc<Respawner>) -> Self {
+        Self {
+            drones: network
+                .drones
+                .iter()
+                .map(|(id, (c, ..))| (*id, c.clone()))
+                .collect(),
+            web_clients: network
+                .web_clients
+                .iter()
+                .map(|(id, (c, ..))| (*id, c.clone()))
+                .collect(),
+            chat_clients: network
+                .chat_clients
+                .iter()
+                .map(|(id, (c, ..))| (*id, c.clone()))
+                .collect(),
+            servers: network
+                .servers
+                .iter()
+                .map(|(id, (c, ..))| (*id, c.clone()))
+                .collect(),
+            respawner,
+        }
+    }
+
+    fn drone(&self, id: NodeId, command: DroneCommand) {
+        if let Some(c) = self.drones.get(&id) {
+            let _ = c.send(command);
+        }
+    }
+
+    /// tells `node`, whatever its kind, to forget `neighbour`
+    fn remove_sender(&self, node: NodeId, neighbour: NodeId) {
+        if self.drones.contains_key(&node) {
+            self.drone(node, DroneCommand::RemoveSender(neighbour));
+        } else if let Some(c) = self.web_clients.get(&node) {
+            let _ = c.send(WebClientCommand::RemoveSender(neighbour));
+        } else if let Some(c) = self.chat_clients.get(&node) {
+            let _ = c.send(ChatClientCommand::RemoveSender(neighbour));
+        } else if let Some(c) = self.servers.get(&node) {
+            let _ = c.send(ServerCommand::RemoveSender(neighbour));
+        }
+    }
+
+    /// sends the commands of `fault`, `before` is the topology it was
+    /// applied to
+    fn inject(&self, fault: &Fault, before: &Config) {
+        match *fault {
+            Fault::Crash { drone } => {
+                self.drone(drone, DroneCommand::Crash);
+                let neighbours: Vec<NodeId> = before
+                    .drone
+                    .iter()
+                    .find(|d| d.id == drone)
+                    .map(|d| d.connected_node_ids.clone())
+                    .unwrap_or_default();
+                for n in neighbours {
+                    self.remove_sender(n, drone);
+                }
+            }
+            Fault::SetPacketDropRate { drone, pdr } => {
+                self.drone(drone, DroneCommand::SetPacketDropRate(pdr));
+            }
+            Fault::LinkDown { a, b } => {
+                self.drone(a, DroneCommand::RemoveSender(b));
+                self.drone(b, DroneCommand::RemoveSender(a));
+            }
+            Fault::LinkUp { a, b } => {
+                for (from, to) in [(a, b), (b, a)] {
+                    for (n, sender) in self.respawner.links(from, &[to]) {
+                        self.drone(from, DroneCommand::AddSender(n, sender));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// injects a fault every `opts.interval` on its own thread, for as long as
+/// the process runs; `config` is the topology the network was built from
+pub(super) fn start_chaos(
+    network: &Network,
+    config: &Config,
+    seed: u64,
+    opts: &ChaosOptions,
+    respawner: Arc<Respawner>,
+) {
+    let mut out: Option<BufWriter<File>> = opts.log.as_ref().and_then(|path| {
+        File::create(path)
+            .map(BufWriter::new)
+            .map_err(|e| error!("Unable to create chaos log {}: {e}", path.display()))
+            .ok()
+    });
+    let targets = Targets::new(network, respawner);
+    let mut state = ChaosState::new(config.clone(), opts.max_pdr);
+    let mut rng: SimRng = rng_for(seed, "chaos");
+    let interval: Duration = opts.interval;
+    thread::spawn(move || loop {
+        sleep(interval);
+        let mut before: Config = state.config.clone();
+        for fault in state.tick(&mut rng) {
+            info!("Chaos: {fault:?}");
+            targets.inject(&fault, &before);
+            apply(&mut before, &fault);
+            if let Some(out) = &mut out {
+                let record = FaultRecord {
+                    timestamp: now_micros(),
+                    fault: &fault,
+                };
+                if let Err(e) = write_line(out, &record).and_then(|()| out.flush()) {
+                    error!("Unable to write chaos log: {e}");
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,585 @@
+use std::{path::PathBuf, str::FromStr};
+
+use std::time::Duration;
+
+use log::LevelFilter;
+use wg_2024::network::NodeId;
+
+use crate::{
+    bench::{BenchCaseOptions, BenchOptions},
+    chaos::ChaosOptions,
+    conformance::ConformanceOptions,
+    diff::DiffOptions,
+    formatter::FmtOptions,
+    inspect::InspectOptions,
+    ipc::NodeOptions,
+    logging::LoggingOptions,
+    matrix::{Content, Implementations, MatrixOptions, Scenario, ScenarioOptions},
+    network::drone_factory,
+    pcap::PcapOptions,
+    random::Assignment,
+    replay::{ReplayOptions, DEFAULT_REPLAY_WAIT},
+    rpc::RpcEndpoint,
+    schema::SchemaOptions,
+    supervisor::ProcessOptions,
+    tap::TapFilter,
+};
+
+/// config used when none is given on the command line
+pub(super) const DEFAULT_CONFIG: &str = "config/test_chat_config.toml";
+
+/// how the packet tap should be set up
+#[derive(Debug, Default)]
+pub(super) struct TapOptions {
+    pub(super) filter: TapFilter,
+    /// print tapped packets through the logger
+    pub(super) log: bool,
+    /// write tapped packets, one per line, to this file
+    pub(super) text_file: Option<PathBuf>,
+    /// record tapped packets in a capture file
+    pub(super) capture_file: Option<PathBuf>,
+}
+
+/// options of a simulation run
+#[derive(Debug)]
+pub(super) struct RunOptions {
+    pub(super) config: PathBuf,
+    pub(super) tap: Option<TapOptions>,
+    /// seed of every random choice, a fresh one is logged if missing
+    pub(super) seed: Option<u64>,
+    pub(super) assignment: Assignment,
+    /// overrides the PDRs of the config with random ones below this value
+    pub(super) random_pdr: Option<f32>,
+    /// JSON lines file receiving every event sent to the controller
+    pub(super) event_log: Option<PathBuf>,
+    /// file receiving every command sent by the controller, for replays
+    pub(super) recording: Option<PathBuf>,
+    /// serve the metrics on 127.0.0.1 at this port (0 picks a free one)
+    pub(super) metrics_port: Option<u16>,
+    /// log the metrics with this period
+    pub(super) metrics_interval: Option<Duration>,
+    /// serve the dashboard on 127.0.0.1 at this port (0 picks a free one)
+    pub(super) dashboard_port: Option<u16>,
+    /// serve the JSON-RPC API there
+    pub(super) rpc: Option<RpcEndpoint>,
+    /// run without the simulation controller GUI
+    pub(super) headless: bool,
+    /// run a console in place of the simulation controller GUI
+    pub(super) repl: bool,
+    /// run the terminal UI in place of the simulation controller GUI
+    pub(super) tui: bool,
+    /// stop a headless run after this long, run forever if missing
+    pub(super) duration: Option<Duration>,
+    /// inject random faults while the network runs
+    pub(super) chaos: Option<ChaosOptions>,
+    /// run every drone in a child process
+    pub(super) processes: Option<ProcessOptions>,
+    /// config file receiving the topology left at the end of the run
+    pub(super) save: Option<PathBuf>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            config: PathBuf::from(DEFAULT_CONFIG),
+            tap: None,
+            seed: None,
+            assignment: Assignment::default(),
+            random_pdr: None,
+            event_log: None,
+            recording: None,
+            metrics_port: None,
+            metrics_interval: None,
+            dashboard_port: None,
+            rpc: None,
+            headless: false,
+            repl: false,
+            tui: false,
+            duration: None,
+            chaos: None,
+            processes: None,
+            save: None,
+        }
+    }
+}
+
+/// subcommands of the executable, running a simulation is the default
+#[derive(Debug)]
+pub(super) enum Command {
+    Run(RunOptions),
+    Inspect(InspectOptions),
+    Pcap(PcapOptions),
+    Replay(ReplayOptions),
+    Matrix(MatrixOptions),
+    Conformance(ConformanceOptions),
+    Bench(BenchOptions),
+    Diff(DiffOptions),
+    Fmt(FmtOptions),
+    Schema(SchemaOptions),
+    /// a drone process of the multi-process mode, not meant to be typed
+    Node(NodeOptions),
+    /// a scenario of the matrix run by `matrix`, not meant to be typed
+    Scenario(ScenarioOptions),
+    /// a case of the benchmark run by `bench`, not meant to be typed
+    BenchCase(BenchCaseOptions),
+}
+
+/// parses the value following `flag`
+fn value<T: FromStr>(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<T, String> {
+    let raw: String = args
+        .next()
+        .ok_or_else(|| format!("Missing value for {flag}"))?;
+    raw.parse()
+        .map_err(|_| format!("Invalid value for {flag}: {raw}"))
+}
+
+/// parses the value following `flag` as a number of seconds
+fn seconds(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<Duration, String> {
+    let secs: f64 = value(flag, args)?;
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("Invalid value for {flag}: {secs}"))
+}
+
+/// parses the arguments of a simulation run (program name excluded)
+fn parse_run_options(args: impl IntoIterator<Item = String>) -> Result<RunOptions, String> {
+    let mut opts = RunOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" | "-c" => opts.config = value(&arg, &mut args)?,
+            "--seed" => opts.seed = Some(value(&arg, &mut args)?),
+            "--assign" => opts.assignment = value(&arg, &mut args)?,
+            "--random-pdr" => opts.random_pdr = Some(value(&arg, &mut args)?),
+            "--record-events" => opts.event_log = Some(value(&arg, &mut args)?),
+            "--record" => opts.recording = Some(value(&arg, &mut args)?),
+            "--metrics-port" => opts.metrics_port = Some(value(&arg, &mut args)?),
+            "--metrics-interval" => opts.metrics_interval = Some(seconds(&arg, &mut args)?),
+            "--dashboard" => opts.dashboard_port = Some(value(&arg, &mut args)?),
+            "--rpc" => opts.rpc = Some(value(&arg, &mut args)?),
+            "--headless" => opts.headless = true,
+            "--repl" => opts.repl = true,
+            "--tui" => opts.tui = true,
+            "--duration" => opts.duration = Some(seconds(&arg, &mut args)?),
+            "--save" => opts.save = Some(value(&arg, &mut args)?),
+            "--chaos" => {
+                opts.chaos
+                    .get_or_insert_with(ChaosOptions::default)
+                    .interval = seconds(&arg, &mut args)?;
+            }
+            "--chaos-max-pdr" => {
+                opts.chaos.get_or_insert_with(ChaosOptions::default).max_pdr =
+                    value(&arg, &mut args)?;
+            }
+            "--chaos-log" => {
+                opts.chaos.get_or_insert_with(ChaosOptions::default).log =
+                    Some(value(&arg, &mut args)?);
+            }
+            "--processes" => {
+                opts.processes.get_or_insert_with(ProcessOptions::default);
+            }
+            "--restart-dead" => {
+                opts.processes
+                    .get_or_insert_with(ProcessOptions::default)
+                    .restart = true;
+            }
+            "--tap" => opts.tap.get_or_insert_with(TapOptions::default).log = true,
+            "--tap-file" => {
+                opts.tap.get_or_insert_with(TapOptions::default).text_file =
+                    Some(value(&arg, &mut args)?);
+            }
+            "--capture" => {
+                opts.tap
+                    .get_or_insert_with(TapOptions::default)
+                    .capture_file = Some(value(&arg, &mut args)?);
+            }
+            "--tap-node" => {
+                let id = value(&arg, &mut args)?;
+                let tap = opts.tap.get_or_insert_with(TapOptions::default);
+                tap.filter.nodes.push(id);
+            }
+            "--tap-session" => {
+                let id = value(&arg, &mut args)?;
+                let tap = opts.tap.get_or_insert_with(TapOptions::default);
+                tap.filter.sessions.push(id);
+            }
+            "--tap-type" => {
+                let t = value(&arg, &mut args)?;
+                let tap = opts.tap.get_or_insert_with(TapOptions::default);
+                tap.filter.types.push(t);
+            }
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the `inspect` subcommand
+fn parse_inspect_options(args: impl IntoIterator<Item = String>) -> Result<InspectOptions, String> {
+    let mut opts = InspectOptions::default();
+    let mut file: Option<PathBuf> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--node" => opts.filter.nodes.push(value(&arg, &mut args)?),
+            "--session" => opts.filter.sessions.push(value(&arg, &mut args)?),
+            "--type" => opts.filter.types.push(value(&arg, &mut args)?),
+            "--messages" => opts.messages = true,
+            "--summary" => opts.summary = true,
+            _ if file.is_none() && !arg.starts_with('-') => file = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    opts.file = file.ok_or("Missing capture file")?;
+    Ok(opts)
+}
+
+/// parses the arguments of the `pcap` subcommand
+fn parse_pcap_options(args: impl IntoIterator<Item = String>) -> Result<PcapOptions, String> {
+    let mut opts = PcapOptions::default();
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--encap" => opts.encapsulation = value(&arg, &mut args)?,
+            _ if files.len() < 2 && !arg.starts_with('-') => files.push(PathBuf::from(&arg)),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    let [input, output]: [PathBuf; 2] = files
+        .try_into()
+        .map_err(|_| "Usage: pcap <capture> <output.pcapng> [--encap user|udp]")?;
+    opts.input = input;
+    opts.output = output;
+    Ok(opts)
+}
+
+/// parses the arguments of the `replay` subcommand
+fn parse_replay_options(args: impl IntoIterator<Item = String>) -> Result<ReplayOptions, String> {
+    let mut file: Option<PathBuf> = None;
+    let mut wait: Duration = DEFAULT_REPLAY_WAIT;
+    let mut event_log: Option<PathBuf> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wait" => wait = seconds(&arg, &mut args)?,
+            "--record-events" => event_log = Some(value(&arg, &mut args)?),
+            _ if file.is_none() && !arg.starts_with('-') => file = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    Ok(ReplayOptions {
+        file: file.ok_or("Missing recording")?,
+        wait,
+        event_log,
+    })
+}
+
+/// index in the drone factory of the implementation called `name`
+/// (or with that index)
+pub(super) fn implementation_index(name: &str) -> Result<usize, String> {
+    let factory = drone_factory();
+    factory
+        .iter()
+        .position(|(n, _)| *n == name)
+        .or_else(|| name.parse().ok().filter(|&i: &usize| i < factory.len()))
+        .ok_or_else(|| {
+            let names: Vec<&str> = factory.iter().map(|(n, _)| *n).collect();
+            format!(
+                "Unknown implementation: {name} (expected one of {})",
+                names.join(", ")
+            )
+        })
+}
+
+/// parses the arguments of the `matrix` subcommand
+fn parse_matrix_options(args: impl IntoIterator<Item = String>) -> Result<MatrixOptions, String> {
+    let mut opts = MatrixOptions::default();
+    let (mut topologies, mut pdrs, mut mixed) = (Vec::new(), Vec::new(), Vec::new());
+    let mut no_mixed: bool = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--topology" => topologies.push(value(&arg, &mut args)?),
+            "--pdr" => pdrs.push(value(&arg, &mut args)?),
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                opts.implementations.push(implementation_index(&name)?);
+            }
+            "--mixed" => mixed.push(value(&arg, &mut args)?),
+            "--no-mixed" => no_mixed = true,
+            "--timeout" => opts.timeout = seconds(&arg, &mut args)?,
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    if !topologies.is_empty() {
+        opts.topologies = topologies;
+    }
+    if !pdrs.is_empty() {
+        opts.pdrs = pdrs;
+    }
+    if no_mixed {
+        opts.mixed.clear();
+    } else if !mixed.is_empty() {
+        opts.mixed = mixed;
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the hidden `scenario` subcommand
+fn parse_scenario_options(
+    args: impl IntoIterator<Item = String>,
+) -> Result<ScenarioOptions, String> {
+    let mut implementations: Option<Implementations> = None;
+    let mut topology: Option<PathBuf> = None;
+    let mut pdr: f32 = 0.;
+    let mut content: Content = Content::Text;
+    let mut timeout: Duration = MatrixOptions::default().timeout;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                implementations = Some(Implementations::Single(implementation_index(&name)?));
+            }
+            "--mixed" => implementations = Some(Implementations::Mixed(value(&arg, &mut args)?)),
+            "--topology" => topology = Some(value(&arg, &mut args)?),
+            "--pdr" => pdr = value(&arg, &mut args)?,
+            "--content" => content = value(&arg, &mut args)?,
+            "--timeout" => timeout = seconds(&arg, &mut args)?,
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    let (Some(implementations), Some(topology)) = (implementations, topology) else {
+        return Err("Missing implementation or topology".to_owned());
+    };
+    Ok(ScenarioOptions {
+        scenario: Scenario {
+            implementations,
+            topology,
+            pdr,
+            content,
+        },
+        timeout,
+    })
+}
+
+/// parses the arguments of the `conformance` subcommand
+fn parse_conformance_options(
+    args: impl IntoIterator<Item = String>,
+) -> Result<ConformanceOptions, String> {
+    let mut opts = ConformanceOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                opts.implementations.push(implementation_index(&name)?);
+            }
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the `bench` subcommand
+fn parse_bench_options(args: impl IntoIterator<Item = String>) -> Result<BenchOptions, String> {
+    let mut opts = BenchOptions::default();
+    let mut pdrs: Vec<f32> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--topology" => opts.topology = value(&arg, &mut args)?,
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                opts.implementations.push(implementation_index(&name)?);
+            }
+            "--pdr" => pdrs.push(value(&arg, &mut args)?),
+            "--requests" => opts.requests = value(&arg, &mut args)?,
+            "--timeout" => opts.timeout = seconds(&arg, &mut args)?,
+            "--output" => opts.output = Some(value(&arg, &mut args)?),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    if !pdrs.is_empty() {
+        opts.pdrs = pdrs;
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the hidden `bench-case` subcommand
+fn parse_bench_case_options(
+    args: impl IntoIterator<Item = String>,
+) -> Result<BenchCaseOptions, String> {
+    let mut bench = BenchOptions::default();
+    let mut implementation: Option<usize> = None;
+    let mut pdr: f32 = 0.;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--topology" => bench.topology = value(&arg, &mut args)?,
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                implementation = Some(implementation_index(&name)?);
+            }
+            "--pdr" => pdr = value(&arg, &mut args)?,
+            "--requests" => bench.requests = value(&arg, &mut args)?,
+            "--timeout" => bench.timeout = seconds(&arg, &mut args)?,
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    let Some(implementation) = implementation else {
+        return Err("Missing implementation".to_owned());
+    };
+    Ok(BenchCaseOptions {
+        bench,
+        implementation,
+        pdr,
+    })
+}
+
+/// parses the arguments of the `diff` subcommand
+fn parse_diff_options(args: impl IntoIterator<Item = String>) -> Result<DiffOptions, String> {
+    let mut opts = DiffOptions::default();
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => opts.json = true,
+            "--assign" => opts.assignment = value(&arg, &mut args)?,
+            "--seed" => opts.seed = value(&arg, &mut args)?,
+            _ if files.len() < 2 && !arg.starts_with('-') => files.push(PathBuf::from(&arg)),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    let [old, new]: [PathBuf; 2] = files
+        .try_into()
+        .map_err(|_| "Usage: diff <old.toml> <new.toml> [--json] [--assign id|random --seed n]")?;
+    opts.old = old;
+    opts.new = new;
+    Ok(opts)
+}
+
+/// parses the arguments of the `fmt` subcommand
+fn parse_fmt_options(args: impl IntoIterator<Item = String>) -> Result<FmtOptions, String> {
+    let mut opts = FmtOptions::default();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => opts.check = true,
+            _ if !arg.starts_with('-') => opts.files.push(PathBuf::from(&arg)),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    if opts.files.is_empty() {
+        return Err("Usage: fmt [--check] <config.toml>...".to_owned());
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the `schema` subcommand
+fn parse_schema_options(args: impl IntoIterator<Item = String>) -> Result<SchemaOptions, String> {
+    let mut opts = SchemaOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => opts.output = Some(value(&arg, &mut args)?),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    Ok(opts)
+}
+
+/// parses the arguments of the hidden `node` subcommand
+fn parse_node_options(args: impl IntoIterator<Item = String>) -> Result<NodeOptions, String> {
+    let mut opts = NodeOptions::default();
+    let mut socket: Option<PathBuf> = None;
+    let mut id: Option<NodeId> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => socket = Some(value(&arg, &mut args)?),
+            "--id" => id = Some(value(&arg, &mut args)?),
+            "--implementation" => {
+                let name: String = value(&arg, &mut args)?;
+                opts.implementation = implementation_index(&name)?;
+            }
+            "--pdr" => opts.pdr = value(&arg, &mut args)?,
+            "--neighbours" => {
+                let list: String = value(&arg, &mut args)?;
+                opts.neighbours = list
+                    .split(',')
+                    .filter(|n| !n.is_empty())
+                    .map(|n| n.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Invalid value for {arg}: {list}"))?;
+            }
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+    opts.socket = socket.ok_or("Missing --socket")?;
+    opts.id = id.ok_or("Missing --id")?;
+    Ok(opts)
+}
+
+/// takes the logging flags, valid with every subcommand, out of `args`
+pub(super) fn split_logging_options(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(LoggingOptions, Vec<String>), String> {
+    let mut opts = LoggingOptions::default();
+    let mut rest: Vec<String> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => opts.spec = Some(value(&arg, &mut args)?),
+            "--log-format" => opts.format = value(&arg, &mut args)?,
+            "--log-node" => {
+                let node: String = value(&arg, &mut args)?;
+                let (id, level) = node
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid value for {arg}: {node}"))?;
+                let id: NodeId = id
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {arg}: {node}"))?;
+                let level: LevelFilter = level
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {arg}: {node}"))?;
+                opts.nodes.insert(id, level);
+            }
+            "--log-dir" => opts.dir = Some(value(&arg, &mut args)?),
+            "--log-per-node" => opts.per_node = true,
+            "--log-rotate" => {
+                let mib: u64 = value(&arg, &mut args)?;
+                let bytes: u64 = mib
+                    .checked_mul(1024 * 1024)
+                    .ok_or_else(|| format!("Invalid value for {arg}: {mib}"))?;
+                opts.rotate = Some(bytes);
+            }
+            "--log-keep" => opts.keep = value(&arg, &mut args)?,
+            _ => rest.push(arg),
+        }
+    }
+    if opts.per_node && opts.dir.is_none() {
+        return Err("--log-per-node needs --log-dir".to_owned());
+    }
+    Ok((opts, rest))
+}
+
+/// parses the whole command line (program name excluded)
+pub(super) fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let subcommand: Option<String> = args.peek().cloned();
+    match subcommand.as_deref() {
+        Some("inspect") => parse_inspect_options(args.skip(1)).map(Command::Inspect),
+        Some("pcap") => parse_pcap_options(args.skip(1)).map(Command::Pcap),
+        Some("replay") => parse_replay_options(args.skip(1)).map(Command::Replay),
+        Some("matrix") => parse_matrix_options(args.skip(1)).map(Command::Matrix),
+        Some("conformance") => parse_conformance_options(args.skip(1)).map(Command::Conformance),
+        Some("bench") => parse_bench_options(args.skip(1)).map(Command::Bench),
+        Some("diff") => parse_diff_options(args.skip(1)).map(Command::Diff),
+        Some("fmt") => parse_fmt_options(args.skip(1)).map(Command::Fmt),
+        Some("schema") => parse_schema_options(args.skip(1)).map(Command::Schema),
+        Some("node") => parse_node_options(args.skip(1)).map(Command::Node),
+        Some("scenario") => parse_scenario_options(args.skip(1)).map(Command::Scenario),
+        Some("bench-case") => parse_bench_case_options(args.skip(1)).map(Command::BenchCase),
+        Some("run") => parse_run_options(args.skip(1)).map(Command::Run),
+        _ => parse_run_options(args).map(Command::Run),
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    collections::HashMap,
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use wg_2024::{
+    controller::{DroneCommand, DroneEvent},
+    network::{NodeId, SourceRoutingHeader},
+    packet::{
+        Ack, FloodRequest, Fragment, Nack, NackType, NodeType, Packet, PacketType, FRAGMENT_DSIZE,
+    },
+};
+
+use crate::{
+    factories::DroneFn,
+    network::drone_factory,
+    recorder::{packet_summary, EventSummary},
+};
+
+/// id of the drone under test
+const DRONE: NodeId = 1;
+/// neighbour the crafted packets come from
+const UPSTREAM: NodeId = 0;
+/// neighbour the crafted packets are routed to
+const DOWNSTREAM: NodeId = 2;
+/// node connected to the drone only through `AddSender`
+const LATE: NodeId = 3;
+/// node that isn't connected to the drone at all
+const STRANGER: NodeId = 7;
+const SESSION: u64 = 42;
+const FLOOD_ID: u64 = 5;
+/// how long to wait for something that should happen
+const WAIT: Duration = Duration::from_millis(500);
+/// how long to wait to be sure that something doesn't happen,
+/// also given to the drone to apply a command
+const QUIET: Duration = Duration::from_millis(100);
+
+/// outcome of a single check, with what went wrong
+pub(super) type CheckResult = Result<(), String>;
+
+/// every check of the suite, each one runs on a fresh drone
+pub(super) const CHECKS: [(&str, fn(DroneFn) -> CheckResult); 14] = [
+    ("forward_fragment", forward_fragment),
+    ("forward_ack", forward_ack),
+    ("unexpected_recipient", unexpected_recipient),
+    ("destination_is_drone", destination_is_drone),
+    ("error_in_routing", error_in_routing),
+    ("nack_wrong_hop_index", nack_wrong_hop_index),
+    ("pdr_drops_fragments", pdr_drops_fragments),
+    ("pdr_spares_acks", pdr_spares_acks),
+    ("set_packet_drop_rate", set_packet_drop_rate),
+    ("flood_forward", flood_forward),
+    ("flood_repeated_id", flood_repeated_id),
+    ("add_sender", add_sender),
+    ("remove_sender", remove_sender),
+    ("crash_drains_queue", crash_drains_queue),
+];
+
+/// a drone running on its own thread, connected to `UPSTREAM`
+/// and `DOWNSTREAM`; it's crashed once dropped
+struct Harness {
+    commands: Sender<DroneCommand>,
+    events: Receiver<DroneEvent>,
+    packets: Option<Sender<Packet>>,
+    neighbours: HashMap<NodeId, Receiver<Packet>>,
+    done: Receiver<()>,
+}
+
+impl Harness {
+    fn start(factory: DroneFn, pdr: f32) -> Self {
+        let (commands, command_recv) = unbounded::<DroneCommand>();
+        let (event_send, events) = unbounded::<DroneEvent>();
+        let (packets, packet_recv) = unbounded::<Packet>();
+        let mut senders: HashMap<NodeId, Sender<Packet>> = HashMap::new();
+        let mut neighbours: HashMap<NodeId, Receiver<Packet>> = HashMap::new();
+        for id in [UPSTREAM, DOWNSTREAM] {
+            let (s, r) = unbounded::<Packet>();
+            senders.insert(id, s);
+            neighbours.insert(id, r);
+        }
+        let (done_send, done) = bounded::<()>(1);
+        thread::spawn(move || {
+            factory(DRONE, event_send, command_recv, packet_recv, senders, pdr).run();
+            let _ = done_send.send(());
+        });
+        Self {
+            commands,
+            events,
+            packets: Some(packets),
+            neighbours,
+            done,
+        }
+    }
+
+    fn send(&self, packet: Packet) {
+        if let Some(p) = &self.packets {
+            let _ = p.send(packet);
+        }
+    }
+
+    /// sends `command` and gives the drone some time to apply it
+    fn command(&self, command: DroneCommand) {
+        let _ = self.commands.send(command);
+        sleep(QUIET);
+    }
+
+    /// connects `LATE` to the drone
+    fn connect_late(&mut self) {
+        let (s, r) = unbounded::<Packet>();
+        self.neighbours.insert(LATE, r);
+        self.command(DroneCommand::AddSender(LATE, s));
+    }
+
+    fn expect(&self, node: NodeId) -> Result<Packet, String> {
+        self.neighbours[&node]
+            .recv_timeout(WAIT)
+            .map_err(|_| format!("nothing reached {node}"))
+    }
+
+    fn expect_nothing(&self, node: NodeId) -> CheckResult {
+        match self.neighbours[&node].recv_timeout(QUIET) {
+            Ok(p) => Err(format!("{node} got {}", packet_summary(&p))),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// waits for a nack of type `expected` at `node`
+    fn expect_nack(&self, node: NodeId, expected: &NackType) -> CheckResult {
+        let p: Packet = self.expect(node)?;
+        match &p.pack_type {
+            PacketType::Nack(n) if n.nack_type == *expected => {
+                if p.session_id == SESSION {
+                    Ok(())
+                } else {
+                    Err(format!("nack has session {}", p.session_id))
+                }
+            }
+            _ => Err(format!(
+                "expected a {expected:?} nack, got {}",
+                packet_summary(&p)
+            )),
+        }
+    }
+
+    /// waits for an event called `variant`, skipping the others
+    fn expect_event(&self, variant: &str) -> Result<DroneEvent, String> {
+        let deadline: Instant = Instant::now() + WAIT;
+        while let Ok(e) = self.events.recv_deadline(deadline) {
+            if e.variant() == variant {
+                return Ok(e);
+            }
+        }
+        Err(format!("no {variant} event"))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = self.commands.send(DroneCommand::Crash);
+        self.packets = None;
+    }
+}
+
+fn packet(hops: Vec<NodeId>, pack_type: PacketType) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id: SESSION,
+        pack_type,
+    }
+}
+
+fn fragment(hops: Vec<NodeId>) -> Packet {
+    let mut data = [0; FRAGMENT_DSIZE];
+    data[..5].copy_from_slice(b"hello");
+    packet(
+        hops,
+        PacketType::MsgFragment(Fragment {
+            fragment_index: 0,
+            total_n_fragments: 1,
+            length: 5,
+            data,
+        }),
+    )
+}
+
+fn ack(hops: Vec<NodeId>) -> Packet {
+    packet(hops, PacketType::Ack(Ack { fragment_index: 0 }))
+}
+
+fn flood_request() -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 0,
+            hops: Vec::new(),
+        },
+        session_id: SESSION,
+        pack_type: PacketType::FloodRequest(FloodRequest {
+            flood_id: FLOOD_ID,
+            initiator_id: UPSTREAM,
+            path_trace: vec![(UPSTREAM, NodeType::Client)],
+        }),
+    }
+}
+
+/// last node of the path trace of a flood packet
+fn traced_last(p: &Packet) -> Option<NodeId> {
+    match &p.pack_type {
+        PacketType::FloodRequest(f) => f.path_trace.last().map(|(id, _)| *id),
+        PacketType::FloodResponse(f) => f.path_trace.last().map(|(id, _)| *id),
+        _ => None,
+    }
+}
+
+fn forward_fragment(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(fragment(vec![UPSTREAM, DRONE, DOWNSTREAM]));
+    let p: Packet = h.expect(DOWNSTREAM)?;
+    if p.routing_header.hop_index != 2 {
+        return Err(format!("hop index is {}", p.routing_header.hop_index));
+    }
+    h.expect_event("PacketSent").map(|_| ())
+}
+
+fn forward_ack(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(ack(vec![DOWNSTREAM, DRONE, UPSTREAM]));
+    h.expect(UPSTREAM).map(|_| ())
+}
+
+fn unexpected_recipient(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(fragment(vec![UPSTREAM, STRANGER, DOWNSTREAM]));
+    h.expect_nack(UPSTREAM, &NackType::UnexpectedRecipient(DRONE))?;
+    h.expect_nothing(DOWNSTREAM)
+}
+
+fn destination_is_drone(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(fragment(vec![UPSTREAM, DRONE]));
+    h.expect_nack(UPSTREAM, &NackType::DestinationIsDrone)
+}
+
+fn error_in_routing(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(fragment(vec![UPSTREAM, DRONE, STRANGER]));
+    h.expect_nack(UPSTREAM, &NackType::ErrorInRouting(STRANGER))
+}
+
+/// a nack can't be answered with another nack, it goes to the controller
+fn nack_wrong_hop_index(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(packet(
+        vec![DOWNSTREAM, STRANGER, UPSTREAM],
+        PacketType::Nack(Nack {
+            fragment_index: 0,
+            nack_type: NackType::Dropped,
+        }),
+    ));
+    h.expect_event("ControllerShortcut")?;
+    h.expect_nothing(UPSTREAM)?;
+    h.expect_nothing(DOWNSTREAM)
+}
+
+fn pdr_drops_fragments(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 1.);
+    h.send(fragment(vec![UPSTREAM, DRONE, DOWNSTREAM]));
+    h.expect_nack(UPSTREAM, &NackType::Dropped)?;
+    h.expect_nothing(DOWNSTREAM)?;
+    h.expect_event("PacketDropped").map(|_| ())
+}
+
+fn pdr_spares_acks(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 1.);
+    h.send(ack(vec![DOWNSTREAM, DRONE, UPSTREAM]));
+    h.expect(UPSTREAM).map(|_| ())
+}
+
+fn set_packet_drop_rate(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.command(DroneCommand::SetPacketDropRate(1.));
+    h.send(fragment(vec![UPSTREAM, DRONE, DOWNSTREAM]));
+    h.expect_nack(UPSTREAM, &NackType::Dropped)?;
+    h.command(DroneCommand::SetPacketDropRate(0.));
+    h.send(fragment(vec![UPSTREAM, DRONE, DOWNSTREAM]));
+    h.expect(DOWNSTREAM).map(|_| ())
+}
+
+fn flood_forward(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(flood_request());
+    let p: Packet = h.expect(DOWNSTREAM)?;
+    if traced_last(&p) != Some(DRONE) {
+        return Err(format!("drone missing from path trace: {p:?}"));
+    }
+    // never back to the sender
+    h.expect_nothing(UPSTREAM)
+}
+
+/// an already seen flood is answered, not forwarded again
+fn flood_repeated_id(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.send(flood_request());
+    h.expect(DOWNSTREAM)?;
+    h.send(flood_request());
+    let p: Packet = h.expect(UPSTREAM)?;
+    let PacketType::FloodResponse(r) = &p.pack_type else {
+        return Err(format!(
+            "expected a flood response, got {}",
+            packet_summary(&p)
+        ));
+    };
+    if r.flood_id != FLOOD_ID || traced_last(&p) != Some(DRONE) {
+        return Err(format!("wrong flood response: {r:?}"));
+    }
+    h.expect_nothing(DOWNSTREAM)
+}
+
+fn add_sender(factory: DroneFn) -> CheckResult {
+    let mut h = Harness::start(factory, 0.);
+    h.connect_late();
+    h.send(fragment(vec![UPSTREAM, DRONE, LATE]));
+    h.expect(LATE).map(|_| ())
+}
+
+fn remove_sender(factory: DroneFn) -> CheckResult {
+    let h = Harness::start(factory, 0.);
+    h.command(DroneCommand::RemoveSender(DOWNSTREAM));
+    h.send(fragment(vec![UPSTREAM, DRONE, DOWNSTREAM]));
+    h.expect_nack(UPSTREAM, &NackType::ErrorInRouting(DOWNSTREAM))?;
+    h.expect_nothing(DOWNSTREAM)
+}
+
+/// packets still queued when the drone crashes are handled, then the
+/// drone stops once its packet channel is closed
+fn crash_drains_queue(factory: DroneFn) -> CheckResult {
+    let mut h = Harness::start(factory, 0.);
+    for _ in 0..3 {
+        h.send(ack(vec![DOWNSTREAM, DRONE, UPSTREAM]));
+    }
+    let _ = h.commands.send(DroneCommand::Crash);
+    h.packets = None;
+    for _ in 0..3 {
+        h.expect(UPSTREAM)?;
+    }
+    h.done
+        .recv_timeout(WAIT)
+        .map_err(|_| "still running after the crash".to_owned())
+}
+
+/// runs every check against `factory`
+pub(super) fn run_checks(factory: DroneFn) -> Vec<(&'static str, CheckResult)> {
+    CHECKS
+        .iter()
+        .map(|(name, check)| (*name, check(factory)))
+        .collect()
+}
+
+/// options of the `conformance` subcommand
+#[derive(Debug, Default)]
+pub(super) struct ConformanceOptions {
+    /// indexes in the drone factory, every implementation if empty
+    pub(super) implementations: Vec<usize>,
+}
+
+/// runs the suite against the requested implementations, printing the
+/// failures of each one; returns how many checks passed
+pub(super) fn run_conformance(opts: &ConformanceOptions) -> (usize, usize) {
+    let factory = drone_factory();
+    let implementations: Vec<usize> = if opts.implementations.is_empty() {
+        (0..factory.len()).collect()
+    } else {
+        opts.implementations.clone()
+    };
+    let (mut passed, mut total): (usize, usize) = (0, 0);
+    for i in implementations {
+        let (name, f) = factory[i];
+        let results = run_checks(f);
+        let ok: usize = results.iter().filter(|(_, r)| r.is_ok()).count();
+        println!("{name:<16} {ok:>2}/{}", results.len());
+        for (check, result) in &results {
+            if let Err(e) = result {
+                println!("    {check:<22} {e}");
+            }
+        }
+        passed += ok;
+        total += results.len();
+    }
+    println!("{passed}/{total} checks passed");
+    (passed, total)
+}
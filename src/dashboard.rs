@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::{debug, info, warn};
+use serde::Serialize;
+use tungstenite::Message;
+use wg_2024::{config::Config, network::NodeId};
+
+use crate::{
+    metrics::{Metrics, NodeMetrics},
+    network::{drone_factory, server_implementation, Network, NodeKind},
+    recorder::{EventRecord, EventRecorder},
+    tap::{TapRecord, TapSink},
+};
+
+/// page served on `/`
+const PAGE: &str = include_str!("dashboard.html");
+
+/// updates queued for a browser before the newest ones are dropped
+const BROWSER_QUEUE: usize = 4096;
+
+/// time between two metrics updates
+pub(super) const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// time a browser gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a node as drawn by the dashboard
+#[derive(Debug, Serialize)]
+pub(super) struct TopologyNode {
+    pub(super) id: NodeId,
+    pub(super) kind: &'static str,
+    pub(super) implementation: Option<&'static str>,
+    /// drones only
+    pub(super) pdr: Option<f32>,
+}
+
+/// the network as loaded from the config file
+#[derive(Debug, Serialize)]
+pub(super) struct Topology {
+    pub(super) nodes: Vec<TopologyNode>,
+    /// every link once, lowest id first
+    pub(super) links: Vec<(NodeId, NodeId)>,
+}
+
+impl Topology {
+    /// `network` tells the web clients from the chat clients,
+    /// `implementations` the drone implementations
+    pub(super) fn new(
+        config: &Config,
+        network: &Network,
+        implementations: &HashMap<NodeId, usize>,
+    ) -> Self {
+        let drone_factory = drone_factory();
+        let mut nodes: Vec<TopologyNode> = Vec::new();
+        let mut links: Vec<(NodeId, NodeId)> = Vec::new();
+        let mut link = |a: NodeId, b: NodeId| {
+            let l: (NodeId, NodeId) = (a.min(b), a.max(b));
+            if !links.contains(&l) {
+                links.push(l);
+            }
+        };
+        for d in &config.drone {
+            nodes.push(TopologyNode {
+                id: d.id,
+                kind: NodeKind::Drone.as_str(),
+                implementation: implementations
+                    .get(&d.id)
+                    .map(|i| drone_factory[i % drone_factory.len()].0),
+                pdr: Some(d.pdr),
+            });
+            for n in &d.connected_node_ids {
+                link(d.id, *n);
+            }
+        }
+        for c in &config.client {
+            let kind: NodeKind = if network.chat_clients.contains_key(&c.id) {
+                NodeKind::ChatClient
+            } else {
+                NodeKind::WebClient
+            };
+            nodes.push(TopologyNode {
+                id: c.id,
+                kind: kind.as_str(),
+                implementation: None,
+                pdr: None,
+            });
+            for n in &c.connected_drone_ids {
+                link(c.id, *n);
+            }
+        }
+        for s in &config.server {
+            nodes.push(TopologyNode {
+                id: s.id,
+                kind: NodeKind::Server.as_str(),
+                implementation: Some(server_implementation(s.id)),
+                pdr: None,
+            });
+            for n in &s.connected_drone_ids {
+                link(s.id, *n);
+            }
+        }
+        links.sort_unstable();
+        Self { nodes, links }
+    }
+}
+
+/// counters of a node in a metrics update
+#[derive(Debug, Serialize)]
+pub(super) struct NodeUpdate {
+    pub(super) id: NodeId,
+    pub(super) kind: &'static str,
+    #[serde(flatten)]
+    pub(super) metrics: NodeMetrics,
+}
+
+/// message sent to the browsers, one per WebSocket frame
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum Update<'a> {
+    Topology(&'a Topology),
+    Event(&'a EventRecord),
+    Packet {
+        /// microseconds since the UNIX epoch
+        timestamp: u64,
+        from: NodeId,
+        to: NodeId,
+        packet_type: &'static str,
+        session: u64,
+        fragment: Option<u64>,
+    },
+    Metrics {
+        nodes: Vec<NodeUpdate>,
+    },
+}
+
+/// state shared by the dashboard server and the threads observing the
+/// network
+#[derive(Default)]
+pub(super) struct Dashboard {
+    /// set once the network is spawned, the packet tap needs the
+    /// dashboard before
+    topology: OnceLock<Topology>,
+    /// one queue per connected browser
+    browsers: Mutex<Vec<Sender<String>>>,
+}
+
+impl Dashboard {
+    /// sets the topology sent to the browsers, only the first call counts
+    pub(super) fn set_topology(&self, topology: Topology) {
+        let _ = self.topology.set(topology);
+    }
+
+    /// sends `update` to every connected browser, forgetting the ones
+    /// that are gone; browsers lagging behind miss it
+    pub(super) fn broadcast(&self, update: &Update) {
+        let mut browsers = self.browsers.lock().unwrap_or_else(PoisonError::into_inner);
+        if browsers.is_empty() {
+            return;
+        }
+        let text: String = match serde_json::to_string(update) {
+            Ok(t) => t,
+            Err(e) => {
+                warn!("Unable to encode dashboard update: {e}");
+                return;
+            }
+        };
+        browsers
+            .retain(|b| !matches!(b.try_send(text.clone()), Err(TrySendError::Disconnected(_))));
+    }
+
+    /// registers a browser, which gets the topology first
+    fn subscribe(&self) -> Receiver<String> {
+        let (browser, updates) = crossbeam_channel::bounded(BROWSER_QUEUE);
+        let topology: Option<String> = self
+            .topology
+            .get()
+            .and_then(|t| serde_json::to_string(&Update::Topology(t)).ok());
+        if let Some(topology) = topology {
+            let _ = browser.send(topology);
+        }
+        self.browsers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(browser);
+        updates
+    }
+
+    /// an event recorder broadcasting the events of the nodes it wraps
+    pub(super) fn recorder(self: &Arc<Self>) -> EventRecorder {
+        let (sink, records) = crossbeam_channel::unbounded::<EventRecord>();
+        let dashboard: Arc<Dashboard> = Arc::clone(self);
+        thread::spawn(move || {
+            for r in records {
+                dashboard.broadcast(&Update::Event(&r));
+            }
+        });
+        EventRecorder::with_sink(sink)
+    }
+
+    /// broadcasts the counters of every node each `interval`
+    pub(super) fn stream_metrics(self: &Arc<Self>, metrics: Arc<Metrics>, interval: Duration) {
+        let dashboard: Arc<Dashboard> = Arc::clone(self);
+        thread::spawn(move || loop {
+            sleep(interval);
+            let nodes: Vec<NodeUpdate> = metrics
+                .snapshot()
+                .into_iter()
+                .map(|(id, (kind, metrics))| NodeUpdate {
+                    id,
+                    kind: kind.as_str(),
+                    metrics,
+                })
+                .collect();
+            dashboard.broadcast(&Update::Metrics { nodes });
+        });
+    }
+}
+
+/// tap sink broadcasting every packet crossing the network
+pub(super) struct DashboardSink(pub(super) Arc<Dashboard>);
+
+impl TapSink for DashboardSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        self.0.broadcast(&Update::Packet {
+            timestamp: record.micros(),
+            from: record.from,
+            to: record.to,
+            packet_type: record.packet_type(),
+            session: record.session_id(),
+            fragment: record.fragment_index(),
+        });
+        Ok(())
+    }
+}
+
+/// forwards the updates to a browser until either side is gone
+fn forward_updates(
+    stream: TcpStream,
+    updates: &Receiver<String>,
+) -> Result<(), tungstenite::Error> {
+    let mut socket = tungstenite::accept(stream).map_err(|e| match e {
+        tungstenite::HandshakeError::Failure(e) => e,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+    })?;
+    for text in updates {
+        socket.send(Message::text(text))?;
+    }
+    socket.close(None)
+}
+
+fn respond(mut stream: TcpStream, dashboard: &Dashboard) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    // peek, the WebSocket handshake needs to read the request again
+    let mut head = [0; 1024];
+    let len: usize = stream.peek(&mut head)?;
+    let head: String = String::from_utf8_lossy(&head[..len]).to_lowercase();
+    if head.starts_with("get /ws ") && head.contains("upgrade: websocket") {
+        let updates: Receiver<String> = dashboard.subscribe();
+        if let Err(e) = forward_updates(stream, &updates) {
+            debug!("Dashboard browser gone: {e}");
+        }
+        return Ok(());
+    }
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+        Some("/") => ("200 OK", "text/html; charset=utf-8", PAGE.to_owned()),
+        Some("/topology") => match dashboard.topology.get() {
+            Some(t) => ("200 OK", "application/json", serde_json::to_string(t)?),
+            None => (
+                "503 Service Unavailable",
+                "text/plain",
+                "network not spawned yet\n".to_owned(),
+            ),
+        },
+        _ => ("404 Not Found", "text/plain", "not found\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// serves the dashboard on 127.0.0.1:`port`, returns the port actually
+/// bound
+pub(super) fn serve(dashboard: Arc<Dashboard>, port: u16) -> io::Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let port: u16 = listener.local_addr()?.port();
+    info!("Dashboard available on http://127.0.0.1:{port}/");
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // one thread per browser, an idle one can't hold up the others
+            let dashboard: Arc<Dashboard> = Arc::clone(&dashboard);
+            thread::spawn(move || {
+                if let Err(e) = respond(stream, &dashboard) {
+                    warn!("Dashboard request failed: {e}");
+                }
+            });
+        }
+    });
+    Ok(port)
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use wg_2024::{config::Config, network::NodeId};
+
+use crate::{
+    network::{client_kind, drone_factory, server_implementation, NodeKind},
+    random::{assign_implementations, rng_for, Assignment},
+};
+
+/// options of the `diff` subcommand
+#[derive(Debug, Default)]
+pub(super) struct DiffOptions {
+    pub(super) old: PathBuf,
+    pub(super) new: PathBuf,
+    /// print a JSON object instead of one line per change
+    pub(super) json: bool,
+    /// how the drone implementations are picked on both sides
+    pub(super) assignment: Assignment,
+    pub(super) seed: u64,
+}
+
+/// what a node runs as
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub(super) struct NodeSummary {
+    pub(super) id: NodeId,
+    pub(super) kind: NodeKind,
+    pub(super) implementation: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) pdr: Option<f32>,
+}
+
+/// a node present on both sides whose kind or implementation changed
+#[derive(Debug, PartialEq, Serialize)]
+pub(super) struct NodeChange {
+    pub(super) old: NodeSummary,
+    pub(super) new: NodeSummary,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(super) struct PdrChange {
+    pub(super) id: NodeId,
+    pub(super) old: f32,
+    pub(super) new: f32,
+}
+
+/// differences between two topologies, links as (lower id, higher id)
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(super) struct TopologyDiff {
+    pub(super) added_nodes: Vec<NodeSummary>,
+    pub(super) removed_nodes: Vec<NodeSummary>,
+    pub(super) changed_nodes: Vec<NodeChange>,
+    pub(super) added_links: Vec<(NodeId, NodeId)>,
+    pub(super) removed_links: Vec<(NodeId, NodeId)>,
+    pub(super) pdr_changes: Vec<PdrChange>,
+}
+
+impl TopologyDiff {
+    pub(super) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// one line per change: `+` added, `-` removed, `~` changed
+    pub(super) fn human(&self) -> String {
+        fn node(n: &NodeSummary) -> String {
+            format!("{} ({})", n.kind.as_str(), n.implementation)
+        }
+
+        let mut out = String::new();
+        for n in &self.added_nodes {
+            let _ = writeln!(out, "+ node {} {}", n.id, node(n));
+        }
+        for n in &self.removed_nodes {
+            let _ = writeln!(out, "- node {} {}", n.id, node(n));
+        }
+        for c in &self.changed_nodes {
+            let _ = writeln!(
+                out,
+                "~ node {}: {} -> {}",
+                c.old.id,
+                node(&c.old),
+                node(&c.new)
+            );
+        }
+        for (a, b) in &self.added_links {
+            let _ = writeln!(out, "+ link {a}-{b}");
+        }
+        for (a, b) in &self.removed_links {
+            let _ = writeln!(out, "- link {a}-{b}");
+        }
+        for p in &self.pdr_changes {
+            let _ = writeln!(out, "~ pdr {}: {} -> {}", p.id, p.old, p.new);
+        }
+        if out.is_empty() {
+            out.push_str("no differences\n");
+        }
+        out
+    }
+}
+
+/// every node of `config` with what it runs as, `implementations` giving
+/// the index in the drone factory of every drone
+fn nodes(
+    config: &Config,
+    implementations: &HashMap<NodeId, usize>,
+) -> BTreeMap<NodeId, NodeSummary> {
+    let drone_factory = drone_factory();
+    let drones = config.drone.iter().map(|d| NodeSummary {
+        id: d.id,
+        kind: NodeKind::Drone,
+        implementation: drone_factory[implementations[&d.id] % drone_factory.len()].0,
+        pdr: Some(d.pdr),
+    });
+    let clients = config.client.iter().map(|c| {
+        let kind: NodeKind = client_kind(c.id);
+        NodeSummary {
+            id: c.id,
+            kind,
+            implementation: match kind {
+                NodeKind::ChatClient => "chat_client",
+                _ => "web_browser",
+            },
+            pdr: None,
+        }
+    });
+    let servers = config.server.iter().map(|s| NodeSummary {
+        id: s.id,
+        kind: NodeKind::Server,
+        implementation: server_implementation(s.id),
+        pdr: None,
+    });
+    drones
+        .chain(clients)
+        .chain(servers)
+        .map(|n| (n.id, n))
+        .collect()
+}
+
+/// every link of `config` once, whichever side lists it
+fn links(config: &Config) -> BTreeSet<(NodeId, NodeId)> {
+    let drones = config.drone.iter().map(|d| (d.id, &d.connected_node_ids));
+    let clients = config.client.iter().map(|c| (c.id, &c.connected_drone_ids));
+    let servers = config.server.iter().map(|s| (s.id, &s.connected_drone_ids));
+    drones
+        .chain(clients)
+        .chain(servers)
+        .flat_map(|(id, nbrs)| nbrs.iter().map(move |n| (id.min(*n), id.max(*n))))
+        .collect()
+}
+
+/// what changed from `old` to `new`, the drones of both getting their
+/// implementation through `assignment`
+pub(super) fn diff_configs(
+    old: &Config,
+    new: &Config,
+    assignment: Assignment,
+    seed: u64,
+) -> TopologyDiff {
+    let implementations = |config: &Config| {
+        assign_implementations(
+            &config.drone,
+            drone_factory().len(),
+            assignment,
+            &mut rng_for(seed, "implementations"),
+        )
+    };
+    let old_nodes = nodes(old, &implementations(old));
+    let new_nodes = nodes(new, &implementations(new));
+    let old_links = links(old);
+    let new_links = links(new);
+
+    let mut diff = TopologyDiff {
+        added_links: new_links.difference(&old_links).copied().collect(),
+        removed_links: old_links.difference(&new_links).copied().collect(),
+        ..TopologyDiff::default()
+    };
+    for (id, n) in &new_nodes {
+        match old_nodes.get(id) {
+            None => diff.added_nodes.push(*n),
+            Some(o) => {
+                if (o.kind, o.implementation) != (n.kind, n.implementation) {
+                    diff.changed_nodes.push(NodeChange { old: *o, new: *n });
+                }
+                if let (Some(old), Some(new)) = (o.pdr, n.pdr) {
+                    if (old - new).abs() > f32::EPSILON {
+                        diff.pdr_changes.push(PdrChange { id: *id, old, new });
+                    }
+                }
+            }
+        }
+    }
+    diff.removed_nodes = old_nodes
+        .values()
+        .filter(|n| !new_nodes.contains_key(&n.id))
+        .copied()
+        .collect();
+    diff
+}
+
+fn read_config(path: &Path) -> Result<Config, String> {
+    let data: String =
+        fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    toml::from_str(&data).map_err(|e| format!("Unable to parse {}: {e}", path.display()))
+}
+
+/// prints the differences between the two configs of `opts`, returns
+/// whether there are any
+pub(super) fn run_diff(opts: &DiffOptions) -> Result<bool, String> {
+    let diff: TopologyDiff = diff_configs(
+        &read_config(&opts.old)?,
+        &read_config(&opts.new)?,
+        opts.assignment,
+        opts.seed,
+    );
+    if opts.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())?
+        );
+    } else {
+        print!("{}", diff.human());
+    }
+    Ok(!diff.is_empty())
+}
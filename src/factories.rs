@@ -4,7 +4,12 @@ use chat_server_client::client::ChatClient;
 use common::Client as ClientTrait;
 use crossbeam_channel::{Receiver, Sender};
 use web_client::web_client::WebBrowser;
-use wg_2024::{network::NodeId, packet::Packet};
+use wg_2024::{
+    controller::{DroneCommand, DroneEvent},
+    drone::Drone as DroneTrait,
+    network::NodeId,
+    packet::Packet,
+};
 
 /// handy alias for shorter naming
 #[allow(type_alias_bounds)]
@@ -16,6 +21,16 @@ type ClientFn<C: ClientTrait> = fn(
     HashMap<NodeId, Sender<Packet>>,
 ) -> C;
 
+/// signature of the closures built by [`create_boxed_drone`]
+pub(super) type DroneFn = fn(
+    NodeId,
+    Sender<DroneEvent>,
+    Receiver<DroneCommand>,
+    Receiver<Packet>,
+    HashMap<NodeId, Sender<Packet>>,
+    f32,
+) -> Box<dyn DroneTrait>;
+
 /// create a closure that returns a scpecific Drone as a
 /// boxed trait obj
 #[macro_export]
@@ -0,0 +1,216 @@
+use std::{fs, path::PathBuf};
+
+use itertools::Itertools;
+use wg_2024::config::Config;
+
+use crate::topology_utils::config_to_toml;
+
+/// options of the `fmt` subcommand
+#[derive(Debug, Default)]
+pub(super) struct FmtOptions {
+    pub(super) files: Vec<PathBuf>,
+    /// only report the files that aren't formatted
+    pub(super) check: bool,
+}
+
+/// keys of a node, in the order they're written
+const KEY_ORDER: [&str; 4] = ["id", "connected_node_ids", "connected_drone_ids", "pdr"];
+
+/// node tables, in the order they're written
+const TABLE_ORDER: [&str; 3] = ["[[drone]]", "[[client]]", "[[server]]"];
+
+/// `key = value` line, with the comments written above it
+#[derive(Debug)]
+struct Entry {
+    comments: Vec<String>,
+    key: String,
+    value: String,
+    trailing: Option<String>,
+}
+
+/// a `[[kind]]` table, with the comments written above it
+#[derive(Debug)]
+struct Table {
+    comments: Vec<String>,
+    header: String,
+    trailing: Option<String>,
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    fn rank(&self) -> usize {
+        TABLE_ORDER
+            .iter()
+            .position(|h| *h == self.header)
+            .unwrap_or(TABLE_ORDER.len())
+    }
+
+    fn id(&self) -> Option<i64> {
+        self.entries
+            .iter()
+            .find(|e| e.key == "id")
+            .and_then(|e| e.value.parse().ok())
+    }
+}
+
+/// splits the comment off `line`, `#` inside strings don't count
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut in_string: bool = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return (line[..i].trim(), Some(line[i..].trim())),
+            _ => {}
+        }
+    }
+    (line.trim(), None)
+}
+
+/// `[2, 1, 4]` as `[1, 2, 4]`; anything else but a list of integers is
+/// only trimmed
+fn canonical_value(value: &str) -> String {
+    let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) else {
+        return value.to_owned();
+    };
+    let items: Option<Vec<i64>> = items
+        .split(',')
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(|i| i.parse().ok())
+        .collect();
+    match items {
+        Some(items) => format!("[{}]", items.iter().sorted().join(", ")),
+        None => value.to_owned(),
+    }
+}
+
+fn push_line(out: &mut String, code: &str, comment: Option<&String>) {
+    out.push_str(code);
+    if let Some(c) = comment {
+        out.push(' ');
+        out.push_str(c);
+    }
+    out.push('\n');
+}
+
+/// rewrites a topology file canonically: nodes sorted by kind and id,
+/// neighbour lists sorted, one blank line between nodes; comments move
+/// along with the node or key below them
+pub(super) fn format_config(text: &str) -> Result<String, String> {
+    let before: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+
+    // comments at the top, separated from the first node by a blank line
+    let mut preamble: Vec<String> = Vec::new();
+    let mut tables: Vec<Table> = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let (code, comment) = split_comment(line);
+        if code.is_empty() {
+            match comment {
+                Some(c) => comments.push(c.to_owned()),
+                None if tables.is_empty() => preamble.append(&mut comments),
+                None => {}
+            }
+            continue;
+        }
+        if code.starts_with('[') && !code.contains('=') {
+            tables.push(Table {
+                comments: std::mem::take(&mut comments),
+                header: code.split_whitespace().collect(),
+                trailing: comment.map(str::to_owned),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = code.split_once('=') else {
+            return Err(format!("Unexpected line: {line}"));
+        };
+        let mut value: String = value.trim().to_owned();
+        // a list spanning several lines, its comments go above the key
+        while value.matches('[').count() > value.matches(']').count() {
+            let next: &str = lines.next().ok_or("Unterminated list")?;
+            let (code, comment) = split_comment(next);
+            value.push_str(code);
+            comments.extend(comment.map(str::to_owned));
+        }
+        let entry = Entry {
+            comments: std::mem::take(&mut comments),
+            key: key.trim().to_owned(),
+            value: canonical_value(&value),
+            trailing: comment.map(str::to_owned),
+        };
+        tables
+            .last_mut()
+            .ok_or_else(|| format!("Key outside of a node: {line}"))?
+            .entries
+            .push(entry);
+    }
+
+    tables.sort_by_key(|t| (t.rank(), t.id().is_none(), t.id()));
+    let mut out = String::new();
+    for c in &preamble {
+        push_line(&mut out, c, None);
+    }
+    for t in &mut tables {
+        t.entries.sort_by_key(|e| {
+            KEY_ORDER
+                .iter()
+                .position(|k| *k == e.key)
+                .unwrap_or(KEY_ORDER.len())
+        });
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        for c in &t.comments {
+            push_line(&mut out, c, None);
+        }
+        push_line(&mut out, &t.header, t.trailing.as_ref());
+        for e in &t.entries {
+            for c in &e.comments {
+                push_line(&mut out, c, None);
+            }
+            push_line(
+                &mut out,
+                &format!("{} = {}", e.key, e.value),
+                e.trailing.as_ref(),
+            );
+        }
+    }
+    if !comments.is_empty() {
+        out.push('\n');
+        for c in &comments {
+            push_line(&mut out, c, None);
+        }
+    }
+
+    // the formatter only moves things around, make sure of it
+    let after: Config = toml::from_str(&out).map_err(|e| e.to_string())?;
+    if config_to_toml(&before.drone, &before.client, &before.server)
+        != config_to_toml(&after.drone, &after.client, &after.server)
+    {
+        return Err("formatting would change the topology".to_owned());
+    }
+    Ok(out)
+}
+
+/// formats the files of `opts` in place, or only checks them; returns
+/// the files that weren't formatted
+pub(super) fn run_fmt(opts: &FmtOptions) -> Result<Vec<PathBuf>, String> {
+    let mut unformatted: Vec<PathBuf> = Vec::new();
+    for path in &opts.files {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        let formatted: String = format_config(&text)
+            .map_err(|e| format!("Unable to format {}: {e}", path.display()))?;
+        if formatted == text {
+            continue;
+        }
+        if !opts.check {
+            fs::write(path, &formatted)
+                .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+        }
+        unformatted.push(path.clone());
+    }
+    Ok(unformatted)
+}
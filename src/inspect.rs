@@ -0,0 +1,175 @@
+use std::{collections::BTreeMap, io, path::PathBuf, time::Duration};
+
+use wg_2024::{
+    network::NodeId,
+    packet::{NackType, PacketType},
+};
+
+use crate::{
+    capture::CaptureReader,
+    tap::{TapFilter, TapRecord},
+};
+
+/// options of the `inspect` subcommand
+#[derive(Debug, Default)]
+pub(super) struct InspectOptions {
+    pub(super) file: PathBuf,
+    pub(super) filter: TapFilter,
+    /// reassemble fragments into messages instead of listing packets
+    pub(super) messages: bool,
+    /// print NACKs and drops per drone instead of listing packets
+    pub(super) summary: bool,
+}
+
+/// message reassembled from the fragments of a session
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Message {
+    pub(super) session_id: u64,
+    pub(super) source: NodeId,
+    pub(super) destination: Option<NodeId>,
+    pub(super) total_fragments: u64,
+    /// fragments seen, indexed by fragment index
+    pub(super) fragments: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Message {
+    pub(super) fn is_complete(&self) -> bool {
+        self.fragments.len() as u64 == self.total_fragments
+    }
+
+    /// concatenation of the fragments seen so far
+    pub(super) fn data(&self) -> Vec<u8> {
+        self.fragments.values().flatten().copied().collect()
+    }
+}
+
+/// groups the fragments by session and source, every fragment is seen once per
+/// hop so duplicates are ignored
+pub(super) fn reassemble<'a>(records: impl IntoIterator<Item = &'a TapRecord>) -> Vec<Message> {
+    let mut messages: BTreeMap<(u64, NodeId), Message> = BTreeMap::new();
+    for r in records {
+        let PacketType::MsgFragment(f) = &r.packet.pack_type else {
+            continue;
+        };
+        let hops: &[NodeId] = &r.packet.routing_header.hops;
+        let source: NodeId = hops.first().copied().unwrap_or(r.from);
+        let msg: &mut Message = messages
+            .entry((r.packet.session_id, source))
+            .or_insert_with(|| Message {
+                session_id: r.packet.session_id,
+                source,
+                destination: hops.last().copied(),
+                total_fragments: f.total_n_fragments,
+                fragments: BTreeMap::new(),
+            });
+        msg.fragments
+            .entry(f.fragment_index)
+            .or_insert_with(|| f.data[..usize::from(f.length).min(f.data.len())].to_vec());
+    }
+    messages.into_values().collect()
+}
+
+/// NACKs generated by a single drone
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct NackSummary {
+    pub(super) dropped: usize,
+    pub(super) error_in_routing: usize,
+    pub(super) destination_is_drone: usize,
+    pub(super) unexpected_recipient: usize,
+}
+
+/// counts the NACKs by the node that generated them (first hop of their route);
+/// a NACK is seen once per hop, so only the first link it crosses is counted
+pub(super) fn nack_summary<'a>(
+    records: impl IntoIterator<Item = &'a TapRecord>,
+) -> BTreeMap<NodeId, NackSummary> {
+    let mut summary: BTreeMap<NodeId, NackSummary> = BTreeMap::new();
+    for r in records {
+        let PacketType::Nack(n) = &r.packet.pack_type else {
+            continue;
+        };
+        let Some(&origin) = r.packet.routing_header.hops.first() else {
+            continue;
+        };
+        if origin != r.from {
+            continue;
+        }
+        let s: &mut NackSummary = summary.entry(origin).or_default();
+        match n.nack_type {
+            NackType::Dropped => s.dropped += 1,
+            NackType::ErrorInRouting(_) => s.error_in_routing += 1,
+            NackType::DestinationIsDrone => s.destination_is_drone += 1,
+            NackType::UnexpectedRecipient(_) => s.unexpected_recipient += 1,
+        }
+    }
+    summary
+}
+
+fn print_packets(records: &[TapRecord]) {
+    let t0: u64 = records.first().map_or(0, TapRecord::micros);
+    for r in records {
+        let fragment: String = r
+            .fragment_index()
+            .map_or_else(|| "-".to_owned(), |i| i.to_string());
+        println!(
+            "{:>12.6} {:>3} -> {:<3} {:<14} session={:<20} fragment={}",
+            Duration::from_micros(r.micros().saturating_sub(t0)).as_secs_f64(),
+            r.from,
+            r.to,
+            r.packet_type(),
+            r.session_id(),
+            fragment
+        );
+    }
+}
+
+fn print_messages(records: &[TapRecord]) {
+    for m in reassemble(records) {
+        let data: Vec<u8> = m.data();
+        let preview: String = String::from_utf8_lossy(&data[..data.len().min(60)])
+            .chars()
+            .map(|c| if c.is_control() { '.' } else { c })
+            .collect();
+        println!(
+            "session={} {} -> {} fragments={}/{} bytes={} {} \"{}\"",
+            m.session_id,
+            m.source,
+            m.destination
+                .map_or_else(|| "?".to_owned(), |d| d.to_string()),
+            m.fragments.len(),
+            m.total_fragments,
+            data.len(),
+            if m.is_complete() {
+                "complete"
+            } else {
+                "incomplete"
+            },
+            preview
+        );
+    }
+}
+
+fn print_summary(records: &[TapRecord]) {
+    println!("drone  dropped  error_in_routing  destination_is_drone  unexpected_recipient");
+    for (id, s) in nack_summary(records) {
+        println!(
+            "{id:>5}  {:>7}  {:>16}  {:>20}  {:>20}",
+            s.dropped, s.error_in_routing, s.destination_is_drone, s.unexpected_recipient
+        );
+    }
+}
+
+/// runs the `inspect` subcommand
+pub(super) fn inspect(opts: &InspectOptions) -> io::Result<()> {
+    let records: Vec<TapRecord> = CaptureReader::open(&opts.file)?
+        .filter(|r| r.as_ref().map_or(true, |r| opts.filter.matches(r)))
+        .collect::<io::Result<_>>()?;
+    if opts.summary {
+        print_summary(&records);
+    } else if opts.messages {
+        print_messages(&records);
+    } else {
+        print_packets(&records);
+    }
+    Ok(())
+}
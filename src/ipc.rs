@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use crossbeam_channel::Sender;
+use log::{debug, info};
+use wg_2024::{
+    controller::{DroneCommand, DroneEvent},
+    network::NodeId,
+    packet::Packet,
+};
+
+use crate::{
+    capture::{decode_packet, encode_packet},
+    network::drone_factory,
+};
+
+/*
+    Frame layout (every integer is big endian, packets as in capture files):
+
+    frame: length of the rest (u32) | tag (u8) | body
+
+    hello          (0): node id (u8), first frame of a child
+    packet         (1): packet, for the drone
+    forward        (2): neighbour (u8) | packet, sent by the drone
+    event          (3): event (u8) | packet
+    add sender     (4): neighbour (u8)
+    remove sender  (5): neighbour (u8)
+    set pdr        (6): pdr (f32 bits, u32)
+    crash          (7)
+
+    events: 0 PacketSent, 1 PacketDropped, 2 ControllerShortcut
+*/
+
+const HELLO: u8 = 0;
+const PACKET: u8 = 1;
+const FORWARD: u8 = 2;
+const EVENT: u8 = 3;
+const ADD_SENDER: u8 = 4;
+const REMOVE_SENDER: u8 = 5;
+const SET_PDR: u8 = 6;
+const CRASH: u8 = 7;
+
+/// longest frame accepted, far above any packet
+const MAX_FRAME: usize = 1 << 20;
+
+/// a message between the initializer and a child process
+#[derive(Debug, Clone)]
+pub(super) enum Frame {
+    Hello(NodeId),
+    Packet(Packet),
+    Forward(NodeId, Packet),
+    Event(DroneEvent),
+    AddSender(NodeId),
+    RemoveSender(NodeId),
+    SetPacketDropRate(f32),
+    Crash,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+pub(super) fn write_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let mut body: Vec<u8> = Vec::new();
+    match frame {
+        Frame::Hello(id) => body.extend([HELLO, *id]),
+        Frame::Packet(p) => {
+            body.push(PACKET);
+            encode_packet(p, &mut body)?;
+        }
+        Frame::Forward(to, p) => {
+            body.extend([FORWARD, *to]);
+            encode_packet(p, &mut body)?;
+        }
+        Frame::Event(e) => {
+            let (kind, p): (u8, &Packet) = match e {
+                DroneEvent::PacketSent(p) => (0, p),
+                DroneEvent::PacketDropped(p) => (1, p),
+                DroneEvent::ControllerShortcut(p) => (2, p),
+            };
+            body.extend([EVENT, kind]);
+            encode_packet(p, &mut body)?;
+        }
+        Frame::AddSender(id) => body.extend([ADD_SENDER, *id]),
+        Frame::RemoveSender(id) => body.extend([REMOVE_SENDER, *id]),
+        Frame::SetPacketDropRate(pdr) => {
+            body.push(SET_PDR);
+            body.extend(pdr.to_bits().to_be_bytes());
+        }
+        Frame::Crash => body.push(CRASH),
+    }
+    let len: u32 = u32::try_from(body.len()).map_err(|_| invalid("frame too long"))?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(&body)?;
+    out.flush()
+}
+
+/// next frame of `input`, `None` once it's closed between two frames
+pub(super) fn read_frame(input: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len: usize = usize::try_from(u32::from_be_bytes(len)).unwrap_or(usize::MAX);
+    if len == 0 || len > MAX_FRAME {
+        return Err(invalid("bad frame length"));
+    }
+    let mut body: Vec<u8> = vec![0; len];
+    input.read_exact(&mut body)?;
+    let byte = |i: usize| {
+        body.get(i)
+            .copied()
+            .ok_or_else(|| invalid("frame too short"))
+    };
+    let frame: Frame = match body[0] {
+        HELLO => Frame::Hello(byte(1)?),
+        PACKET => Frame::Packet(decode_packet(&body[1..])?),
+        FORWARD => Frame::Forward(byte(1)?, decode_packet(body.get(2..).unwrap_or_default())?),
+        EVENT => {
+            let p: Packet = decode_packet(body.get(2..).unwrap_or_default())?;
+            Frame::Event(match byte(1)? {
+                0 => DroneEvent::PacketSent(p),
+                1 => DroneEvent::PacketDropped(p),
+                2 => DroneEvent::ControllerShortcut(p),
+                _ => return Err(invalid("unknown event")),
+            })
+        }
+        ADD_SENDER => Frame::AddSender(byte(1)?),
+        REMOVE_SENDER => Frame::RemoveSender(byte(1)?),
+        SET_PDR => {
+            let bits: [u8; 4] = body
+                .get(1..5)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| invalid("frame too short"))?;
+            Frame::SetPacketDropRate(f32::from_bits(u32::from_be_bytes(bits)))
+        }
+        CRASH => Frame::Crash,
+        _ => return Err(invalid("unknown frame")),
+    };
+    Ok(Some(frame))
+}
+
+/// a writer shared by the threads of a bridge, one frame at a time
+pub(super) type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+pub(super) fn send_frame(out: &SharedWriter, frame: &Frame) -> io::Result<()> {
+    let mut out = out.lock().unwrap_or_else(PoisonError::into_inner);
+    write_frame(&mut *out, frame)
+}
+
+/// options of the hidden `node` subcommand, run by the child processes
+#[derive(Debug, Default)]
+pub(super) struct NodeOptions {
+    /// socket of the initializer
+    pub(super) socket: PathBuf,
+    pub(super) id: NodeId,
+    /// index in the drone factory
+    pub(super) implementation: usize,
+    pub(super) pdr: f32,
+    pub(super) neighbours: Vec<NodeId>,
+}
+
+/// a sender whose packets are written to `out` as sent to `to`
+fn forwarder(out: &SharedWriter, to: NodeId) -> Sender<Packet> {
+    let (send, packets) = crossbeam_channel::unbounded::<Packet>();
+    let out: SharedWriter = Arc::clone(out);
+    thread::spawn(move || {
+        for p in packets {
+            if send_frame(&out, &Frame::Forward(to, p)).is_err() {
+                return;
+            }
+        }
+    });
+    send
+}
+
+/// runs the drone described by `opts`, its packets, commands and events
+/// going through `input` and `output`; returns once the drone stops
+pub(super) fn run_node(
+    mut input: impl Read + Send + 'static,
+    output: impl Write + Send + 'static,
+    opts: &NodeOptions,
+) -> io::Result<()> {
+    let out: SharedWriter = Arc::new(Mutex::new(Box::new(output)));
+    send_frame(&out, &Frame::Hello(opts.id))?;
+
+    let (packet_send, packet_recv) = crossbeam_channel::unbounded::<Packet>();
+    let (command_send, command_recv) = crossbeam_channel::unbounded::<DroneCommand>();
+    let (event_send, event_recv) = crossbeam_channel::unbounded::<DroneEvent>();
+    let neighbours: HashMap<NodeId, Sender<Packet>> = opts
+        .neighbours
+        .iter()
+        .map(|id| (*id, forwarder(&out, *id)))
+        .collect();
+
+    let events_out: SharedWriter = Arc::clone(&out);
+    thread::spawn(move || {
+        for e in event_recv {
+            if send_frame(&events_out, &Frame::Event(e)).is_err() {
+                return;
+            }
+        }
+    });
+    let id: NodeId = opts.id;
+    thread::spawn(move || loop {
+        let frame: Frame = match read_frame(&mut input) {
+            Ok(Some(f)) => f,
+            Ok(None) => {
+                info!("Drone {id}: initializer gone, crashing");
+                let _ = command_send.send(DroneCommand::Crash);
+                return;
+            }
+            Err(e) => {
+                info!("Drone {id}: initializer unreachable ({e}), crashing");
+                let _ = command_send.send(DroneCommand::Crash);
+                return;
+            }
+        };
+        let command: DroneCommand = match frame {
+            Frame::Packet(p) => {
+                let _ = packet_send.send(p);
+                continue;
+            }
+            Frame::AddSender(n) => DroneCommand::AddSender(n, forwarder(&out, n)),
+            Frame::RemoveSender(n) => DroneCommand::RemoveSender(n),
+            Frame::SetPacketDropRate(pdr) => DroneCommand::SetPacketDropRate(pdr),
+            Frame::Crash => {
+                // closing the packet channel lets the drone finish draining it
+                let _ = command_send.send(DroneCommand::Crash);
+                return;
+            }
+            f => {
+                debug!("Drone {id}: unexpected {f:?}");
+                continue;
+            }
+        };
+        let _ = command_send.send(command);
+    });
+
+    let drone_factory = drone_factory();
+    let (_, factory) = drone_factory[opts.implementation % drone_factory.len()];
+    let mut drone = factory(
+        opts.id,
+        event_send,
+        command_recv,
+        packet_recv,
+        neighbours,
+        opts.pdr,
+    );
+    drone.run();
+    Ok(())
+}
+
+/// connects to the initializer and runs the drone of `opts`
+#[cfg(unix)]
+pub(super) fn run_node_process(opts: &NodeOptions) -> io::Result<()> {
+    let stream = std::os::unix::net::UnixStream::connect(&opts.socket)?;
+    let output = stream.try_clone()?;
+    run_node(stream, output, opts)
+}
+
+#[cfg(not(unix))]
+pub(super) fn run_node_process(_opts: &NodeOptions) -> io::Result<()> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "Unix domain sockets are not available on this platform",
+    ))
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    thread,
+};
+
+use flexi_logger::{
+    filter::{LogLineFilter, LogLineWriter},
+    with_thread,
+    writers::{FileLogWriter, LogWriter},
+    Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, FlexiLoggerError, LogSpecBuilder,
+    LogSpecification, Logger, LoggerHandle, Naming,
+};
+use log::{LevelFilter, Record};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
+use wg_2024::network::NodeId;
+
+use crate::network::NodeKind;
+
+/// name of the combined log file
+const COMBINED_LOG: &str = "network";
+
+/// directory of the log files in the temporary directory when the
+/// terminal is taken and no directory is given
+const QUIET_LOG_DIR: &str = "network_initializer";
+
+/// shape of the log lines
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LogFormat {
+    /// plain lines tagged with the thread name, the only format with
+    /// per-node files and rotation
+    #[default]
+    Text,
+    /// lines prefixed by the node and session spans they come from
+    Human,
+    /// one JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown log format: {s}")),
+        }
+    }
+}
+
+/// how the logger is set up
+#[derive(Debug)]
+pub(super) struct LoggingOptions {
+    pub(super) format: LogFormat,
+    /// levels per module, e.g. `info,rusty_drones=debug`; `RUST_LOG`
+    /// (or `info`) if missing
+    pub(super) spec: Option<String>,
+    /// level of the lines logged by the thread of each node, in place of
+    /// the levels of `spec`
+    pub(super) nodes: HashMap<NodeId, LevelFilter>,
+    /// directory of the log files, only stderr is used if missing
+    pub(super) dir: Option<PathBuf>,
+    /// also write the lines of each node to a file of its own
+    pub(super) per_node: bool,
+    /// rotate the files once they reach this size, in bytes
+    pub(super) rotate: Option<u64>,
+    /// rotated files kept for each log
+    pub(super) keep: usize,
+    /// leave the terminal alone, nothing goes to stderr
+    pub(super) quiet: bool,
+}
+
+impl Default for LoggingOptions {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            spec: None,
+            nodes: HashMap::new(),
+            dir: None,
+            per_node: false,
+            rotate: None,
+            keep: 5,
+            quiet: false,
+        }
+    }
+}
+
+/// id of the node run by the thread called `name`, if any
+pub(super) fn thread_node(name: &str) -> Option<NodeId> {
+    let (kind, id) = name.rsplit_once('-')?;
+    if NodeKind::ALL.iter().any(|k| k.as_str() == kind) {
+        id.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// level of `module` according to `spec`, the longest matching prefix wins
+pub(super) fn module_level(spec: &LogSpecification, module: Option<&str>) -> LevelFilter {
+    let module: &str = module.unwrap_or_default();
+    spec.module_filters()
+        .iter()
+        .filter(|f| {
+            f.module_name
+                .as_deref()
+                .map_or(true, |m| module.starts_with(m))
+        })
+        .max_by_key(|f| f.module_name.as_deref().map_or(0, str::len))
+        .map_or(LevelFilter::Off, |f| f.level_filter)
+}
+
+/// applies the per-node levels, the lines of the other threads go
+/// through the module levels
+struct NodeLevels {
+    spec: LogSpecification,
+    nodes: HashMap<NodeId, LevelFilter>,
+}
+
+impl LogLineFilter for NodeLevels {
+    fn write(
+        &self,
+        now: &mut DeferredNow,
+        record: &Record,
+        log_line_writer: &dyn LogLineWriter,
+    ) -> io::Result<()> {
+        let level: LevelFilter = thread::current()
+            .name()
+            .and_then(thread_node)
+            .and_then(|id| self.nodes.get(&id).copied())
+            .unwrap_or_else(|| module_level(&self.spec, record.module_path()));
+        if record.level() <= level {
+            log_line_writer.write(now, record)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// writes the lines of each node thread to `<dir>/<thread name>.log`
+struct NodeFiles {
+    dir: PathBuf,
+    rotate: Option<u64>,
+    keep: usize,
+    files: Mutex<HashMap<String, FileLogWriter>>,
+}
+
+impl NodeFiles {
+    fn file(&self, name: &str) -> Result<FileLogWriter, FlexiLoggerError> {
+        let spec: FileSpec = FileSpec::default()
+            .directory(&self.dir)
+            .basename(name)
+            .suppress_timestamp();
+        let builder = FileLogWriter::builder(spec).format(with_thread);
+        match self.rotate {
+            Some(size) => builder.rotate(
+                Criterion::Size(size),
+                Naming::Numbers,
+                Cleanup::KeepLogFiles(self.keep),
+            ),
+            None => builder,
+        }
+        .try_build()
+    }
+}
+
+impl LogWriter for NodeFiles {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+        let Some(name) = thread::current()
+            .name()
+            .filter(|n| thread_node(n).is_some())
+            .map(str::to_owned)
+        else {
+            return Ok(());
+        };
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|_| io::Error::other("node log files poisoned"))?;
+        if !files.contains_key(&name) {
+            let file: FileLogWriter = self.file(&name).map_err(io::Error::other)?;
+            files.insert(name.clone(), file);
+        }
+        files[&name].write(now, record)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let files = self
+            .files
+            .lock()
+            .map_err(|_| io::Error::other("node log files poisoned"))?;
+        files.values().try_for_each(LogWriter::flush)
+    }
+}
+
+fn combined_file(dir: &Path) -> FileSpec {
+    FileSpec::default()
+        .directory(dir)
+        .basename(COMBINED_LOG)
+        .suppress_timestamp()
+}
+
+/// directory of the log files: `dir`, or one in the temporary directory
+/// when stderr can't be used
+pub(super) fn log_dir(opts: &LoggingOptions) -> Option<PathBuf> {
+    opts.dir
+        .clone()
+        .or_else(|| opts.quiet.then(|| env::temp_dir().join(QUIET_LOG_DIR)))
+}
+
+/// filter directives of the span-aware formats: `spec` (or `RUST_LOG`, or
+/// `info`) followed by one directive per node, matching its span
+pub(super) fn tracing_directives(opts: &LoggingOptions) -> String {
+    let mut directives: Vec<String> = vec![opts
+        .spec
+        .clone()
+        .or_else(|| env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "info".to_owned())];
+    let mut nodes: Vec<(&NodeId, &LevelFilter)> = opts.nodes.iter().collect();
+    nodes.sort();
+    directives.extend(
+        nodes
+            .into_iter()
+            .map(|(id, level)| format!("[node{{id={id}}}]={}", level.as_str().to_lowercase())),
+    );
+    directives.join(",")
+}
+
+/// installs a tracing subscriber printing the spans of every line, the
+/// `log` records of the node crates included
+fn init_tracing(opts: &LoggingOptions) -> Result<(), String> {
+    if opts.per_node || opts.rotate.is_some() {
+        return Err("--log-per-node and --log-rotate need --log-format text".to_owned());
+    }
+    let filter = EnvFilter::try_new(tracing_directives(opts)).map_err(|e| e.to_string())?;
+    let dir: Option<PathBuf> = log_dir(opts);
+    let writer: BoxMakeWriter = match &dir {
+        None => BoxMakeWriter::new(io::stderr),
+        Some(dir) => {
+            let extension: &str = if opts.format == LogFormat::Json {
+                "jsonl"
+            } else {
+                "log"
+            };
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            let file: File = File::create(dir.join(format!("{COMBINED_LOG}.{extension}")))
+                .map_err(|e| e.to_string())?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_thread_names(true)
+        .with_writer(writer);
+    if opts.format == LogFormat::Json {
+        builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init()
+    } else {
+        builder.with_ansi(dir.is_none()).try_init()
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// starts the logger; with the text format it stops once the handle is
+/// dropped, the other formats log until the process exits
+pub(super) fn init_logging(opts: &LoggingOptions) -> Result<Option<LoggerHandle>, String> {
+    if opts.format == LogFormat::Text {
+        init_text(opts).map(Some).map_err(|e| e.to_string())
+    } else {
+        init_tracing(opts).map(|()| None)
+    }
+}
+
+fn init_text(opts: &LoggingOptions) -> Result<LoggerHandle, FlexiLoggerError> {
+    let spec: LogSpecification = match &opts.spec {
+        Some(s) => LogSpecification::parse(s)?,
+        None => LogSpecification::env_or_parse("info")?,
+    };
+    let mut logger: Logger = if opts.nodes.is_empty() {
+        Logger::with(spec)
+    } else {
+        // let everything through up to the most verbose level asked for,
+        // the filter does the rest
+        let max: LevelFilter = spec
+            .module_filters()
+            .iter()
+            .map(|f| f.level_filter)
+            .chain(opts.nodes.values().copied())
+            .max()
+            .unwrap_or(LevelFilter::Info);
+        Logger::with(LogSpecBuilder::new().default(max).build()).filter(Box::new(NodeLevels {
+            spec,
+            nodes: opts.nodes.clone(),
+        }))
+    };
+
+    logger = match log_dir(opts) {
+        None => logger.log_to_stderr(),
+        Some(dir) => {
+            let logger: Logger = if opts.per_node {
+                let files = NodeFiles {
+                    dir: dir.clone(),
+                    rotate: opts.rotate,
+                    keep: opts.keep,
+                    files: Mutex::default(),
+                };
+                logger.log_to_file_and_writer(combined_file(&dir), Box::new(files))
+            } else {
+                logger.log_to_file(combined_file(&dir))
+            }
+            .format_for_files(with_thread)
+            .duplicate_to_stderr(if opts.quiet {
+                Duplicate::None
+            } else {
+                Duplicate::Info
+            });
+            match opts.rotate {
+                Some(size) => logger.rotate(
+                    Criterion::Size(size),
+                    Naming::Numbers,
+                    Cleanup::KeepLogFiles(opts.keep),
+                ),
+                None => logger,
+            }
+        }
+    };
+    logger.start()
+}
@@ -12,289 +12,405 @@
 #![warn(missing_docs)]
 #![deny(unsafe_code)]
 
-use ap2024_rustinpeace_nosounddrone::NoSoundDroneRIP;
-use ap2024_unitn_cppenjoyers_webservers::{MediaServer, TextServer};
-use chat_server_client::client::ChatClient;
-use chat_server_client::server::ChatServer;
-use common::slc_commands::{
-    ChatClientCommand, ChatClientEvent, ServerCommand, ServerEvent, WebClientCommand,
-    WebClientEvent,
-};
-use common::{Client as ClientTrait, Server as ServerTrait};
-use crossbeam_channel::{Receiver, Sender};
-use dr_ones::Drone as DrDrone;
-use drone_bettercalldrone::BetterCallDrone;
-use factories::ClientFuncs;
-use getdroned::GetDroned;
-use itertools::chain;
-use log::error;
-use rolling_drone::RollingDrone;
-use rust_do_it::RustDoIt;
-use rust_roveri::RustRoveri;
-use rustafarian_drone::RustafarianDrone;
-use rusteze_drone::RustezeDrone;
-use rusty_drones::RustyDrone;
-use std::collections::HashMap;
+use bench::{run_bench, run_bench_case};
+use capture::CaptureWriter;
+use chaos::start_chaos;
+use cli::{parse_command, split_logging_options, Command, RunOptions, TapOptions};
+use conformance::run_conformance;
+use dashboard::{serve as serve_dashboard, Dashboard, DashboardSink, Topology, METRICS_INTERVAL};
+use diff::run_diff;
+use formatter::run_fmt;
+use inspect::inspect;
+use ipc::run_node_process;
+use log::{error, info};
+use logging::{init_logging, log_dir};
+use matrix::{run_isolated, run_matrix, run_scenario, Implementations};
+use metrics::{dump_periodically, serve as serve_metrics, Metrics, MetricsSink};
+use network::{drain_events, spawn_network, spawn_network_on, DroneHost, Network, Setup};
+use pcap::export;
+use random::resolve_seed;
+use recorder::EventRecorder;
+use repl::run_repl;
+use replay::{read_recording, replay, CommandRecorder, ReplayOptions};
+use respawn::Respawner;
+use rpc::{start_rpc, Rpc};
+use schema::run_schema;
 use std::env;
 use std::fs;
-use topology_utils::check_topology_constraints;
-use web_client::web_client::WebBrowser;
-use wg_2024::config::Config;
-use wg_2024::config::{Client, Drone, Server};
-use wg_2024::controller::{DroneCommand, DroneEvent};
-use wg_2024::drone::Drone as DroneTrait;
-use wg_2024::network::NodeId;
-use wg_2024::packet::Packet;
-// use chat_common;
+use std::sync::Arc;
+use std::thread::{park, sleep};
+use supervisor::{default_socket, Supervisor};
+use tap::{LogSink, Tap, TapFilter, TapSink, TextSink};
+use tui::run_tui;
 
+mod bench;
+mod capture;
+mod chaos;
+mod cli;
+mod conformance;
+mod dashboard;
+mod diff;
 mod factories;
+mod formatter;
+mod inspect;
+mod ipc;
+mod logging;
+mod matrix;
+mod metrics;
+mod network;
+mod pcap;
+mod random;
+mod recorder;
+mod repl;
+mod replay;
+mod respawn;
+mod router;
+mod rpc;
+mod schema;
+mod supervisor;
+mod tap;
 mod topology_utils;
+mod tui;
 
 #[cfg(test)]
 mod test;
 
-type DroneChannels = HashMap<
-    NodeId,
-    (
-        Sender<DroneCommand>,
-        Receiver<DroneEvent>,
-        Sender<Packet>,
-        Receiver<Packet>,
-    ),
->;
-type WebClientChannels = HashMap<
-    NodeId,
-    (
-        Sender<WebClientCommand>,
-        Receiver<WebClientEvent>,
-        Sender<Packet>,
-        Receiver<Packet>,
-    ),
->;
-type ChatClientChannels = HashMap<
-    NodeId,
-    (
-        Sender<ChatClientCommand>,
-        Receiver<ChatClientEvent>,
-        Sender<Packet>,
-        Receiver<Packet>,
-    ),
->;
-type ServerChannels = HashMap<
-    NodeId,
-    (
-        Sender<ServerCommand>,
-        Receiver<ServerEvent>,
-        Sender<Packet>,
-        Receiver<Packet>,
-    ),
->;
-
-// IDK how I should name it so that's it
-#[allow(clippy::type_complexity)]
-fn create_scl_channels<T, U>(
-    v: &[U],
-    f: fn(&U) -> (NodeId, (Sender<T>, Receiver<T>)),
-) -> HashMap<NodeId, (Sender<T>, Receiver<T>)> {
-    v.iter().map(f).collect()
+/// starts the packet tap requested on the command line, or the one feeding
+/// the dashboard
+fn start_tap(
+    opts: Option<&TapOptions>,
+    metrics: Option<&Arc<Metrics>>,
+    dashboard: Option<&Arc<Dashboard>>,
+) -> Option<Tap> {
+    if opts.is_none() && dashboard.is_none() {
+        return None;
+    }
+    let mut sinks: Vec<Box<dyn TapSink>> = Vec::new();
+    if let Some(opts) = opts {
+        if let Some(path) = &opts.text_file {
+            match TextSink::create(path) {
+                Ok(s) => sinks.push(Box::new(s)),
+                Err(e) => error!("Unable to create tap file {}: {e}", path.display()),
+            }
+        }
+        if let Some(path) = &opts.capture_file {
+            match CaptureWriter::create(path) {
+                Ok(s) => sinks.push(Box::new(s)),
+                Err(e) => error!("Unable to create capture file {}: {e}", path.display()),
+            }
+        }
+        if opts.log || sinks.is_empty() {
+            sinks.push(Box::new(LogSink));
+        }
+    }
+    if let Some(m) = metrics {
+        sinks.push(Box::new(MetricsSink(Arc::clone(m))));
+    }
+    if let Some(d) = dashboard {
+        sinks.push(Box::new(DashboardSink(Arc::clone(d))));
+    }
+    let filter: TapFilter = opts.map(|o| o.filter.clone()).unwrap_or_default();
+    Some(Tap::start(filter, sinks))
 }
 
-fn create_channels<'a, T>(
-    drones: &'a [Drone],
-    clients: &'a [Client],
-    servers: &'a [Server],
-) -> impl Iterator<Item = (NodeId, (Sender<T>, Receiver<T>))> + use<'a, T> {
-    chain![
-        drones
-            .iter()
-            .map(|d: &Drone| (d.id, crossbeam_channel::unbounded::<T>())),
-        clients
-            .iter()
-            .map(|c: &Client| (c.id, crossbeam_channel::unbounded::<T>())),
-        servers
-            .iter()
-            .map(|s: &Server| (s.id, crossbeam_channel::unbounded::<T>())),
-    ]
+/// wraps the channels of `network` in the recorders requested on the
+/// command line
+fn observe(
+    mut network: Network,
+    opts: &RunOptions,
+    setup: &Setup,
+    metrics: Option<&Arc<Metrics>>,
+) -> Network {
+    if let Some(path) = &opts.event_log {
+        match EventRecorder::start(path) {
+            // the writer runs as long as the nodes
+            Ok((recorder, _writer)) => network = recorder.wrap_network(network),
+            Err(e) => error!("Unable to create event log {}: {e}", path.display()),
+        }
+    }
+    if let Some(path) = &opts.recording {
+        match CommandRecorder::start(path, setup) {
+            Ok((recorder, _writer)) => network = recorder.wrap_network(network),
+            Err(e) => error!("Unable to create recording {}: {e}", path.display()),
+        }
+    }
+    if let Some(m) = metrics {
+        m.register(&network);
+        network = m.wrap_network(network);
+        if let Some(port) = opts.metrics_port {
+            if let Err(e) = serve_metrics(Arc::clone(m), port) {
+                error!("Unable to serve metrics on port {port}: {e}");
+            }
+        }
+        if let Some(interval) = opts.metrics_interval {
+            dump_periodically(Arc::clone(m), interval);
+        }
+    }
+    network
 }
 
-// It's fair to have a longer main here... most of the lines are just constructors
-// after the "cargo fmt" command
-#[allow(clippy::too_many_lines)]
-fn main() {
-    env::set_var("RUST_LOG", "info");
-    let _ = env_logger::try_init();
+/// serves the dashboard of `network` on `port`, returns the network with
+/// its events copied to the browsers
+fn start_dashboard(
+    dashboard: &Arc<Dashboard>,
+    port: u16,
+    network: Network,
+    topology: Topology,
+    metrics: Option<&Arc<Metrics>>,
+) -> Network {
+    dashboard.set_topology(topology);
+    let network: Network = dashboard.recorder().wrap_network(network);
+    if let Some(m) = metrics {
+        dashboard.stream_metrics(Arc::clone(m), METRICS_INTERVAL);
+    }
+    if let Err(e) = serve_dashboard(Arc::clone(dashboard), port) {
+        error!("Unable to serve the dashboard on port {port}: {e}");
+    }
+    network
+}
 
-    let drone_factory = [
-        create_boxed_drone!(DrDrone),
-        create_boxed_drone!(RustDoIt),
-        create_boxed_drone!(RustRoveri),
-        create_boxed_drone!(RollingDrone),
-        create_boxed_drone!(RustafarianDrone),
-        create_boxed_drone!(RustezeDrone),
-        create_boxed_drone!(RustyDrone),
-        create_boxed_drone!(GetDroned),
-        create_boxed_drone!(NoSoundDroneRIP),
-        create_boxed_drone!(BetterCallDrone),
-    ];
-    let client_factory = [
-        ClientFuncs::WebFn(WebBrowser::new),
-        ClientFuncs::ChatFn(ChatClient::new),
-    ];
-    let server_factory = [
-        create_boxed_server!(TextServer),
-        create_boxed_server!(MediaServer),
-        create_boxed_server!(ChatServer),
-    ];
+/// builds the network of a simulation run and hands it to the controller
+fn run(opts: &RunOptions) {
+    let setup = Setup {
+        config: fs::read_to_string(&opts.config).expect("Unable to read config file"),
+        seed: resolve_seed(opts.seed),
+        assignment: opts.assignment,
+        random_pdr: opts.random_pdr,
+    };
+    let Some((config, implementations)) = setup.prepare() else {
+        return;
+    };
 
-    let config_data: String =
-        fs::read_to_string("config/test_chat_config.toml").expect("Unable to read config file");
-    // having our structs implement the Deserialize trait allows us to use the toml::from_str function to deserialize the config file into each of them
-    let Config {
-        drone,
-        client,
-        server,
-    }: Config = toml::from_str(&config_data).expect("Unable to parse TOML");
+    let dashboard: Option<Arc<Dashboard>> = opts.dashboard_port.map(|_| Arc::default());
+    let metrics: Option<Arc<Metrics>> =
+        (opts.metrics_port.is_some() || opts.metrics_interval.is_some() || dashboard.is_some())
+            .then(Arc::default);
+    let tap: Option<Tap> = start_tap(opts.tap.as_ref(), metrics.as_ref(), dashboard.as_ref());
+    // kills the drone processes once dropped, at the end of the run
+    let supervisor: Option<Supervisor> = opts.processes.as_ref().and_then(|p| {
+        let socket = default_socket();
+        Supervisor::bind(&socket, p)
+            .map_err(|e| error!("Unable to listen on {}: {e}", socket.display()))
+            .ok()
+    });
+    let host: DroneHost = supervisor
+        .as_ref()
+        .map_or(DroneHost::Threads, DroneHost::Processes);
+    let (network, respawner) = spawn_network_on(&config, &implementations, tap.as_ref(), host);
+    let respawner: Arc<Respawner> = Arc::new(respawner);
+    // the links keep their own handle to the collector
+    drop(tap);
+    let mut network: Network = observe(network, opts, &setup, metrics.as_ref());
+    if let (Some(d), Some(port)) = (&dashboard, opts.dashboard_port) {
+        let topology = Topology::new(&config, &network, &implementations);
+        network = start_dashboard(d, port, network, topology, metrics.as_ref());
+    }
+    // the one view of the topology shared by the API, the consoles and
+    // `--save`, kept up to date with the commands of every controller
+    let rpc: Arc<Rpc> =
+        Arc::new(Rpc::new(&network, &config).with_respawner(Some(Arc::clone(&respawner))));
+    network = rpc.track_network(network);
+    if let Some(endpoint) = &opts.rpc {
+        network = start_rpc(endpoint, network, Arc::clone(&rpc));
+    }
+    if let Some(chaos) = &opts.chaos {
+        start_chaos(&network, &config, setup.seed, chaos, Arc::clone(&respawner));
+    }
 
-    // check topology constraints
-    if !check_topology_constraints(&drone, &client, &server) {
-        error!("Inconsistent topology");
+    if opts.repl {
+        run_repl(network, Arc::clone(&rpc));
+        save(&rpc, opts);
+        return;
+    }
+    if opts.tui {
+        run_tui(network, Arc::clone(&rpc));
+        save(&rpc, opts);
         return;
     }
 
-    let mut scl_drones_channels: DroneChannels = HashMap::new();
-    let mut scl_web_clients_channels: WebClientChannels = HashMap::new();
-    let mut scl_chat_clients_channels: ChatClientChannels = HashMap::new();
-    let mut scl_servers_channels: ServerChannels = HashMap::new();
+    if opts.headless {
+        let router = drain_events(&network);
+        match opts.duration {
+            Some(d) => sleep(d),
+            None => loop {
+                park();
+            },
+        }
+        router.log_counts();
+        save(&rpc, opts);
+        return;
+    }
 
-    let scl_events: HashMap<NodeId, (Sender<DroneEvent>, Receiver<DroneEvent>)> =
-        create_scl_channels(&drone, |d| (d.id, crossbeam_channel::unbounded()));
-    let scl_commands: HashMap<NodeId, (Sender<DroneCommand>, Receiver<DroneCommand>)> =
-        create_scl_channels(&drone, |d| (d.id, crossbeam_channel::unbounded()));
-    let scl_server_events: HashMap<NodeId, (Sender<ServerEvent>, Receiver<ServerEvent>)> =
-        create_scl_channels(&server, |s| (s.id, crossbeam_channel::unbounded()));
-    let scl_server_commands: HashMap<NodeId, (Sender<ServerCommand>, Receiver<ServerCommand>)> =
-        create_scl_channels(&server, |s| (s.id, crossbeam_channel::unbounded()));
-    let channels: HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)> =
-        create_channels(&drone, &client, &server).collect();
+    let Network {
+        drones,
+        web_clients,
+        chat_clients,
+        servers,
+    } = network;
+    simulation_controller::run(
+        drones,
+        web_clients,
+        chat_clients,
+        servers,
+        config.drone,
+        config.client,
+        config.server,
+    );
+    save(&rpc, opts);
+}
 
-    // drones and servers spawn (trivial)
-    for d in &drone {
-        let nbrs: HashMap<NodeId, Sender<Packet>> = d
-            .connected_node_ids
-            .iter()
-            .map(|id: &NodeId| (*id, channels[id].0.clone()))
-            .collect();
-        let mut new_drone: Box<dyn DroneTrait> = drone_factory
-            [usize::from(d.id) % drone_factory.len()](
-            d.id,
-            scl_events[&d.id].0.clone(),
-            scl_commands[&d.id].1.clone(),
-            channels[&d.id].1.clone(),
-            nbrs,
-            d.pdr,
-        );
-        std::thread::spawn(move || new_drone.run());
-    }
-    for s in &server {
-        let nbrs: HashMap<NodeId, Sender<Packet>> = s
-            .connected_drone_ids
-            .iter()
-            .map(|id: &NodeId| (*id, channels[id].0.clone()))
-            .collect();
-        let mut new_server: Box<dyn ServerTrait> = server_factory
-            [usize::from(s.id) % server_factory.len()](
-            s.id,
-            scl_server_events[&s.id].0.clone(),
-            scl_server_commands[&s.id].1.clone(),
-            channels[&s.id].1.clone(),
-            nbrs,
-        );
-        std::thread::spawn(move || new_server.run());
+/// writes the topology left by the run to `--save`, if given
+fn save(rpc: &Rpc, opts: &RunOptions) {
+    let Some(path) = &opts.save else {
+        return;
+    };
+    match rpc.save_config(path) {
+        Ok(()) => info!("Saved the topology to {}", path.display()),
+        Err(e) => error!("Unable to save the topology: {}", e.message),
     }
+}
 
-    for d in &drone {
-        scl_drones_channels.insert(
-            d.id,
-            (
-                scl_commands[&d.id].0.clone(),
-                scl_events[&d.id].1.clone(),
-                channels[&d.id].0.clone(),
-                channels[&d.id].1.clone(),
-            ),
-        );
-    }
-    for s in &server {
-        scl_servers_channels.insert(
-            s.id,
-            (
-                scl_server_commands[&s.id].0.clone(),
-                scl_server_events[&s.id].1.clone(),
-                channels[&s.id].0.clone(),
-                channels[&s.id].1.clone(),
-            ),
-        );
+/// rebuilds the network of a recording and issues its commands again
+fn run_replay(opts: &ReplayOptions) {
+    let (setup, commands) = match read_recording(&opts.file) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Unable to read recording {}: {e}", opts.file.display());
+            return;
+        }
+    };
+    info!(
+        "Replaying {} commands with seed {}",
+        commands.len(),
+        setup.seed
+    );
+    let Some((config, implementations)) = setup.prepare() else {
+        return;
+    };
+    let mut network: Network = spawn_network(&config, &implementations, None);
+    if let Some(path) = &opts.event_log {
+        match EventRecorder::start(path) {
+            // the writer runs as long as the nodes
+            Ok((recorder, _writer)) => network = recorder.wrap_network(network),
+            Err(e) => error!("Unable to create event log {}: {e}", path.display()),
+        }
     }
+    replay(&network, &commands, opts.wait);
+}
 
-    // clients spawn
-    for c in &client {
-        let nbrs: HashMap<NodeId, Sender<Packet>> = c
-            .connected_drone_ids
-            .iter()
-            .map(|id: &NodeId| (*id, channels[id].0.clone()))
-            .collect();
-        match client_factory[usize::from(c.id) % client_factory.len()] {
-            ClientFuncs::WebFn(f) => {
-                let (c1, c2) = crossbeam_channel::unbounded();
-                let (c3, c4) = crossbeam_channel::unbounded();
-                let mut new_client = f(
-                    c.id,
-                    c1.clone(),
-                    c4.clone(),
-                    channels[&c.id].1.clone(),
-                    nbrs,
-                );
-                scl_web_clients_channels.insert(
-                    c.id,
-                    (
-                        c3.clone(),
-                        c2.clone(),
-                        channels[&c.id].0.clone(),
-                        channels[&c.id].1.clone(),
-                    ),
-                );
-                std::thread::spawn(move || new_client.run());
+fn main() {
+    let (mut logging, args) = match split_logging_options(env::args().skip(1)) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let command: Result<Command, String> = parse_command(args);
+    // the terminal UI draws over anything written to stderr
+    logging.quiet = matches!(&command, Ok(Command::Run(opts)) if opts.tui);
+    // stops logging once dropped, at the end of main
+    let _logger = init_logging(&logging)
+        .map_err(|e| eprintln!("Unable to start the logger: {e}"))
+        .ok()
+        .flatten();
+
+    match command {
+        Ok(Command::Run(opts)) => {
+            run(&opts);
+            if let Some(dir) = log_dir(&logging).filter(|_| logging.quiet) {
+                eprintln!("Log written to {}", dir.display());
+            }
+        }
+        Ok(Command::Inspect(opts)) => {
+            if let Err(e) = inspect(&opts) {
+                error!("Unable to read capture {}: {e}", opts.file.display());
+            }
+        }
+        Ok(Command::Pcap(opts)) => match export(&opts) {
+            Ok(n) => info!("Exported {n} packets to {}", opts.output.display()),
+            Err(e) => error!("Unable to export {}: {e}", opts.input.display()),
+        },
+        Ok(Command::Replay(opts)) => run_replay(&opts),
+        Ok(Command::Matrix(opts)) => {
+            let (passed, total) = run_matrix(&opts, run_isolated);
+            if passed < total {
+                std::process::exit(1);
+            }
+        }
+        Ok(Command::Conformance(opts)) => {
+            let (passed, total) = run_conformance(&opts);
+            if passed < total {
+                std::process::exit(1);
+            }
+        }
+        Ok(Command::Bench(opts)) => {
+            run_bench(&opts);
+        }
+        Ok(Command::Diff(opts)) => match run_diff(&opts) {
+            Ok(false) => {}
+            // like diff(1), differences are reported through the exit code
+            Ok(true) => std::process::exit(1),
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(2);
+            }
+        },
+        Ok(Command::Fmt(opts)) => match run_fmt(&opts) {
+            Ok(files) if opts.check && !files.is_empty() => {
+                for f in files {
+                    println!("{} isn't formatted", f.display());
+                }
+                std::process::exit(1);
             }
-            ClientFuncs::ChatFn(f) => {
-                let (c1, c2) = crossbeam_channel::unbounded();
-                let (c3, c4) = crossbeam_channel::unbounded();
-                let mut new_client = f(
-                    c.id,
-                    c1.clone(),
-                    c4.clone(),
-                    channels[&c.id].1.clone(),
-                    nbrs,
-                );
-                scl_chat_clients_channels.insert(
-                    c.id,
-                    (
-                        c3.clone(),
-                        c2.clone(),
-                        channels[&c.id].0.clone(),
-                        channels[&c.id].1.clone(),
-                    ),
-                );
-                std::thread::spawn(move || new_client.run());
+            Ok(files) => {
+                for f in files {
+                    info!("Formatted {}", f.display());
+                }
+            }
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(2);
+            }
+        },
+        Ok(Command::Schema(opts)) => {
+            if let Err(e) = run_schema(&opts) {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        }
+        Ok(Command::Node(opts)) => {
+            if let Err(e) = run_node_process(&opts) {
+                error!("Drone {}: unable to reach the initializer: {e}", opts.id);
+                std::process::exit(1);
             }
         }
+        Ok(Command::Scenario(opts)) => {
+            let outcome = run_scenario(&opts.scenario, opts.timeout);
+            // the nodes are still running, they go away with the process
+            match serde_json::to_string(&outcome) {
+                Ok(line) => println!("{line}"),
+                Err(e) => error!("{e}"),
+            }
+        }
+        Ok(Command::BenchCase(opts)) => {
+            let implementations: Implementations = Implementations::Single(opts.implementation);
+            match run_bench_case(&opts.bench, implementations, opts.pdr) {
+                // the nodes are still running, they go away with the process
+                Ok(result) => match serde_json::to_string(&result) {
+                    Ok(line) => println!("{line}"),
+                    Err(e) => error!("{e}"),
+                },
+                Err(e) => {
+                    error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        // like the usage errors of most tools
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(2);
+        }
     }
-
-    simulation_controller::run(
-        scl_drones_channels,
-        scl_web_clients_channels,
-        scl_chat_clients_channels,
-        scl_servers_channels,
-        drone.clone(),
-        client.clone(),
-        server.clone(),
-    );
 }
@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use common::slc_commands::{
+    ChatClientCommand, ChatClientEvent, ServerType, TextMediaResponse, WebClientCommand,
+    WebClientEvent,
+};
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use wg_2024::{config::Config, network::NodeId};
+
+use crate::{
+    network::{drone_factory, server_implementation, spawn_network, Network},
+    random::{assign_implementations, rng_for, Assignment},
+    router::{NodeEvent, ShortcutRouter},
+    topology_utils::check_topology_constraints,
+};
+
+/// topologies with a web client, a text server, two chat clients and a chat
+/// server under the default factories
+pub(super) const DEFAULT_TOPOLOGIES: [&str; 4] = [
+    "config/double_chain.toml",
+    "config/tree.toml",
+    "config/butterfly.toml",
+    "config/star.toml",
+];
+/// PDR given to every drone
+pub(super) const DEFAULT_PDRS: [f32; 3] = [0., 0.2, 0.5];
+/// time given to the clients to discover the topology
+pub(super) const FLOOD_DELAY: Duration = Duration::from_secs(1);
+/// what a chat client sends to the other
+const CHAT_MESSAGE: &str = "hello from the matrix";
+
+/// what goes through the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Content {
+    /// plain html page asked by a web client
+    Text,
+    /// html page referencing the files of `media/`, asked by a web client
+    Media,
+    /// message between two chat clients, through a chat server
+    Chat,
+}
+
+impl Content {
+    pub(super) const ALL: [Content; 3] = [Content::Text, Content::Media, Content::Chat];
+
+    fn file(self) -> Option<&'static str> {
+        match self {
+            Content::Text => Some("./public/file.html"),
+            Content::Media => Some("./public/three.html"),
+            Content::Chat => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Content::Text => "text",
+            Content::Media => "media",
+            Content::Chat => "chat",
+        }
+    }
+
+    /// checks the received response, returning what's wrong with it
+    fn check(self, r: &TextMediaResponse) -> Result<(), String> {
+        let file: &str = self.file().ok_or("not a file")?;
+        let expected: Vec<u8> = fs::read(file).map_err(|e| e.to_string())?;
+        if r.get_html_file().1 != expected {
+            return Err("html file differs".to_owned());
+        }
+        match (self, r.get_media_files().is_empty()) {
+            (Content::Text, false) => Err("unexpected media files".to_owned()),
+            (Content::Media, true) => Err("media files missing".to_owned()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Content {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Content::ALL
+            .into_iter()
+            .find(|c| c.as_str() == s)
+            .ok_or_else(|| format!("Unknown content: {s}"))
+    }
+}
+
+/// drone implementations of a scenario
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Implementations {
+    /// every drone runs the implementation at this index of the factory
+    Single(usize),
+    /// every drone gets a random implementation, driven by the seed
+    Mixed(u64),
+}
+
+impl fmt::Display for Implementations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Implementations::Single(i) => f.write_str(drone_factory()[*i].0),
+            Implementations::Mixed(seed) => write!(f, "mixed (seed {seed})"),
+        }
+    }
+}
+
+/// a single cell of the matrix
+#[derive(Debug, Clone)]
+pub(super) struct Scenario {
+    pub(super) implementations: Implementations,
+    pub(super) topology: PathBuf,
+    pub(super) pdr: f32,
+    pub(super) content: Content,
+}
+
+impl Scenario {
+    /// arguments of the `scenario` subcommand running it
+    pub(super) fn args(&self) -> Vec<String> {
+        let implementations: [String; 2] = match self.implementations {
+            Implementations::Single(i) => ["--implementation".to_owned(), i.to_string()],
+            Implementations::Mixed(seed) => ["--mixed".to_owned(), seed.to_string()],
+        };
+        let mut args: Vec<String> = vec!["scenario".to_owned()];
+        args.extend(implementations);
+        args.extend([
+            "--topology".to_owned(),
+            self.topology.display().to_string(),
+            "--pdr".to_owned(),
+            self.pdr.to_string(),
+            "--content".to_owned(),
+            self.content.to_string(),
+        ]);
+        args
+    }
+}
+
+/// options of the hidden `scenario` subcommand, running one cell of the
+/// matrix in a process of its own
+#[derive(Debug)]
+pub(super) struct ScenarioOptions {
+    pub(super) scenario: Scenario,
+    pub(super) timeout: Duration,
+}
+
+/// how a scenario ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Outcome {
+    /// content received and checked, with the time elapsed since the request
+    Pass(Duration),
+    Fail(String),
+    Timeout,
+}
+
+/// options of the `matrix` subcommand
+#[derive(Debug)]
+pub(super) struct MatrixOptions {
+    pub(super) topologies: Vec<PathBuf>,
+    pub(super) pdrs: Vec<f32>,
+    /// indexes in the drone factory, every implementation if empty
+    pub(super) implementations: Vec<usize>,
+    /// seeds of the mixed-implementation scenarios
+    pub(super) mixed: Vec<u64>,
+    pub(super) timeout: Duration,
+}
+
+impl Default for MatrixOptions {
+    fn default() -> Self {
+        Self {
+            topologies: DEFAULT_TOPOLOGIES.iter().map(PathBuf::from).collect(),
+            pdrs: DEFAULT_PDRS.to_vec(),
+            implementations: Vec::new(),
+            mixed: vec![0],
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl MatrixOptions {
+    /// every combination of the options
+    pub(super) fn scenarios(&self) -> Vec<Scenario> {
+        let implementations: Vec<Implementations> = if self.implementations.is_empty() {
+            (0..drone_factory().len())
+                .map(Implementations::Single)
+                .collect()
+        } else {
+            self.implementations
+                .iter()
+                .map(|&i| Implementations::Single(i))
+                .collect()
+        };
+        let mut scenarios: Vec<Scenario> = Vec::new();
+        for i in implementations
+            .into_iter()
+            .chain(self.mixed.iter().map(|&s| Implementations::Mixed(s)))
+        {
+            for topology in &self.topologies {
+                for &pdr in &self.pdrs {
+                    for content in Content::ALL {
+                        scenarios.push(Scenario {
+                            implementations: i,
+                            topology: topology.clone(),
+                            pdr,
+                            content,
+                        });
+                    }
+                }
+            }
+        }
+        scenarios
+    }
+}
+
+/// config of `topology` with every drone at `pdr`, and the drone
+/// implementations to spawn
+pub(super) fn build(
+    topology: &Path,
+    pdr: f32,
+    implementations: Implementations,
+) -> Result<(Config, HashMap<NodeId, usize>), String> {
+    let data: String = fs::read_to_string(topology).map_err(|e| e.to_string())?;
+    let mut config: Config = toml::from_str(&data).map_err(|e| e.to_string())?;
+    for d in &mut config.drone {
+        d.pdr = pdr;
+    }
+    if !check_topology_constraints(&config.drone, &config.client, &config.server) {
+        return Err("inconsistent topology".to_owned());
+    }
+    let implementations: HashMap<NodeId, usize> = match implementations {
+        Implementations::Single(i) => config.drone.iter().map(|d| (d.id, i)).collect(),
+        Implementations::Mixed(seed) => assign_implementations(
+            &config.drone,
+            drone_factory().len(),
+            Assignment::Random,
+            &mut rng_for(seed, "implementations"),
+        ),
+    };
+    Ok((config, implementations))
+}
+
+/// asks the first web client for the file of the scenario and waits for it
+fn request_file(network: &Network, content: Content, file: &str, timeout: Duration) -> Outcome {
+    let Some(&client) = network.web_clients.keys().min() else {
+        return Outcome::Fail("no web client".to_owned());
+    };
+    let commands: &Sender<WebClientCommand> = &network.web_clients[&client].0;
+    let router = ShortcutRouter::from_network(network);
+    // give the clients some time to discover the topology
+    router.wait_for(|_| false, FLOOD_DELAY);
+
+    let mut requested: Option<Instant> = None;
+    let mut failure: Option<String> = None;
+    let _ = commands.send(WebClientCommand::AskServersTypes);
+    let event: Option<NodeEvent> = router.wait_for(
+        |e: &NodeEvent| {
+            let NodeEvent::WebClient(id, e) = e else {
+                return false;
+            };
+            match e {
+                _ if *id != client => false,
+                WebClientEvent::ServersTypes(types) if requested.is_none() => {
+                    let server: Option<NodeId> = types
+                        .iter()
+                        .filter(|(_, t)| matches!(t, ServerType::FileServer))
+                        .map(|(id, _)| *id)
+                        .min();
+                    let Some(server) = server else {
+                        failure = Some("no text server found".to_owned());
+                        return true;
+                    };
+                    requested = Some(Instant::now());
+                    let _ = commands.send(WebClientCommand::RequestFile(file.to_owned(), server));
+                    false
+                }
+                WebClientEvent::FileFromClient(..) => true,
+                WebClientEvent::UnsupportedRequest => {
+                    failure = Some("unsupported request".to_owned());
+                    true
+                }
+                _ => false,
+            }
+        },
+        timeout,
+    );
+    if let Some(f) = failure {
+        return Outcome::Fail(f);
+    }
+    match event {
+        Some(NodeEvent::WebClient(_, WebClientEvent::FileFromClient(r, _))) => {
+            let elapsed: Duration = requested.map_or(Duration::ZERO, |t| t.elapsed());
+            match content.check(&r) {
+                Ok(()) => Outcome::Pass(elapsed),
+                Err(e) => Outcome::Fail(e),
+            }
+        }
+        _ => Outcome::Timeout,
+    }
+}
+
+/// registers the first two chat clients to the first chat server, sends a
+/// message from one to the other and waits for it
+fn send_message(network: &Network, timeout: Duration) -> Outcome {
+    let mut clients: Vec<NodeId> = network.chat_clients.keys().copied().collect();
+    clients.sort_unstable();
+    let &[from, to, ..] = clients.as_slice() else {
+        return Outcome::Fail("less than two chat clients".to_owned());
+    };
+    let server: Option<NodeId> = network
+        .servers
+        .keys()
+        .copied()
+        .filter(|id| server_implementation(*id) == "chat_server")
+        .min();
+    let Some(server) = server else {
+        return Outcome::Fail("no chat server".to_owned());
+    };
+    let commands = |id: NodeId| -> &Sender<ChatClientCommand> { &network.chat_clients[&id].0 };
+    let router = ShortcutRouter::from_network(network);
+    router.wait_for(|_| false, FLOOD_DELAY);
+
+    for c in [from, to] {
+        let _ = commands(c).send(ChatClientCommand::RegisterToServer(server));
+    }
+    // the registrations have no reply, leave them the time to get there
+    router.wait_for(|_| false, FLOOD_DELAY);
+    let sent: Instant = Instant::now();
+    let _ = commands(from).send(ChatClientCommand::SendMessage(
+        server,
+        to,
+        CHAT_MESSAGE.to_owned(),
+    ));
+    let event: Option<NodeEvent> = router.wait_for(
+        |e: &NodeEvent| {
+            matches!(e, NodeEvent::ChatClient(id, ChatClientEvent::MessageReceived(..)) if *id == to)
+        },
+        timeout,
+    );
+    match event {
+        Some(_) => Outcome::Pass(sent.elapsed()),
+        None => Outcome::Timeout,
+    }
+}
+
+/// builds the network of `s` and runs it; its nodes keep running
+/// afterwards, see [`run_isolated`]
+pub(super) fn run_scenario(s: &Scenario, timeout: Duration) -> Outcome {
+    let (config, implementations) = match build(&s.topology, s.pdr, s.implementations) {
+        Ok(b) => b,
+        Err(e) => return Outcome::Fail(e),
+    };
+    let network: Network = spawn_network(&config, &implementations, None);
+    match s.content.file() {
+        Some(file) => request_file(&network, s.content, file, timeout),
+        None => send_message(&network, timeout),
+    }
+}
+
+/// runs `s` in a child process, so that its nodes are gone once it's done
+pub(super) fn run_isolated(s: &Scenario, timeout: Duration) -> Outcome {
+    let output = env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .args(s.args())
+            .args(["--timeout", &timeout.as_secs_f64().to_string()])
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+    });
+    let stdout: String = match output {
+        Ok(o) => String::from_utf8_lossy(&o.stdout).into_owned(),
+        Err(e) => return Outcome::Fail(format!("unable to run the scenario: {e}")),
+    };
+    stdout
+        .lines()
+        .last()
+        .and_then(|l| serde_json::from_str(l).ok())
+        .unwrap_or_else(|| Outcome::Fail("the scenario process failed".to_owned()))
+}
+
+/// runs every scenario through `run`, printing a row of the table as soon
+/// as it's done; returns how many scenarios passed
+pub(super) fn run_matrix(
+    opts: &MatrixOptions,
+    run: impl Fn(&Scenario, Duration) -> Outcome,
+) -> (usize, usize) {
+    let scenarios: Vec<Scenario> = opts.scenarios();
+    println!(
+        "{:<24} {:<28} {:>5} {:<7} {:<8} latency",
+        "implementation", "topology", "pdr", "content", "result"
+    );
+    let mut passed: usize = 0;
+    for s in &scenarios {
+        let outcome: Outcome = run(s, opts.timeout);
+        let (result, detail): (&str, String) = match &outcome {
+            Outcome::Pass(d) => {
+                passed += 1;
+                ("pass", format!("{:.3}s", d.as_secs_f64()))
+            }
+            Outcome::Fail(e) => ("fail", e.clone()),
+            Outcome::Timeout => ("timeout", "-".to_owned()),
+        };
+        println!(
+            "{:<24} {:<28} {:>5.2} {:<7} {:<8} {}",
+            s.implementations.to_string(),
+            s.topology.display(),
+            s.pdr,
+            s.content,
+            result,
+            detail
+        );
+    }
+    println!("{passed}/{} scenarios passed", scenarios.len());
+    (passed, scenarios.len())
+}
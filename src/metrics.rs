@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use common::slc_commands::{ChatClientEvent, ServerEvent, WebClientEvent};
+use crossbeam_channel::Receiver;
+use itertools::chain;
+use log::{info, warn};
+use serde::Serialize;
+use wg_2024::{
+    controller::DroneEvent,
+    network::NodeId,
+    packet::{Packet, PacketType},
+};
+
+use crate::{
+    network::{Network, NodeKind},
+    tap::{TapRecord, TapSink},
+};
+
+/// counters of a single node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(super) struct NodeMetrics {
+    pub(super) packets_sent: u64,
+    /// only counted when the packet tap is enabled
+    pub(super) packets_received: u64,
+    pub(super) packets_dropped: u64,
+    pub(super) shortcuts: u64,
+    pub(super) nacks_sent: u64,
+    pub(super) files_delivered: u64,
+    pub(super) chat_messages: u64,
+    pub(super) events: u64,
+}
+
+impl NodeMetrics {
+    /// updates the counters with an event sent by the node
+    pub(super) fn count<E: CountedEvent>(&mut self, event: &E) {
+        self.events += 1;
+        event.count(self);
+    }
+
+    fn sent(&mut self, p: &Packet) {
+        self.packets_sent += 1;
+        if let PacketType::Nack(_) = p.pack_type {
+            self.nacks_sent += 1;
+        }
+    }
+}
+
+/// events sent to the controller, with the counters each of them updates
+pub(super) trait CountedEvent {
+    fn count(&self, m: &mut NodeMetrics);
+}
+
+impl CountedEvent for DroneEvent {
+    fn count(&self, m: &mut NodeMetrics) {
+        match self {
+            DroneEvent::PacketSent(p) => m.sent(p),
+            DroneEvent::PacketDropped(_) => m.packets_dropped += 1,
+            DroneEvent::ControllerShortcut(_) => m.shortcuts += 1,
+        }
+    }
+}
+
+impl CountedEvent for WebClientEvent {
+    fn count(&self, m: &mut NodeMetrics) {
+        match self {
+            WebClientEvent::PacketSent(p) => m.sent(p),
+            WebClientEvent::Shortcut(_) => m.shortcuts += 1,
+            WebClientEvent::FileFromClient(..) => m.files_delivered += 1,
+            _ => {}
+        }
+    }
+}
+
+impl CountedEvent for ChatClientEvent {
+    fn count(&self, m: &mut NodeMetrics) {
+        match self {
+            ChatClientEvent::PacketSent(p) => m.sent(p),
+            ChatClientEvent::Shortcut(_) => m.shortcuts += 1,
+            ChatClientEvent::MessageReceived(..) => m.chat_messages += 1,
+            _ => {}
+        }
+    }
+}
+
+impl CountedEvent for ServerEvent {
+    fn count(&self, m: &mut NodeMetrics) {
+        match self {
+            ServerEvent::PacketSent(p) => m.sent(p),
+            ServerEvent::Shortcut(_) => m.shortcuts += 1,
+        }
+    }
+}
+
+/// time a scraper gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// name, help and getter of every exported counter
+const COUNTERS: [(&str, &str, fn(&NodeMetrics) -> u64); 8] = [
+    ("wg_packets_sent_total", "Packets sent by the node", |m| {
+        m.packets_sent
+    }),
+    (
+        "wg_packets_received_total",
+        "Packets delivered to the node (packet tap only)",
+        |m| m.packets_received,
+    ),
+    ("wg_packets_dropped_total", "PacketDropped events", |m| {
+        m.packets_dropped
+    }),
+    (
+        "wg_shortcuts_total",
+        "Packets shortcut through the controller",
+        |m| m.shortcuts,
+    ),
+    ("wg_nacks_sent_total", "NACKs sent by the node", |m| {
+        m.nacks_sent
+    }),
+    (
+        "wg_files_delivered_total",
+        "Files delivered to the client",
+        |m| m.files_delivered,
+    ),
+    ("wg_chat_messages_total", "Chat messages received", |m| {
+        m.chat_messages
+    }),
+    ("wg_events_total", "Events sent to the controller", |m| {
+        m.events
+    }),
+];
+
+/// counters of every node of the network, shared between the threads
+/// that observe it
+#[derive(Debug, Default)]
+pub(super) struct Metrics {
+    nodes: Mutex<BTreeMap<NodeId, (NodeKind, NodeMetrics)>>,
+}
+
+impl Metrics {
+    /// adds every node of `network` with empty counters
+    pub(super) fn register(&self, network: &Network) {
+        let mut nodes = self.nodes.lock().unwrap_or_else(PoisonError::into_inner);
+        let ids = chain![
+            network.drones.keys().map(|id| (*id, NodeKind::Drone)),
+            network
+                .web_clients
+                .keys()
+                .map(|id| (*id, NodeKind::WebClient)),
+            network
+                .chat_clients
+                .keys()
+                .map(|id| (*id, NodeKind::ChatClient)),
+            network.servers.keys().map(|id| (*id, NodeKind::Server)),
+        ];
+        for (id, kind) in ids {
+            nodes.entry(id).or_insert((kind, NodeMetrics::default()));
+        }
+    }
+
+    fn update(&self, node: NodeId, kind: NodeKind, f: impl FnOnce(&mut NodeMetrics)) {
+        let mut nodes = self.nodes.lock().unwrap_or_else(PoisonError::into_inner);
+        let (k, m) = nodes
+            .entry(node)
+            .or_insert_with(|| (kind, NodeMetrics::default()));
+        *k = kind;
+        f(m);
+    }
+
+    /// counters of `node`, if it has been seen
+    pub(super) fn get(&self, node: NodeId) -> Option<NodeMetrics> {
+        let nodes = self.nodes.lock().unwrap_or_else(PoisonError::into_inner);
+        nodes.get(&node).map(|(_, m)| *m)
+    }
+
+    /// copy of every counter
+    pub(super) fn snapshot(&self) -> BTreeMap<NodeId, (NodeKind, NodeMetrics)> {
+        self.nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Prometheus text exposition format
+    pub(super) fn render(&self) -> String {
+        let nodes = self.snapshot();
+        let mut out = String::new();
+        for (name, help, get) in COUNTERS {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (id, (kind, m)) in &nodes {
+                let _ = writeln!(
+                    out,
+                    "{name}{{node=\"{id}\",kind=\"{}\"}} {}",
+                    kind.as_str(),
+                    get(m)
+                );
+            }
+        }
+        out
+    }
+
+    /// returns a receiver getting the same events as `events`, after they
+    /// have been counted
+    pub(super) fn wrap<E: CountedEvent + Send + 'static>(
+        self: &Arc<Self>,
+        node: NodeId,
+        kind: NodeKind,
+        events: Receiver<E>,
+    ) -> Receiver<E> {
+        let (forward, wrapped) = crossbeam_channel::unbounded();
+        let metrics: Arc<Metrics> = Arc::clone(self);
+        thread::spawn(move || {
+            for e in events {
+                metrics.update(node, kind, |m| m.count(&e));
+                if forward.send(e).is_err() {
+                    break;
+                }
+            }
+        });
+        wrapped
+    }
+
+    /// counts the events of every node of `network`
+    pub(super) fn wrap_network(self: &Arc<Self>, network: Network) -> Network {
+        fn wrap_all<C, E: CountedEvent + Send + 'static, P, R>(
+            metrics: &Arc<Metrics>,
+            kind: NodeKind,
+            channels: HashMap<NodeId, (C, Receiver<E>, P, R)>,
+        ) -> HashMap<NodeId, (C, Receiver<E>, P, R)> {
+            channels
+                .into_iter()
+                .map(|(id, (cmd, events, send, recv))| {
+                    (id, (cmd, metrics.wrap(id, kind, events), send, recv))
+                })
+                .collect()
+        }
+
+        Network {
+            drones: wrap_all(self, NodeKind::Drone, network.drones),
+            web_clients: wrap_all(self, NodeKind::WebClient, network.web_clients),
+            chat_clients: wrap_all(self, NodeKind::ChatClient, network.chat_clients),
+            servers: wrap_all(self, NodeKind::Server, network.servers),
+        }
+    }
+}
+
+/// tap sink counting the packets received by every node
+pub(super) struct MetricsSink(pub(super) Arc<Metrics>);
+
+impl TapSink for MetricsSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        let mut nodes = self.0.nodes.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, m)) = nodes.get_mut(&record.to) {
+            m.packets_received += 1;
+        }
+        Ok(())
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", "text/plain; version=0.0.4", metrics.render()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// serves `/metrics` on 127.0.0.1:`port`, returns the port actually bound
+pub(super) fn serve(metrics: Arc<Metrics>, port: u16) -> io::Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let port: u16 = listener.local_addr()?.port();
+    info!("Metrics available on http://127.0.0.1:{port}/metrics");
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // one thread per scraper, an idle one can't hold up the others
+            let metrics: Arc<Metrics> = Arc::clone(&metrics);
+            thread::spawn(move || {
+                if let Err(e) = respond(stream, &metrics) {
+                    warn!("Metrics request failed: {e}");
+                }
+            });
+        }
+    });
+    Ok(port)
+}
+
+/// logs every counter each `interval`
+pub(super) fn dump_periodically(metrics: Arc<Metrics>, interval: Duration) {
+    thread::spawn(move || loop {
+        sleep(interval);
+        info!(target: "metrics", "\n{}", metrics.render());
+    });
+}
@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::thread::JoinHandle;
+
+use ap2024_rustinpeace_nosounddrone::NoSoundDroneRIP;
+use ap2024_unitn_cppenjoyers_webservers::{MediaServer, TextServer};
+use chat_server_client::client::ChatClient;
+use chat_server_client::server::ChatServer;
+use common::slc_commands::{
+    ChatClientCommand, ChatClientEvent, ServerCommand, ServerEvent, WebClientCommand,
+    WebClientEvent,
+};
+use common::{Client as ClientTrait, Server as ServerTrait};
+use crossbeam_channel::{Receiver, Sender};
+use dr_ones::Drone as DrDrone;
+use drone_bettercalldrone::BetterCallDrone;
+use getdroned::GetDroned;
+use itertools::chain;
+use log::error;
+use rolling_drone::RollingDrone;
+use rust_do_it::RustDoIt;
+use rust_roveri::RustRoveri;
+use rustafarian_drone::RustafarianDrone;
+use rusteze_drone::RustezeDrone;
+use rusty_drones::RustyDrone;
+use serde::{Deserialize, Serialize};
+use web_client::web_client::WebBrowser;
+use wg_2024::config::{Client, Config, Drone, Server};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone as DroneTrait;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+use crate::factories::{ClientFuncs, DroneFn};
+use crate::random::{assign_implementations, randomize_pdr, rng_for, Assignment};
+use crate::respawn::Respawner;
+use crate::router::ShortcutRouter;
+use crate::supervisor::{HostedDrone, Supervisor};
+use crate::tap::Tap;
+use crate::topology_utils::check_topology_constraints;
+use crate::{create_boxed_drone, create_boxed_server};
+
+pub(super) type DroneChannels = HashMap<
+    NodeId,
+    (
+        Sender<DroneCommand>,
+        Receiver<DroneEvent>,
+        Sender<Packet>,
+        Receiver<Packet>,
+    ),
+>;
+pub(super) type WebClientChannels = HashMap<
+    NodeId,
+    (
+        Sender<WebClientCommand>,
+        Receiver<WebClientEvent>,
+        Sender<Packet>,
+        Receiver<Packet>,
+    ),
+>;
+pub(super) type ChatClientChannels = HashMap<
+    NodeId,
+    (
+        Sender<ChatClientCommand>,
+        Receiver<ChatClientEvent>,
+        Sender<Packet>,
+        Receiver<Packet>,
+    ),
+>;
+pub(super) type ServerChannels = HashMap<
+    NodeId,
+    (
+        Sender<ServerCommand>,
+        Receiver<ServerEvent>,
+        Sender<Packet>,
+        Receiver<Packet>,
+    ),
+>;
+
+/// kind of a node, as seen by the simulation controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum NodeKind {
+    Drone,
+    WebClient,
+    ChatClient,
+    Server,
+}
+
+impl NodeKind {
+    pub(super) const ALL: [NodeKind; 4] = [
+        NodeKind::Drone,
+        NodeKind::WebClient,
+        NodeKind::ChatClient,
+        NodeKind::Server,
+    ];
+
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            NodeKind::Drone => "drone",
+            NodeKind::WebClient => "web_client",
+            NodeKind::ChatClient => "chat_client",
+            NodeKind::Server => "server",
+        }
+    }
+}
+
+/// channels of every spawned node, as expected by the simulation controller
+pub(super) struct Network {
+    pub(super) drones: DroneChannels,
+    pub(super) web_clients: WebClientChannels,
+    pub(super) chat_clients: ChatClientChannels,
+    pub(super) servers: ServerChannels,
+}
+
+/// everything needed to build the same network again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Setup {
+    /// TOML of the topology
+    pub(super) config: String,
+    pub(super) seed: u64,
+    pub(super) assignment: Assignment,
+    /// overrides the PDRs of the config with random ones below this value
+    pub(super) random_pdr: Option<f32>,
+}
+
+impl Setup {
+    /// parses the topology and applies the seeded choices; returns the
+    /// config and the implementation of every drone, `None` if the topology
+    /// is inconsistent
+    pub(super) fn prepare(&self) -> Option<(Config, HashMap<NodeId, usize>)> {
+        // having our structs implement the Deserialize trait allows us to use the toml::from_str function to deserialize the config file into each of them
+        let mut config: Config = toml::from_str(&self.config).expect("Unable to parse TOML");
+        if let Some(max) = self.random_pdr {
+            randomize_pdr(&mut config.drone, max, &mut rng_for(self.seed, "pdr"));
+        }
+        let implementations: HashMap<NodeId, usize> = assign_implementations(
+            &config.drone,
+            drone_factory().len(),
+            self.assignment,
+            &mut rng_for(self.seed, "implementations"),
+        );
+
+        // check topology constraints
+        if !check_topology_constraints(&config.drone, &config.client, &config.server) {
+            error!("Inconsistent topology");
+            return None;
+        }
+        Some((config, implementations))
+    }
+}
+
+// IDK how I should name it so that's it
+#[allow(clippy::type_complexity)]
+fn create_scl_channels<T, U>(
+    v: &[U],
+    f: fn(&U) -> (NodeId, (Sender<T>, Receiver<T>)),
+) -> HashMap<NodeId, (Sender<T>, Receiver<T>)> {
+    v.iter().map(f).collect()
+}
+
+fn create_channels<'a, T>(
+    drones: &'a [Drone],
+    clients: &'a [Client],
+    servers: &'a [Server],
+) -> impl Iterator<Item = (NodeId, (Sender<T>, Receiver<T>))> + use<'a, T> {
+    chain![
+        drones
+            .iter()
+            .map(|d: &Drone| (d.id, crossbeam_channel::unbounded::<T>())),
+        clients
+            .iter()
+            .map(|c: &Client| (c.id, crossbeam_channel::unbounded::<T>())),
+        servers
+            .iter()
+            .map(|s: &Server| (s.id, crossbeam_channel::unbounded::<T>())),
+    ]
+}
+
+/// names of the server implementations, in the order of the factory
+const SERVER_NAMES: [&str; 3] = ["text_server", "media_server", "chat_server"];
+
+/// name of the implementation spawned for the server `id`
+pub(super) fn server_implementation(id: NodeId) -> &'static str {
+    SERVER_NAMES[usize::from(id) % SERVER_NAMES.len()]
+}
+
+/// kind of the client spawned for `id`, in the order of the client factory
+pub(super) fn client_kind(id: NodeId) -> NodeKind {
+    if id % 2 == 0 {
+        NodeKind::WebClient
+    } else {
+        NodeKind::ChatClient
+    }
+}
+
+/// every drone implementation we can spawn, with a human readable name
+pub(super) fn drone_factory() -> [(&'static str, DroneFn); 10] {
+    [
+        ("dr_ones", create_boxed_drone!(DrDrone)),
+        ("rust_do_it", create_boxed_drone!(RustDoIt)),
+        ("rust_roveri", create_boxed_drone!(RustRoveri)),
+        ("rolling_drone", create_boxed_drone!(RollingDrone)),
+        ("rustafarian", create_boxed_drone!(RustafarianDrone)),
+        ("rusteze", create_boxed_drone!(RustezeDrone)),
+        ("rusty_drones", create_boxed_drone!(RustyDrone)),
+        ("getdroned", create_boxed_drone!(GetDroned)),
+        ("no_sound_drone", create_boxed_drone!(NoSoundDroneRIP)),
+        ("bettercalldrone", create_boxed_drone!(BetterCallDrone)),
+    ]
+}
+
+/// builds the neighbour map of node `id`, wrapping every link
+/// in the tap (if any)
+pub(super) fn neighbours(
+    id: NodeId,
+    connected: &[NodeId],
+    channels: &HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
+    tap: Option<&Tap>,
+) -> HashMap<NodeId, Sender<Packet>> {
+    connected
+        .iter()
+        .map(|nbr: &NodeId| {
+            let sender: Sender<Packet> = channels[nbr].0.clone();
+            match tap {
+                Some(tap) => (*nbr, tap.link(id, *nbr, sender)),
+                None => (*nbr, sender),
+            }
+        })
+        .collect()
+}
+
+/// name of the thread running node `id`, log lines are routed by it
+pub(super) fn node_thread_name(kind: NodeKind, id: NodeId) -> String {
+    format!("{}-{id}", kind.as_str())
+}
+
+/// runs a node on a thread named after it, inside a span carrying its id,
+/// kind and implementation
+pub(super) fn spawn_node(
+    kind: NodeKind,
+    id: NodeId,
+    implementation: &str,
+    run: impl FnOnce() + Send + 'static,
+) -> Option<JoinHandle<()>> {
+    let span = tracing::info_span!("node", id, kind = kind.as_str(), implementation);
+    std::thread::Builder::new()
+        .name(node_thread_name(kind, id))
+        .spawn(move || span.in_scope(run))
+        .map_err(|e| error!("Unable to spawn {} {id}: {e}", kind.as_str()))
+        .ok()
+}
+
+/// where the drones of a network run
+#[derive(Clone, Copy)]
+pub(super) enum DroneHost<'a> {
+    /// a thread of this process each
+    Threads,
+    /// a child process each, bridged by the supervisor; clients and servers
+    /// run on threads either way
+    Processes(&'a Supervisor),
+}
+
+/// spawns every node described by `config` on its own thread and returns
+/// the channels needed to control them, `implementations` maps every drone
+/// to its index in [`drone_factory`]
+pub(super) fn spawn_network(
+    config: &Config,
+    implementations: &HashMap<NodeId, usize>,
+    tap: Option<&Tap>,
+) -> Network {
+    spawn_network_on(config, implementations, tap, DroneHost::Threads).0
+}
+
+/// same as [`spawn_network`], with the drones running on `host`; the
+/// respawner can bring back the drones running on threads
+// It's fair to have a longer function here... most of the lines are just
+// constructors after the "cargo fmt" command
+#[allow(clippy::too_many_lines)]
+pub(super) fn spawn_network_on(
+    config: &Config,
+    implementations: &HashMap<NodeId, usize>,
+    tap: Option<&Tap>,
+    host: DroneHost,
+) -> (Network, Respawner) {
+    let Config {
+        drone,
+        client,
+        server,
+    } = config;
+
+    let drone_factory = drone_factory();
+    let client_factory = [
+        ClientFuncs::WebFn(WebBrowser::new),
+        ClientFuncs::ChatFn(ChatClient::new),
+    ];
+    let server_factory = [
+        create_boxed_server!(TextServer),
+        create_boxed_server!(MediaServer),
+        create_boxed_server!(ChatServer),
+    ];
+
+    let mut scl_drones_channels: DroneChannels = HashMap::new();
+    let mut scl_web_clients_channels: WebClientChannels = HashMap::new();
+    let mut scl_chat_clients_channels: ChatClientChannels = HashMap::new();
+    let mut scl_servers_channels: ServerChannels = HashMap::new();
+
+    let scl_events: HashMap<NodeId, (Sender<DroneEvent>, Receiver<DroneEvent>)> =
+        create_scl_channels(drone, |d| (d.id, crossbeam_channel::unbounded()));
+    let scl_commands: HashMap<NodeId, (Sender<DroneCommand>, Receiver<DroneCommand>)> =
+        create_scl_channels(drone, |d| (d.id, crossbeam_channel::unbounded()));
+    let scl_server_events: HashMap<NodeId, (Sender<ServerEvent>, Receiver<ServerEvent>)> =
+        create_scl_channels(server, |s| (s.id, crossbeam_channel::unbounded()));
+    let scl_server_commands: HashMap<NodeId, (Sender<ServerCommand>, Receiver<ServerCommand>)> =
+        create_scl_channels(server, |s| (s.id, crossbeam_channel::unbounded()));
+    let channels: HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)> =
+        create_channels(drone, client, server).collect();
+    let respawner = Respawner::new(channels.clone(), tap);
+
+    // drones and servers spawn (trivial)
+    for d in drone {
+        let nbrs: HashMap<NodeId, Sender<Packet>> =
+            neighbours(d.id, &d.connected_node_ids, &channels, tap);
+        if let DroneHost::Processes(supervisor) = host {
+            supervisor.host(HostedDrone {
+                id: d.id,
+                implementation: implementations[&d.id],
+                pdr: d.pdr,
+                neighbours: nbrs,
+                events: scl_events[&d.id].0.clone(),
+                commands: scl_commands[&d.id].1.clone(),
+                packets: channels[&d.id].1.clone(),
+            });
+            continue;
+        }
+        let (name, factory) = drone_factory[implementations[&d.id] % drone_factory.len()];
+        let mut new_drone: Box<dyn DroneTrait> = factory(
+            d.id,
+            scl_events[&d.id].0.clone(),
+            scl_commands[&d.id].1.clone(),
+            channels[&d.id].1.clone(),
+            nbrs,
+            d.pdr,
+        );
+        let handle = spawn_node(NodeKind::Drone, d.id, name, move || new_drone.run());
+        respawner.track(
+            d,
+            implementations[&d.id],
+            scl_events[&d.id].0.clone(),
+            scl_commands[&d.id].1.clone(),
+            handle,
+        );
+    }
+    for s in server {
+        let nbrs: HashMap<NodeId, Sender<Packet>> =
+            neighbours(s.id, &s.connected_drone_ids, &channels, tap);
+        let mut new_server: Box<dyn ServerTrait> = server_factory
+            [usize::from(s.id) % server_factory.len()](
+            s.id,
+            scl_server_events[&s.id].0.clone(),
+            scl_server_commands[&s.id].1.clone(),
+            channels[&s.id].1.clone(),
+            nbrs,
+        );
+        spawn_node(
+            NodeKind::Server,
+            s.id,
+            server_implementation(s.id),
+            move || new_server.run(),
+        );
+    }
+
+    for d in drone {
+        scl_drones_channels.insert(
+            d.id,
+            (
+                scl_commands[&d.id].0.clone(),
+                scl_events[&d.id].1.clone(),
+                channels[&d.id].0.clone(),
+                channels[&d.id].1.clone(),
+            ),
+        );
+    }
+    for s in server {
+        scl_servers_channels.insert(
+            s.id,
+            (
+                scl_server_commands[&s.id].0.clone(),
+                scl_server_events[&s.id].1.clone(),
+                channels[&s.id].0.clone(),
+                channels[&s.id].1.clone(),
+            ),
+        );
+    }
+
+    // clients spawn
+    for c in client {
+        let nbrs: HashMap<NodeId, Sender<Packet>> =
+            neighbours(c.id, &c.connected_drone_ids, &channels, tap);
+        match client_factory[usize::from(c.id) % client_factory.len()] {
+            ClientFuncs::WebFn(f) => {
+                let (c1, c2) = crossbeam_channel::unbounded();
+                let (c3, c4) = crossbeam_channel::unbounded();
+                let mut new_client = f(
+                    c.id,
+                    c1.clone(),
+                    c4.clone(),
+                    channels[&c.id].1.clone(),
+                    nbrs,
+                );
+                scl_web_clients_channels.insert(
+                    c.id,
+                    (
+                        c3.clone(),
+                        c2.clone(),
+                        channels[&c.id].0.clone(),
+                        channels[&c.id].1.clone(),
+                    ),
+                );
+                spawn_node(NodeKind::WebClient, c.id, "web_browser", move || {
+                    new_client.run()
+                });
+            }
+            ClientFuncs::ChatFn(f) => {
+                let (c1, c2) = crossbeam_channel::unbounded();
+                let (c3, c4) = crossbeam_channel::unbounded();
+                let mut new_client = f(
+                    c.id,
+                    c1.clone(),
+                    c4.clone(),
+                    channels[&c.id].1.clone(),
+                    nbrs,
+                );
+                scl_chat_clients_channels.insert(
+                    c.id,
+                    (
+                        c3.clone(),
+                        c2.clone(),
+                        channels[&c.id].0.clone(),
+                        channels[&c.id].1.clone(),
+                    ),
+                );
+                spawn_node(NodeKind::ChatClient, c.id, "chat_client", move || {
+                    new_client.run()
+                });
+            }
+        }
+    }
+
+    let network = Network {
+        drones: scl_drones_channels,
+        web_clients: scl_web_clients_channels,
+        chat_clients: scl_chat_clients_channels,
+        servers: scl_servers_channels,
+    };
+    (network, respawner)
+}
+
+/// delivers the controller shortcuts of the drones and drains every other
+/// event, standing in for the controller in headless runs and replays
+pub(super) fn drain_events(network: &Network) -> ShortcutRouter {
+    let router = ShortcutRouter::from_network(network);
+    router.spawn();
+    router
+}
@@ -0,0 +1,218 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use wg_2024::network::NodeId;
+
+use crate::{
+    capture::{encode_packet, CaptureReader},
+    tap::{TapRecord, TapSink},
+};
+
+/// first user-reserved link type, a dissector can be bound to it
+pub(super) const LINKTYPE_USER0: u16 = 147;
+/// raw IPv4/IPv6 packets, no link layer header
+pub(super) const LINKTYPE_RAW: u16 = 101;
+/// UDP port used on both ends when packets are wrapped in UDP
+pub(super) const WG_UDP_PORT: u16 = 2024;
+
+const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const EPB_TYPE: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+
+/// how packets are presented to the pcapng readers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum Encapsulation {
+    /// `from (u8) | to (u8) | packet` on link type USER0
+    #[default]
+    User,
+    /// IPv4 + UDP from 10.0.0.`from` to 10.0.0.`to`, the payload is the packet
+    Udp,
+}
+
+impl FromStr for Encapsulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Self::User),
+            "udp" => Ok(Self::Udp),
+            _ => Err(format!("Unknown encapsulation: {s}")),
+        }
+    }
+}
+
+/// options of the `pcap` subcommand
+#[derive(Debug, Default)]
+pub(super) struct PcapOptions {
+    pub(super) input: PathBuf,
+    pub(super) output: PathBuf,
+    pub(super) encapsulation: Encapsulation,
+}
+
+/// fake address of a node when packets are wrapped in UDP
+pub(super) fn node_ip(id: NodeId) -> [u8; 4] {
+    [10, 0, 0, id]
+}
+
+fn checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|w| u32::from(u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !u16::try_from(sum).unwrap_or(u16::MAX)
+}
+
+/// wraps `payload` in an IPv4 + UDP header, the UDP checksum is left empty
+pub(super) fn udp_frame(from: NodeId, to: NodeId, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let udp_len: u16 = u16::try_from(payload.len() + 8)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "packet too long"))?;
+    let total_len: u16 = udp_len
+        .checked_add(20)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "packet too long"))?;
+    let mut frame: Vec<u8> = Vec::with_capacity(usize::from(total_len));
+    // version 4, IHL 5, no DSCP
+    frame.extend([0x45, 0]);
+    frame.extend(total_len.to_be_bytes());
+    // identification, flags and fragment offset
+    frame.extend([0, 0, 0x40, 0]);
+    // TTL 64, protocol UDP, checksum filled below
+    frame.extend([64, 17, 0, 0]);
+    frame.extend(node_ip(from));
+    frame.extend(node_ip(to));
+    let sum: u16 = checksum(&frame);
+    frame[10..12].copy_from_slice(&sum.to_be_bytes());
+    frame.extend(WG_UDP_PORT.to_be_bytes());
+    frame.extend(WG_UDP_PORT.to_be_bytes());
+    frame.extend(udp_len.to_be_bytes());
+    frame.extend([0, 0]);
+    frame.extend(payload);
+    Ok(frame)
+}
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn block_len(body_len: usize) -> io::Result<u32> {
+    u32::try_from(body_len + 12)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "block too long"))
+}
+
+/// pcapng writer with a single interface
+pub(super) struct PcapWriter<W: Write + Send> {
+    out: W,
+    encapsulation: Encapsulation,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    pub(super) fn create(path: &Path, encapsulation: Encapsulation) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), encapsulation)
+    }
+}
+
+impl<W: Write + Send> PcapWriter<W> {
+    /// writes the section header and the interface description
+    pub(super) fn new(out: W, encapsulation: Encapsulation) -> io::Result<Self> {
+        let mut w = Self { out, encapsulation };
+        let mut shb: Vec<u8> = Vec::new();
+        shb.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend(1u16.to_le_bytes());
+        shb.extend(0u16.to_le_bytes());
+        // unknown section length
+        shb.extend((-1i64).to_le_bytes());
+        w.block(SHB_TYPE, &shb)?;
+
+        let link_type: u16 = match encapsulation {
+            Encapsulation::User => LINKTYPE_USER0,
+            Encapsulation::Udp => LINKTYPE_RAW,
+        };
+        let mut idb: Vec<u8> = Vec::new();
+        idb.extend(link_type.to_le_bytes());
+        idb.extend(0u16.to_le_bytes());
+        // no snapshot length limit
+        idb.extend(0u32.to_le_bytes());
+        w.block(IDB_TYPE, &idb)?;
+        Ok(w)
+    }
+
+    fn block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let len: u32 = block_len(body.len())?;
+        self.out.write_all(&block_type.to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(body)?;
+        self.out.write_all(&len.to_le_bytes())
+    }
+}
+
+impl<W: Write + Send> TapSink for PcapWriter<W> {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        let mut packet: Vec<u8> = Vec::new();
+        encode_packet(&record.packet, &mut packet)?;
+        let data: Vec<u8> = match self.encapsulation {
+            Encapsulation::User => [vec![record.from, record.to], packet].concat(),
+            Encapsulation::Udp => udp_frame(record.from, record.to, &packet)?,
+        };
+        let data_len: u32 = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "packet too long"))?;
+        let comment: String = format!(
+            "{} -> {} {} session={}",
+            record.from,
+            record.to,
+            record.packet_type(),
+            record.session_id()
+        );
+        let comment_len: u16 = u16::try_from(comment.len()).unwrap_or(u16::MAX);
+
+        // timestamps use the default resolution of microseconds
+        let micros: u64 = record.micros();
+        let mut epb: Vec<u8> = Vec::new();
+        epb.extend(0u32.to_le_bytes());
+        epb.extend(
+            u32::try_from(micros >> 32)
+                .unwrap_or(u32::MAX)
+                .to_le_bytes(),
+        );
+        epb.extend(
+            u32::try_from(micros & 0xFFFF_FFFF)
+                .unwrap_or(0)
+                .to_le_bytes(),
+        );
+        epb.extend(data_len.to_le_bytes());
+        epb.extend(data_len.to_le_bytes());
+        epb.extend(&data);
+        epb.resize(epb.len() + padding(data.len()), 0);
+        epb.extend(OPT_COMMENT.to_le_bytes());
+        epb.extend(comment_len.to_le_bytes());
+        epb.extend(&comment.as_bytes()[..usize::from(comment_len)]);
+        epb.resize(epb.len() + padding(usize::from(comment_len)), 0);
+        epb.extend(OPT_END.to_le_bytes());
+        epb.extend(0u16.to_le_bytes());
+        self.block(EPB_TYPE, &epb)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// runs the `pcap` subcommand, converting a capture file to pcapng
+pub(super) fn export(opts: &PcapOptions) -> io::Result<usize> {
+    let mut writer = PcapWriter::create(&opts.output, opts.encapsulation)?;
+    let mut count: usize = 0;
+    for record in CaptureReader::open(&opts.input)? {
+        writer.record(&record?)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use log::info;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use wg_2024::{config::Drone, network::NodeId};
+
+/// random generator used for everything the initializer randomizes
+pub(super) type SimRng = StdRng;
+
+/// returns `seed`, or a fresh one if missing, and logs it so that the run
+/// can be reproduced
+pub(super) fn resolve_seed(seed: Option<u64>) -> u64 {
+    let seed: u64 = seed.unwrap_or_else(|| thread_rng().gen());
+    info!("Simulation seed: {seed} (pass --seed {seed} to reproduce)");
+    seed
+}
+
+/// independent generator for each purpose (`stream`), so that adding a new
+/// random choice doesn't change the outcome of the existing ones
+pub(super) fn rng_for(seed: u64, stream: &str) -> SimRng {
+    // FNV-1a
+    let hash: u64 = stream.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..16].copy_from_slice(&hash.to_le_bytes());
+    SimRng::from_seed(bytes)
+}
+
+/// how drone implementations are assigned to the drones of the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Assignment {
+    /// implementation `id % implementations`
+    #[default]
+    #[serde(rename = "id")]
+    ById,
+    /// uniformly random, driven by the seed
+    Random,
+}
+
+impl std::str::FromStr for Assignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(Self::ById),
+            "random" => Ok(Self::Random),
+            _ => Err(format!("Unknown assignment: {s}")),
+        }
+    }
+}
+
+/// picks the implementation (index in the drone factory) of every drone
+pub(super) fn assign_implementations(
+    drones: &[Drone],
+    implementations: usize,
+    assignment: Assignment,
+    rng: &mut SimRng,
+) -> HashMap<NodeId, usize> {
+    drones
+        .iter()
+        .map(|d| match assignment {
+            Assignment::ById => (d.id, usize::from(d.id) % implementations),
+            Assignment::Random => (d.id, rng.gen_range(0, implementations)),
+        })
+        .collect()
+}
+
+/// overrides the PDR of every drone with a random value in `[0, max)`
+pub(super) fn randomize_pdr(drones: &mut [Drone], max: f32, rng: &mut SimRng) {
+    for d in drones {
+        d.pdr = if max > 0. { rng.gen_range(0., max) } else { 0. };
+    }
+}
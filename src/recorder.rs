@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use common::slc_commands::{ChatClientEvent, ServerEvent, WebClientEvent};
+use crossbeam_channel::{Receiver, Sender};
+use log::error;
+use serde::Serialize;
+use wg_2024::{controller::DroneEvent, network::NodeId, packet::Packet};
+
+use crate::{
+    network::{Network, NodeKind},
+    tap::{fragment_index, packet_type_name},
+};
+
+/// longest payload summary written for events we know nothing about
+const MAX_SUMMARY_LEN: usize = 160;
+
+/// one line of the event log
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct EventRecord {
+    /// microseconds since the UNIX epoch
+    pub(super) timestamp: u64,
+    pub(super) node: NodeId,
+    pub(super) kind: &'static str,
+    pub(super) event: String,
+    pub(super) summary: String,
+}
+
+pub(super) fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_micros()).unwrap_or(u64::MAX))
+}
+
+/// short description of a packet: type, session, fragment and route
+pub(super) fn packet_summary(p: &Packet) -> String {
+    let fragment: String =
+        fragment_index(&p.pack_type).map_or_else(String::new, |i| format!(" fragment={i}"));
+    format!(
+        "{} session={}{} hops={:?}",
+        packet_type_name(&p.pack_type),
+        p.session_id,
+        fragment,
+        p.routing_header.hops
+    )
+}
+
+/// name of the variant and short description of the payload of an event
+pub(super) trait EventSummary: Debug {
+    fn variant(&self) -> String {
+        let debug: String = format!("{self:?}");
+        debug
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    }
+
+    /// packet carried by the event, if we know how to get it
+    fn packet(&self) -> Option<&Packet> {
+        None
+    }
+
+    fn summary(&self) -> String {
+        let debug: String = format!("{self:?}");
+        let variant_len: usize = self.variant().len();
+        let payload: &str = debug[variant_len..]
+            .trim_start_matches(['(', ' ', '{'])
+            .trim_end_matches([')', ' ', '}']);
+        match payload.char_indices().nth(MAX_SUMMARY_LEN) {
+            Some((end, _)) => format!("{}...", &payload[..end]),
+            None => payload.to_owned(),
+        }
+    }
+}
+
+impl EventSummary for DroneEvent {
+    fn packet(&self) -> Option<&Packet> {
+        match self {
+            DroneEvent::PacketSent(p)
+            | DroneEvent::PacketDropped(p)
+            | DroneEvent::ControllerShortcut(p) => Some(p),
+        }
+    }
+
+    fn summary(&self) -> String {
+        self.packet().map(packet_summary).unwrap_or_default()
+    }
+}
+
+impl EventSummary for WebClientEvent {}
+impl EventSummary for ChatClientEvent {}
+impl EventSummary for ServerEvent {}
+
+/// writes `value` as a single JSON line
+pub(super) fn write_line<T: Serialize>(out: &mut impl Write, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")
+}
+
+/// copies every controller-facing event to a JSON lines file
+#[derive(Clone)]
+pub(super) struct EventRecorder {
+    sink: Sender<EventRecord>,
+}
+
+impl EventRecorder {
+    /// starts the writer thread, which stops once every node is gone;
+    /// joining it waits for the log to be complete
+    pub(super) fn start(path: &Path) -> io::Result<(Self, JoinHandle<()>)> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let (sink, records) = crossbeam_channel::unbounded::<EventRecord>();
+        let writer = thread::spawn(move || {
+            for r in &records {
+                if let Err(e) = write_line(&mut out, &r) {
+                    error!("Unable to record events: {e}");
+                    return;
+                }
+                // events come in bursts, flush once the burst is over
+                if records.is_empty() {
+                    let _ = out.flush();
+                }
+            }
+            let _ = out.flush();
+        });
+        Ok((Self::with_sink(sink), writer))
+    }
+
+    /// a recorder handing the records to `sink` instead of a file
+    pub(super) fn with_sink(sink: Sender<EventRecord>) -> Self {
+        Self { sink }
+    }
+
+    /// returns a receiver getting the same events as `events`, after they
+    /// have been recorded
+    pub(super) fn wrap<E: EventSummary + Send + 'static>(
+        &self,
+        node: NodeId,
+        kind: NodeKind,
+        events: Receiver<E>,
+    ) -> Receiver<E> {
+        let (forward, wrapped) = crossbeam_channel::unbounded();
+        let sink: Sender<EventRecord> = self.sink.clone();
+        thread::spawn(move || {
+            for e in events {
+                let _ = sink.send(EventRecord {
+                    timestamp: now_micros(),
+                    node,
+                    kind: kind.as_str(),
+                    event: e.variant(),
+                    summary: e.summary(),
+                });
+                if forward.send(e).is_err() {
+                    break;
+                }
+            }
+        });
+        wrapped
+    }
+
+    fn wrap_all<C, E: EventSummary + Send + 'static, P>(
+        &self,
+        kind: NodeKind,
+        channels: HashMap<NodeId, (C, Receiver<E>, P, Receiver<Packet>)>,
+    ) -> HashMap<NodeId, (C, Receiver<E>, P, Receiver<Packet>)> {
+        channels
+            .into_iter()
+            .map(|(id, (cmd, events, send, recv))| {
+                (id, (cmd, self.wrap(id, kind, events), send, recv))
+            })
+            .collect()
+    }
+
+    /// records the events of every node of `network`
+    pub(super) fn wrap_network(&self, network: Network) -> Network {
+        Network {
+            drones: self.wrap_all(NodeKind::Drone, network.drones),
+            web_clients: self.wrap_all(NodeKind::WebClient, network.web_clients),
+            chat_clients: self.wrap_all(NodeKind::ChatClient, network.chat_clients),
+            servers: self.wrap_all(NodeKind::Server, network.servers),
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use log::error;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+use wg_2024::network::NodeId;
+
+use crate::{
+    cli::implementation_index,
+    network::{drain_events, drone_factory, Network},
+    recorder::{EventRecord, EventRecorder},
+    replay::RecordedCommand,
+    rpc::{NodeInfo, Rpc},
+};
+
+/// events kept for `events tail`
+pub(super) const EVENT_HISTORY: usize = 1000;
+
+/// events printed by `events tail` without a count
+const DEFAULT_TAIL: usize = 20;
+
+/// first word of every command
+const COMMANDS: [&str; 11] = [
+    "crash", "respawn", "pdr", "link", "unlink", "web", "chat", "show", "events", "save", "help",
+];
+
+const HELP: &str = "\
+crash <drone>                  crash a drone and unlink it from its neighbours
+respawn <drone> [implementation]
+                               start a crashed drone again and relink it
+pdr <drone> <pdr>              set the packet drop rate of a drone
+link <a> <b>                   connect two nodes
+unlink <a> <b>                 disconnect two nodes
+web <client> servers           ask the types of the servers
+web <client> files <server>    ask the list of files of a server
+web <client> get <server> <file>
+                               request a file
+chat <client> register <server>
+                               register to a chat server
+show topology                  every node with its neighbours
+events tail [n]                last n events (20)
+save <file>                    write the current topology as a config file
+help                           this message
+quit                           leave, stopping the network";
+
+/// a line typed at the console
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum ReplCommand {
+    Crash(NodeId),
+    Respawn(NodeId, Option<usize>),
+    Pdr(NodeId, f32),
+    Link(NodeId, NodeId),
+    Unlink(NodeId, NodeId),
+    WebServers(NodeId),
+    WebFiles(NodeId, NodeId),
+    WebGet(NodeId, NodeId, String),
+    ChatRegister(NodeId, NodeId),
+    ShowTopology,
+    EventsTail(usize),
+    Save(PathBuf),
+    Help,
+    Quit,
+}
+
+fn arg<T: std::str::FromStr>(words: &[&str], i: usize, what: &str) -> Result<T, String> {
+    let word: &str = words.get(i).ok_or_else(|| format!("Missing {what}"))?;
+    word.parse().map_err(|_| format!("Invalid {what}: {word}"))
+}
+
+/// parses a line of the console, `None` if it's empty
+pub(super) fn parse_line(line: &str) -> Result<Option<ReplCommand>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some(&first) = words.first() else {
+        return Ok(None);
+    };
+    let command: ReplCommand = match (first, words.get(1).copied()) {
+        ("crash", _) => ReplCommand::Crash(arg(&words, 1, "drone")?),
+        ("respawn", _) => ReplCommand::Respawn(
+            arg(&words, 1, "drone")?,
+            words
+                .get(2)
+                .map(|name| implementation_index(name))
+                .transpose()?,
+        ),
+        ("pdr", _) => ReplCommand::Pdr(arg(&words, 1, "drone")?, arg(&words, 2, "pdr")?),
+        ("link", _) => ReplCommand::Link(arg(&words, 1, "node")?, arg(&words, 2, "node")?),
+        ("unlink", _) => ReplCommand::Unlink(arg(&words, 1, "node")?, arg(&words, 2, "node")?),
+        ("web", _) => {
+            let client: NodeId = arg(&words, 1, "client")?;
+            match words.get(2).copied() {
+                Some("servers") => ReplCommand::WebServers(client),
+                Some("files") => ReplCommand::WebFiles(client, arg(&words, 3, "server")?),
+                Some("get") => {
+                    ReplCommand::WebGet(client, arg(&words, 3, "server")?, arg(&words, 4, "file")?)
+                }
+                _ => return Err("Usage: web <client> servers|files|get ...".to_owned()),
+            }
+        }
+        ("chat", _) => match words.get(2).copied() {
+            Some("register") => {
+                ReplCommand::ChatRegister(arg(&words, 1, "client")?, arg(&words, 3, "server")?)
+            }
+            _ => return Err("Usage: chat <client> register <server>".to_owned()),
+        },
+        ("show", Some("topology")) => ReplCommand::ShowTopology,
+        ("events", Some("tail")) => ReplCommand::EventsTail(if words.len() > 2 {
+            arg(&words, 2, "count")?
+        } else {
+            DEFAULT_TAIL
+        }),
+        ("save", _) => ReplCommand::Save(arg(&words, 1, "file")?),
+        ("help" | "?", _) => ReplCommand::Help,
+        ("quit" | "exit", _) => ReplCommand::Quit,
+        _ => return Err(format!("Unknown command: {line} (try help)")),
+    };
+    Ok(Some(command))
+}
+
+/// candidates for the word ending at the end of `line`: command names
+/// first, then subcommands, then node ids; returns where the word starts
+pub(super) fn complete(line: &str, ids: &[NodeId]) -> (usize, Vec<String>) {
+    let start: usize = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word: &str = &line[start..];
+    let before: Vec<&str> = line[..start].split_whitespace().collect();
+    let words: Vec<String> = match (before.first().copied(), before.len()) {
+        (None, _) => COMMANDS.iter().map(|c| (*c).to_owned()).collect(),
+        (Some("show"), 1) => vec!["topology".to_owned()],
+        (Some("events"), 1) => vec!["tail".to_owned()],
+        (Some("events" | "show" | "save" | "help"), _) | (Some("pdr"), 2) => Vec::new(),
+        (Some("web"), 2) => ["servers", "files", "get"].map(str::to_owned).to_vec(),
+        (Some("chat"), 2) => vec!["register".to_owned()],
+        (Some("respawn"), 2) => drone_factory()
+            .iter()
+            .map(|(name, _)| (*name).to_owned())
+            .collect(),
+        (Some("respawn"), _) => Vec::new(),
+        (Some("web"), 4) => Vec::new(),
+        _ => ids.iter().map(ToString::to_string).collect(),
+    };
+    let candidates: Vec<String> = words.into_iter().filter(|w| w.starts_with(word)).collect();
+    (start, candidates)
+}
+
+/// completes the commands and the node ids
+struct ReplHelper {
+    ids: Vec<NodeId>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&line[..pos], &self.ids))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// runs the commands of the console against the network
+pub(super) struct Console {
+    /// shared with the API, so that both see the same topology
+    rpc: Arc<Rpc>,
+    events: Arc<Mutex<VecDeque<EventRecord>>>,
+}
+
+impl Console {
+    pub(super) fn new(rpc: Arc<Rpc>) -> Self {
+        Self {
+            rpc,
+            events: Arc::default(),
+        }
+    }
+
+    /// an event recorder keeping the last events of the nodes it wraps
+    pub(super) fn recorder(&self) -> EventRecorder {
+        let (sink, records) = crossbeam_channel::unbounded::<EventRecord>();
+        let events = Arc::clone(&self.events);
+        thread::spawn(move || {
+            for r in records {
+                let mut events = events.lock().unwrap_or_else(PoisonError::into_inner);
+                if events.len() == EVENT_HISTORY {
+                    events.pop_front();
+                }
+                events.push_back(r);
+            }
+        });
+        EventRecorder::with_sink(sink)
+    }
+
+    fn send(&self, node: NodeId, command: &RecordedCommand) -> Result<(), String> {
+        self.rpc.send_command(node, command).map_err(|e| e.message)
+    }
+
+    /// both ends of a link
+    fn link(&self, a: NodeId, b: NodeId, up: bool) -> Result<(), String> {
+        for (from, to) in [(a, b), (b, a)] {
+            let command = if up {
+                RecordedCommand::AddSender { neighbour: to }
+            } else {
+                RecordedCommand::RemoveSender { neighbour: to }
+            };
+            self.send(from, &command)?;
+        }
+        Ok(())
+    }
+
+    /// every node with its neighbours, `None` once crashed
+    pub(super) fn adjacency(&self) -> Vec<(NodeInfo, Option<Vec<NodeId>>)> {
+        self.rpc
+            .list_nodes()
+            .into_iter()
+            .map(|node| {
+                let neighbours: Option<Vec<NodeId>> = self.rpc.neighbours(node.id).ok();
+                (node, neighbours)
+            })
+            .collect()
+    }
+
+    pub(super) fn ids(&self) -> Vec<NodeId> {
+        self.rpc.list_nodes().iter().map(|n| n.id).collect()
+    }
+
+    fn topology(&self) -> String {
+        let mut out = String::new();
+        for (node, neighbours) in self.adjacency() {
+            let neighbours: String = neighbours.map_or_else(
+                || "crashed".to_owned(),
+                |n| {
+                    n.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                },
+            );
+            let _ = writeln!(
+                out,
+                "{:>3} {:<12} {neighbours}",
+                node.id,
+                node.kind.as_str()
+            );
+        }
+        out
+    }
+
+    /// the last `n` events, oldest first
+    pub(super) fn recent(&self, n: usize) -> Vec<EventRecord> {
+        let events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        events
+            .iter()
+            .skip(events.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    fn tail(&self, n: usize) -> String {
+        let mut out = String::new();
+        for e in self.recent(n) {
+            let _ = writeln!(
+                out,
+                "{} {} {} {} {}",
+                e.timestamp, e.kind, e.node, e.event, e.summary
+            );
+        }
+        out
+    }
+
+    /// runs `command`, returns what to print
+    pub(super) fn execute(&self, command: &ReplCommand) -> Result<String, String> {
+        match command {
+            ReplCommand::Crash(drone) => {
+                let neighbours: Vec<NodeId> = self.rpc.neighbours(*drone).map_err(|e| e.message)?;
+                self.send(*drone, &RecordedCommand::Crash)?;
+                for n in neighbours {
+                    // the other end may have crashed already
+                    let _ = self.send(n, &RecordedCommand::RemoveSender { neighbour: *drone });
+                }
+            }
+            ReplCommand::Respawn(drone, implementation) => {
+                self.rpc
+                    .respawn(*drone, *implementation)
+                    .map_err(|e| e.message)?;
+            }
+            ReplCommand::Pdr(drone, pdr) => {
+                self.send(*drone, &RecordedCommand::SetPacketDropRate { pdr: *pdr })?;
+            }
+            ReplCommand::Link(a, b) => self.link(*a, *b, true)?,
+            ReplCommand::Unlink(a, b) => self.link(*a, *b, false)?,
+            ReplCommand::WebServers(client) => {
+                self.send(*client, &RecordedCommand::AskServersTypes)?;
+            }
+            ReplCommand::WebFiles(client, server) => {
+                self.send(
+                    *client,
+                    &RecordedCommand::AskListOfFiles { server: *server },
+                )?;
+            }
+            ReplCommand::WebGet(client, server, file) => {
+                let command = RecordedCommand::RequestFile {
+                    file: file.clone(),
+                    server: *server,
+                };
+                self.send(*client, &command)?;
+            }
+            ReplCommand::ChatRegister(client, server) => {
+                self.send(
+                    *client,
+                    &RecordedCommand::RegisterToServer { server: *server },
+                )?;
+            }
+            ReplCommand::ShowTopology => return Ok(self.topology()),
+            ReplCommand::EventsTail(n) => return Ok(self.tail(*n)),
+            ReplCommand::Save(path) => {
+                self.rpc.save_config(path).map_err(|e| e.message)?;
+                return Ok(format!("saved {}\n", path.display()));
+            }
+            ReplCommand::Help => return Ok(format!("{HELP}\n")),
+            ReplCommand::Quit => {}
+        }
+        Ok(String::new())
+    }
+}
+
+/// reads commands from the terminal until `quit` or end of input; shortcuts
+/// are routed in the background
+pub(super) fn run_repl(network: Network, rpc: Arc<Rpc>) {
+    let console = Console::new(rpc);
+    let network: Network = console.recorder().wrap_network(network);
+    let router = drain_events(&network);
+
+    let mut editor: Editor<ReplHelper, DefaultHistory> = match Editor::new() {
+        Ok(e) => e,
+        Err(e) => {
+            error!("Unable to open the console: {e}");
+            return;
+        }
+    };
+    editor.set_helper(Some(ReplHelper { ids: console.ids() }));
+    println!("Type help for the list of commands");
+    loop {
+        let line: String = match editor.readline("wg> ") {
+            Ok(l) => l,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => {
+                error!("Unable to read the console: {e}");
+                break;
+            }
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        match parse_line(&line) {
+            Ok(None) => {}
+            Ok(Some(ReplCommand::Quit)) => break,
+            Ok(Some(command)) => match console.execute(&command) {
+                Ok(out) => print!("{out}"),
+                Err(e) => println!("{e}"),
+            },
+            Err(e) => println!("{e}"),
+        }
+    }
+    router.log_counts();
+}
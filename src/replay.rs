@@ -0,0 +1,393 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use common::slc_commands::{ChatClientCommand, ServerCommand, WebClientCommand};
+use crossbeam_channel::Sender;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use wg_2024::{controller::DroneCommand, network::NodeId, packet::Packet};
+
+use crate::{
+    network::{drain_events, Network, NodeKind, Setup},
+    recorder::write_line,
+};
+
+/// time left to the network after the last replayed command
+pub(super) const DEFAULT_REPLAY_WAIT: Duration = Duration::from_secs(5);
+
+/// serializable form of the commands sent to the nodes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(super) enum RecordedCommand {
+    AddSender {
+        neighbour: NodeId,
+    },
+    RemoveSender {
+        neighbour: NodeId,
+    },
+    SetPacketDropRate {
+        pdr: f32,
+    },
+    Crash,
+    AskServersTypes,
+    AskListOfFiles {
+        server: NodeId,
+    },
+    RequestFile {
+        file: String,
+        server: NodeId,
+    },
+    RegisterToServer {
+        server: NodeId,
+    },
+    AskListOfClients {
+        server: NodeId,
+    },
+    SendMessage {
+        server: NodeId,
+        to: NodeId,
+        message: String,
+    },
+    /// shortcuts are a reaction to drone events, the replay routes them again
+    Shortcut,
+}
+
+/// one line of a recording after the header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct CommandRecord {
+    /// microseconds since the start of the recording
+    pub(super) at: u64,
+    pub(super) node: NodeId,
+    pub(super) kind: NodeKind,
+    pub(super) command: RecordedCommand,
+}
+
+/// commands that can be recorded and issued again; `senders` holds the
+/// packet sender of every node, needed to rebuild `AddSender`
+pub(super) trait Replayable: Sized + Send + 'static {
+    fn record(&self) -> RecordedCommand;
+
+    fn replay(command: &RecordedCommand, senders: &HashMap<NodeId, Sender<Packet>>)
+        -> Option<Self>;
+}
+
+impl Replayable for DroneCommand {
+    fn record(&self) -> RecordedCommand {
+        match self {
+            DroneCommand::AddSender(id, _) => RecordedCommand::AddSender { neighbour: *id },
+            DroneCommand::RemoveSender(id) => RecordedCommand::RemoveSender { neighbour: *id },
+            DroneCommand::SetPacketDropRate(pdr) => {
+                RecordedCommand::SetPacketDropRate { pdr: *pdr }
+            }
+            DroneCommand::Crash => RecordedCommand::Crash,
+        }
+    }
+
+    fn replay(
+        command: &RecordedCommand,
+        senders: &HashMap<NodeId, Sender<Packet>>,
+    ) -> Option<Self> {
+        match command {
+            RecordedCommand::AddSender { neighbour } => senders
+                .get(neighbour)
+                .map(|s| DroneCommand::AddSender(*neighbour, s.clone())),
+            RecordedCommand::RemoveSender { neighbour } => {
+                Some(DroneCommand::RemoveSender(*neighbour))
+            }
+            RecordedCommand::SetPacketDropRate { pdr } => {
+                Some(DroneCommand::SetPacketDropRate(*pdr))
+            }
+            RecordedCommand::Crash => Some(DroneCommand::Crash),
+            _ => None,
+        }
+    }
+}
+
+impl Replayable for WebClientCommand {
+    fn record(&self) -> RecordedCommand {
+        match self {
+            WebClientCommand::AddSender(id, _) => RecordedCommand::AddSender { neighbour: *id },
+            WebClientCommand::RemoveSender(id) => RecordedCommand::RemoveSender { neighbour: *id },
+            WebClientCommand::AskServersTypes => RecordedCommand::AskServersTypes,
+            WebClientCommand::AskListOfFiles(id) => RecordedCommand::AskListOfFiles { server: *id },
+            WebClientCommand::RequestFile(file, id) => RecordedCommand::RequestFile {
+                file: file.clone(),
+                server: *id,
+            },
+            WebClientCommand::Shortcut(_) => RecordedCommand::Shortcut,
+        }
+    }
+
+    fn replay(
+        command: &RecordedCommand,
+        senders: &HashMap<NodeId, Sender<Packet>>,
+    ) -> Option<Self> {
+        match command {
+            RecordedCommand::AddSender { neighbour } => senders
+                .get(neighbour)
+                .map(|s| WebClientCommand::AddSender(*neighbour, s.clone())),
+            RecordedCommand::RemoveSender { neighbour } => {
+                Some(WebClientCommand::RemoveSender(*neighbour))
+            }
+            RecordedCommand::AskServersTypes => Some(WebClientCommand::AskServersTypes),
+            RecordedCommand::AskListOfFiles { server } => {
+                Some(WebClientCommand::AskListOfFiles(*server))
+            }
+            RecordedCommand::RequestFile { file, server } => {
+                Some(WebClientCommand::RequestFile(file.clone(), *server))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Replayable for ChatClientCommand {
+    fn record(&self) -> RecordedCommand {
+        match self {
+            ChatClientCommand::AddSender(id, _) => RecordedCommand::AddSender { neighbour: *id },
+            ChatClientCommand::RemoveSender(id) => RecordedCommand::RemoveSender { neighbour: *id },
+            ChatClientCommand::AskServersTypes => RecordedCommand::AskServersTypes,
+            ChatClientCommand::RegisterToServer(id) => {
+                RecordedCommand::RegisterToServer { server: *id }
+            }
+            ChatClientCommand::AskListOfClients(id) => {
+                RecordedCommand::AskListOfClients { server: *id }
+            }
+            ChatClientCommand::SendMessage(server, to, message) => RecordedCommand::SendMessage {
+                server: *server,
+                to: *to,
+                message: message.clone(),
+            },
+            ChatClientCommand::Shortcut(_) => RecordedCommand::Shortcut,
+        }
+    }
+
+    fn replay(
+        command: &RecordedCommand,
+        senders: &HashMap<NodeId, Sender<Packet>>,
+    ) -> Option<Self> {
+        match command {
+            RecordedCommand::AddSender { neighbour } => senders
+                .get(neighbour)
+                .map(|s| ChatClientCommand::AddSender(*neighbour, s.clone())),
+            RecordedCommand::RemoveSender { neighbour } => {
+                Some(ChatClientCommand::RemoveSender(*neighbour))
+            }
+            RecordedCommand::AskServersTypes => Some(ChatClientCommand::AskServersTypes),
+            RecordedCommand::RegisterToServer { server } => {
+                Some(ChatClientCommand::RegisterToServer(*server))
+            }
+            RecordedCommand::AskListOfClients { server } => {
+                Some(ChatClientCommand::AskListOfClients(*server))
+            }
+            RecordedCommand::SendMessage {
+                server,
+                to,
+                message,
+            } => Some(ChatClientCommand::SendMessage(
+                *server,
+                *to,
+                message.clone(),
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl Replayable for ServerCommand {
+    fn record(&self) -> RecordedCommand {
+        match self {
+            ServerCommand::AddSender(id, _) => RecordedCommand::AddSender { neighbour: *id },
+            ServerCommand::RemoveSender(id) => RecordedCommand::RemoveSender { neighbour: *id },
+            ServerCommand::Shortcut(_) => RecordedCommand::Shortcut,
+        }
+    }
+
+    fn replay(
+        command: &RecordedCommand,
+        senders: &HashMap<NodeId, Sender<Packet>>,
+    ) -> Option<Self> {
+        match command {
+            RecordedCommand::AddSender { neighbour } => senders
+                .get(neighbour)
+                .map(|s| ServerCommand::AddSender(*neighbour, s.clone())),
+            RecordedCommand::RemoveSender { neighbour } => {
+                Some(ServerCommand::RemoveSender(*neighbour))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// copies every command sent by the controller to a recording
+#[derive(Clone)]
+pub(super) struct CommandRecorder {
+    sink: Sender<CommandRecord>,
+    start: Instant,
+}
+
+impl CommandRecorder {
+    /// writes the setup of the run as header and starts the writer thread, which stops once
+    /// the controller drops every command sender
+    pub(super) fn start(path: &Path, header: &Setup) -> io::Result<(Self, JoinHandle<()>)> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write_line(&mut out, header)?;
+        out.flush()?;
+        let (sink, records) = crossbeam_channel::unbounded::<CommandRecord>();
+        let writer = thread::spawn(move || {
+            for r in &records {
+                // commands are rare, keep the file usable if the run is killed
+                if let Err(e) = write_line(&mut out, &r).and_then(|()| out.flush()) {
+                    error!("Unable to record commands: {e}");
+                    return;
+                }
+            }
+        });
+        let recorder = Self {
+            sink,
+            start: Instant::now(),
+        };
+        Ok((recorder, writer))
+    }
+
+    /// returns a sender whose commands are recorded, then sent to `commands`
+    pub(super) fn wrap<C: Replayable>(
+        &self,
+        node: NodeId,
+        kind: NodeKind,
+        commands: Sender<C>,
+    ) -> Sender<C> {
+        let (wrapped, recv) = crossbeam_channel::unbounded::<C>();
+        let recorder: CommandRecorder = self.clone();
+        thread::spawn(move || {
+            for c in recv {
+                let _ = recorder.sink.send(CommandRecord {
+                    at: u64::try_from(recorder.start.elapsed().as_micros()).unwrap_or(u64::MAX),
+                    node,
+                    kind,
+                    command: c.record(),
+                });
+                if commands.send(c).is_err() {
+                    break;
+                }
+            }
+        });
+        wrapped
+    }
+
+    fn wrap_all<C: Replayable, E, P, R>(
+        &self,
+        kind: NodeKind,
+        channels: HashMap<NodeId, (Sender<C>, E, P, R)>,
+    ) -> HashMap<NodeId, (Sender<C>, E, P, R)> {
+        channels
+            .into_iter()
+            .map(|(id, (cmd, events, send, recv))| {
+                (id, (self.wrap(id, kind, cmd), events, send, recv))
+            })
+            .collect()
+    }
+
+    /// records the commands sent to every node of `network`
+    pub(super) fn wrap_network(&self, network: Network) -> Network {
+        Network {
+            drones: self.wrap_all(NodeKind::Drone, network.drones),
+            web_clients: self.wrap_all(NodeKind::WebClient, network.web_clients),
+            chat_clients: self.wrap_all(NodeKind::ChatClient, network.chat_clients),
+            servers: self.wrap_all(NodeKind::Server, network.servers),
+        }
+    }
+}
+
+/// options of the `replay` subcommand
+#[derive(Debug)]
+pub(super) struct ReplayOptions {
+    pub(super) file: PathBuf,
+    /// time left to the network after the last command
+    pub(super) wait: Duration,
+    pub(super) event_log: Option<PathBuf>,
+}
+
+/// reads the setup and the commands of a recording
+pub(super) fn read_recording(path: &Path) -> io::Result<(Setup, Vec<CommandRecord>)> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Setup = serde_json::from_str(
+        &lines
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty recording"))??,
+    )?;
+    let commands: Vec<CommandRecord> = lines
+        .filter(|l| l.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(|l| -> io::Result<CommandRecord> { Ok(serde_json::from_str(&l?)?) })
+        .collect::<io::Result<_>>()?;
+    Ok((header, commands))
+}
+
+fn issue<C: Replayable, E, R>(
+    channels: &HashMap<NodeId, (Sender<C>, E, Sender<Packet>, R)>,
+    record: &CommandRecord,
+    senders: &HashMap<NodeId, Sender<Packet>>,
+) {
+    let Some((commands, ..)) = channels.get(&record.node) else {
+        warn!("Replay: node {} doesn't exist", record.node);
+        return;
+    };
+    match C::replay(&record.command, senders) {
+        Some(c) => {
+            let _ = commands.send(c);
+        }
+        None if record.command == RecordedCommand::Shortcut => {}
+        None => warn!(
+            "Replay: skipping {:?} for node {}",
+            record.command, record.node
+        ),
+    }
+}
+
+/// issues `commands` against `network` with their original timings
+pub(super) fn replay(network: &Network, commands: &[CommandRecord], wait: Duration) {
+    let senders: HashMap<NodeId, Sender<Packet>> = network
+        .drones
+        .iter()
+        .map(|(id, (_, _, s, _))| (*id, s.clone()))
+        .chain(
+            network
+                .web_clients
+                .iter()
+                .map(|(id, (_, _, s, _))| (*id, s.clone())),
+        )
+        .chain(
+            network
+                .chat_clients
+                .iter()
+                .map(|(id, (_, _, s, _))| (*id, s.clone())),
+        )
+        .chain(
+            network
+                .servers
+                .iter()
+                .map(|(id, (_, _, s, _))| (*id, s.clone())),
+        )
+        .collect();
+    let router = drain_events(network);
+
+    let start: Instant = Instant::now();
+    for record in commands {
+        let at: Duration = Duration::from_micros(record.at);
+        sleep(at.saturating_sub(start.elapsed()));
+        match record.kind {
+            NodeKind::Drone => issue(&network.drones, record, &senders),
+            NodeKind::WebClient => issue(&network.web_clients, record, &senders),
+            NodeKind::ChatClient => issue(&network.chat_clients, record, &senders),
+            NodeKind::Server => issue(&network.servers, record, &senders),
+        }
+    }
+    sleep(wait);
+    router.log_counts();
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    thread::JoinHandle,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use log::info;
+use wg_2024::{
+    config::Drone,
+    controller::{DroneCommand, DroneEvent},
+    network::NodeId,
+    packet::Packet,
+};
+
+use crate::{
+    network::{self, drone_factory, spawn_node, NodeKind},
+    tap::Tap,
+};
+
+/// what a drone thread was built from, kept to build it again
+struct Slot {
+    config: Drone,
+    implementation: usize,
+    events: Sender<DroneEvent>,
+    commands: Receiver<DroneCommand>,
+    packets: Receiver<Packet>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// brings crashed drones back; the new drone takes over the channels of
+/// the old one, so the maps of the controller stay valid
+pub(super) struct Respawner {
+    drones: Mutex<HashMap<NodeId, Slot>>,
+    /// packet channel of every node
+    channels: HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
+    tap: Option<Tap>,
+}
+
+impl Respawner {
+    pub(super) fn new(
+        channels: HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
+        tap: Option<&Tap>,
+    ) -> Self {
+        Self {
+            drones: Mutex::default(),
+            channels,
+            tap: tap.cloned(),
+        }
+    }
+
+    /// senders from `id` to `neighbours`, going through the tap like the
+    /// links built at startup
+    pub(super) fn links(
+        &self,
+        id: NodeId,
+        neighbours: &[NodeId],
+    ) -> HashMap<NodeId, Sender<Packet>> {
+        let known: Vec<NodeId> = neighbours
+            .iter()
+            .copied()
+            .filter(|n| self.channels.contains_key(n))
+            .collect();
+        network::neighbours(id, &known, &self.channels, self.tap.as_ref())
+    }
+
+    /// keeps the channels of a drone running on `handle`
+    pub(super) fn track(
+        &self,
+        config: &Drone,
+        implementation: usize,
+        events: Sender<DroneEvent>,
+        commands: Receiver<DroneCommand>,
+        handle: Option<JoinHandle<()>>,
+    ) {
+        let slot = Slot {
+            config: config.clone(),
+            implementation,
+            events,
+            commands,
+            packets: self.channels[&config.id].1.clone(),
+            handle,
+        };
+        let mut drones = self.drones.lock().unwrap_or_else(PoisonError::into_inner);
+        drones.insert(config.id, slot);
+    }
+
+    /// starts drone `id` again with the pdr of its config and senders to
+    /// `neighbours`, as `implementation` (an index in the drone factory) or
+    /// as before; the neighbours still have to be told about it
+    pub(super) fn respawn(
+        &self,
+        id: NodeId,
+        implementation: Option<usize>,
+        neighbours: &[NodeId],
+    ) -> Result<(), String> {
+        let mut drones = self.drones.lock().unwrap_or_else(PoisonError::into_inner);
+        let slot: &mut Slot = drones
+            .get_mut(&id)
+            .ok_or_else(|| format!("drone {id} can't be respawned"))?;
+        if slot.handle.as_ref().is_some_and(|h| !h.is_finished()) {
+            return Err(format!("drone {id} is still running, crash it first"));
+        }
+        // commands sent to the dead drone are meaningless to the new one
+        while slot.commands.try_recv().is_ok() {}
+
+        let drone_factory = drone_factory();
+        slot.implementation = implementation.unwrap_or(slot.implementation) % drone_factory.len();
+        let (name, factory) = drone_factory[slot.implementation];
+        let nbrs: HashMap<NodeId, Sender<Packet>> =
+            network::neighbours(id, neighbours, &self.channels, self.tap.as_ref());
+        let mut drone = factory(
+            id,
+            slot.events.clone(),
+            slot.commands.clone(),
+            slot.packets.clone(),
+            nbrs,
+            slot.config.pdr,
+        );
+        slot.handle = spawn_node(NodeKind::Drone, id, name, move || drone.run());
+        info!("Drone {id} respawned as {name}");
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::slc_commands::{
+    ChatClientCommand, ChatClientEvent, ServerCommand, ServerEvent, WebClientCommand,
+    WebClientEvent,
+};
+use crossbeam_channel::{Receiver, Select, Sender};
+use log::{debug, info, warn};
+use wg_2024::{controller::DroneEvent, network::NodeId, packet::Packet};
+
+use crate::{network::Network, recorder::packet_summary, tap::packet_type_name};
+
+/// an event coming from any node of the network
+#[derive(Debug)]
+pub(super) enum NodeEvent {
+    Drone(DroneEvent),
+    WebClient(NodeId, WebClientEvent),
+    ChatClient(NodeId, ChatClientEvent),
+    Server(NodeId, ServerEvent),
+}
+
+impl NodeEvent {
+    /// the packet of a shortcut to the controller, from any kind of node
+    pub(super) fn shortcut(&self) -> Option<&Packet> {
+        match self {
+            Self::Drone(DroneEvent::ControllerShortcut(p))
+            | Self::WebClient(_, WebClientEvent::Shortcut(p))
+            | Self::ChatClient(_, ChatClientEvent::Shortcut(p))
+            | Self::Server(_, ServerEvent::Shortcut(p)) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+/// waits on the events of every node at once, delivering the controller
+/// shortcuts of every node to the node at the end of their route;
+/// clones share the count of routed shortcuts
+#[derive(Clone, Default)]
+pub(super) struct ShortcutRouter {
+    drones: Vec<Receiver<DroneEvent>>,
+    web_clients: Vec<(NodeId, Sender<WebClientCommand>, Receiver<WebClientEvent>)>,
+    chat_clients: Vec<(NodeId, Sender<ChatClientCommand>, Receiver<ChatClientEvent>)>,
+    servers: Vec<(NodeId, Sender<ServerCommand>, Receiver<ServerEvent>)>,
+    /// shortcuts delivered so far, by packet type
+    counts: Arc<Mutex<BTreeMap<&'static str, usize>>>,
+}
+
+impl ShortcutRouter {
+    /// a router for every node of `network`
+    pub(super) fn from_network(network: &Network) -> Self {
+        let mut router = Self::default();
+        for (_, events, ..) in network.drones.values() {
+            router = router.drone(events.clone());
+        }
+        for (id, (commands, events, ..)) in &network.web_clients {
+            router = router.web_client(*id, commands.clone(), events.clone());
+        }
+        for (id, (commands, events, ..)) in &network.chat_clients {
+            router = router.chat_client(*id, commands.clone(), events.clone());
+        }
+        for (id, (commands, events, ..)) in &network.servers {
+            router = router.server(*id, commands.clone(), events.clone());
+        }
+        router
+    }
+
+    pub(super) fn drone(mut self, events: Receiver<DroneEvent>) -> Self {
+        self.drones.push(events);
+        self
+    }
+
+    pub(super) fn web_client(
+        mut self,
+        id: NodeId,
+        commands: Sender<WebClientCommand>,
+        events: Receiver<WebClientEvent>,
+    ) -> Self {
+        self.web_clients.push((id, commands, events));
+        self
+    }
+
+    pub(super) fn chat_client(
+        mut self,
+        id: NodeId,
+        commands: Sender<ChatClientCommand>,
+        events: Receiver<ChatClientEvent>,
+    ) -> Self {
+        self.chat_clients.push((id, commands, events));
+        self
+    }
+
+    pub(super) fn server(
+        mut self,
+        id: NodeId,
+        commands: Sender<ServerCommand>,
+        events: Receiver<ServerEvent>,
+    ) -> Self {
+        self.servers.push((id, commands, events));
+        self
+    }
+
+    /// delivers `p` to the node at the end of its route, returns whether
+    /// that node is known
+    pub(super) fn route(&self, p: Packet) -> bool {
+        let Some(dest) = p.routing_header.hops.last().copied() else {
+            warn!("Shortcut without a route: {}", packet_summary(&p));
+            return false;
+        };
+        let packet_type: &'static str = packet_type_name(&p.pack_type);
+        let _session = tracing::info_span!("session", id = p.session_id).entered();
+        debug!(target: "shortcut", "{} to {dest}", packet_summary(&p));
+        if let Some((_, s, _)) = self.web_clients.iter().find(|(id, ..)| *id == dest) {
+            self.count(packet_type);
+            let _ = s.send(WebClientCommand::Shortcut(p));
+        } else if let Some((_, s, _)) = self.chat_clients.iter().find(|(id, ..)| *id == dest) {
+            self.count(packet_type);
+            let _ = s.send(ChatClientCommand::Shortcut(p));
+        } else if let Some((_, s, _)) = self.servers.iter().find(|(id, ..)| *id == dest) {
+            self.count(packet_type);
+            let _ = s.send(ServerCommand::Shortcut(p));
+        } else {
+            warn!("Shortcut to unknown node {dest}: {}", packet_summary(&p));
+            return false;
+        }
+        true
+    }
+
+    fn count(&self, packet_type: &'static str) {
+        if let Ok(mut counts) = self.counts.lock() {
+            *counts.entry(packet_type).or_default() += 1;
+        }
+    }
+
+    /// shortcuts delivered so far, by packet type
+    pub(super) fn counts(&self) -> BTreeMap<&'static str, usize> {
+        self.counts.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    pub(super) fn log_counts(&self) {
+        let counts: Vec<String> = self
+            .counts()
+            .iter()
+            .map(|(t, n)| format!("{t}={n}"))
+            .collect();
+        info!("Shortcuts routed: {}", counts.join(" "));
+    }
+
+    /// next event of any node, `None` once `deadline` (if any) is passed or
+    /// every node is gone; receivers found disconnected are added to `closed`
+    fn next(&self, closed: &mut HashSet<usize>, deadline: Option<Instant>) -> Option<NodeEvent> {
+        let (d, w, c) = (
+            self.drones.len(),
+            self.web_clients.len(),
+            self.chat_clients.len(),
+        );
+        loop {
+            let mut sel = Select::new();
+            // position of each registered receiver, in the order above
+            let mut registered: Vec<usize> = Vec::new();
+            for (i, r) in self.drones.iter().enumerate() {
+                if !closed.contains(&i) {
+                    sel.recv(r);
+                    registered.push(i);
+                }
+            }
+            for (i, (_, _, r)) in self.web_clients.iter().enumerate() {
+                if !closed.contains(&(d + i)) {
+                    sel.recv(r);
+                    registered.push(d + i);
+                }
+            }
+            for (i, (_, _, r)) in self.chat_clients.iter().enumerate() {
+                if !closed.contains(&(d + w + i)) {
+                    sel.recv(r);
+                    registered.push(d + w + i);
+                }
+            }
+            for (i, (_, _, r)) in self.servers.iter().enumerate() {
+                if !closed.contains(&(d + w + c + i)) {
+                    sel.recv(r);
+                    registered.push(d + w + c + i);
+                }
+            }
+            if registered.is_empty() {
+                return None;
+            }
+
+            let oper = match deadline {
+                Some(deadline) => sel.select_deadline(deadline).ok()?,
+                None => sel.select(),
+            };
+            let i: usize = registered[oper.index()];
+            let event = if i < d {
+                oper.recv(&self.drones[i]).map(NodeEvent::Drone)
+            } else if i < d + w {
+                let (id, _, r) = &self.web_clients[i - d];
+                oper.recv(r).map(|e| NodeEvent::WebClient(*id, e))
+            } else if i < d + w + c {
+                let (id, _, r) = &self.chat_clients[i - d - w];
+                oper.recv(r).map(|e| NodeEvent::ChatClient(*id, e))
+            } else {
+                let (id, _, r) = &self.servers[i - d - w - c];
+                oper.recv(r).map(|e| NodeEvent::Server(*id, e))
+            };
+            match event {
+                Ok(e) => return Some(e),
+                Err(_) => {
+                    closed.insert(i);
+                }
+            }
+        }
+    }
+
+    /// routes shortcuts until an event satisfies `predicate` and returns
+    /// it; every other event is discarded. Returns `None` if nothing
+    /// matched within `timeout` or every node is gone
+    pub(super) fn wait_for(
+        &self,
+        mut predicate: impl FnMut(&NodeEvent) -> bool,
+        timeout: Duration,
+    ) -> Option<NodeEvent> {
+        let deadline: Instant = Instant::now() + timeout;
+        let mut closed: HashSet<usize> = HashSet::new();
+        loop {
+            let e: NodeEvent = self.next(&mut closed, Some(deadline))?;
+            if let Some(p) = e.shortcut() {
+                self.route(p.clone());
+            }
+            if predicate(&e) {
+                return Some(e);
+            }
+        }
+    }
+
+    /// routes shortcuts on a thread of its own, discarding every other
+    /// event, until every node is gone
+    pub(super) fn spawn(&self) {
+        let router: ShortcutRouter = self.clone();
+        thread::spawn(move || {
+            let mut closed: HashSet<usize> = HashSet::new();
+            while let Some(e) = router.next(&mut closed, None) {
+                if let Some(p) = e.shortcut() {
+                    router.route(p.clone());
+                }
+            }
+        });
+    }
+}
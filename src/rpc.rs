@@ -0,0 +1,692 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+
+use common::slc_commands::{ChatClientCommand, ServerCommand, WebClientCommand};
+use crossbeam_channel::Sender;
+use log::{error, info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use wg_2024::{
+    config::{Client, Config, Drone, Server},
+    controller::DroneCommand,
+    network::NodeId,
+    packet::Packet,
+};
+
+use crate::{
+    cli::implementation_index,
+    network::{Network, NodeKind},
+    recorder::{EventRecord, EventRecorder},
+    replay::{RecordedCommand, Replayable},
+    respawn::Respawner,
+    topology_utils::{config_to_toml, topology_error},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// the request was fine but the network can't satisfy it
+const NETWORK_ERROR: i64 = -32000;
+
+/// where the JSON-RPC requests come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum RpcEndpoint {
+    /// a single session on stdin and stdout
+    Stdio,
+    /// a session per connection to the Unix domain socket at this path
+    Unix(PathBuf),
+}
+
+impl FromStr for RpcEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdio" => Ok(Self::Stdio),
+            "" => Err("Empty RPC endpoint".to_owned()),
+            path => Ok(Self::Unix(PathBuf::from(path))),
+        }
+    }
+}
+
+/// error member of a response
+#[derive(Debug, Serialize)]
+pub(super) struct RpcError {
+    pub(super) code: i64,
+    pub(super) message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// missing for notifications, which get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response<'a> {
+    jsonrpc: &'static str,
+    id: &'a Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeParams {
+    node: NodeId,
+}
+
+/// `{"node": 3, "command": "set_packet_drop_rate", "pdr": 0.5}`, the
+/// commands have the form used by recordings
+#[derive(Debug, Deserialize)]
+struct CommandParams {
+    node: NodeId,
+    #[serde(flatten)]
+    command: RecordedCommand,
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveParams {
+    path: PathBuf,
+}
+
+/// `{"node": 3, "implementation": "rusty_drones"}`, the implementation
+/// defaults to the previous one
+#[derive(Debug, Deserialize)]
+struct RespawnParams {
+    node: NodeId,
+    #[serde(default)]
+    implementation: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct NodeInfo {
+    pub(super) id: NodeId,
+    pub(super) kind: NodeKind,
+}
+
+/// sends the command to `node` if it's one of `channels`; `None` if it
+/// isn't, an error if the command doesn't apply to it
+fn issue<C: Replayable>(
+    channels: &HashMap<NodeId, Sender<C>>,
+    node: NodeId,
+    command: &RecordedCommand,
+    packets: &HashMap<NodeId, Sender<Packet>>,
+) -> Option<Result<(), RpcError>> {
+    let commands: &Sender<C> = channels.get(&node)?;
+    let Some(c) = C::replay(command, packets) else {
+        return Some(Err(RpcError::new(
+            NETWORK_ERROR,
+            format!("{command:?} can't be sent to node {node}"),
+        )));
+    };
+    Some(
+        commands
+            .send(c)
+            .map_err(|_| RpcError::new(NETWORK_ERROR, format!("node {node} is gone"))),
+    )
+}
+
+/// the controller side of the API: command senders of every node, what
+/// each node was told about its neighbours and the sessions getting events
+pub(super) struct Rpc {
+    drones: HashMap<NodeId, Sender<DroneCommand>>,
+    web_clients: HashMap<NodeId, Sender<WebClientCommand>>,
+    chat_clients: HashMap<NodeId, Sender<ChatClientCommand>>,
+    servers: HashMap<NodeId, Sender<ServerCommand>>,
+    /// packet sender of every node, needed to build `AddSender`
+    packets: HashMap<NodeId, Sender<Packet>>,
+    neighbours: Mutex<BTreeMap<NodeId, BTreeSet<NodeId>>>,
+    /// neighbours of the crashed drones when they crashed
+    crashed: Mutex<BTreeMap<NodeId, BTreeSet<NodeId>>>,
+    /// PDR of every drone, as last set
+    pdrs: Mutex<HashMap<NodeId, f32>>,
+    /// the topology at startup
+    config: Config,
+    /// session and output of every subscriber
+    subscribers: Mutex<Vec<(usize, Sender<String>)>>,
+    sessions: AtomicUsize,
+    respawner: Option<Arc<Respawner>>,
+}
+
+impl Rpc {
+    /// `config` gives the neighbours of the nodes of `network` at startup
+    pub(super) fn new(network: &Network, config: &Config) -> Self {
+        fn split<C, E, R>(
+            channels: &HashMap<NodeId, (Sender<C>, E, Sender<Packet>, R)>,
+            packets: &mut HashMap<NodeId, Sender<Packet>>,
+        ) -> HashMap<NodeId, Sender<C>> {
+            channels
+                .iter()
+                .map(|(id, (commands, _, p, _))| {
+                    packets.insert(*id, p.clone());
+                    (*id, commands.clone())
+                })
+                .collect()
+        }
+
+        let mut packets: HashMap<NodeId, Sender<Packet>> = HashMap::new();
+        let neighbours: BTreeMap<NodeId, BTreeSet<NodeId>> = config
+            .drone
+            .iter()
+            .map(|d| (d.id, d.connected_node_ids.iter().copied().collect()))
+            .chain(
+                config
+                    .client
+                    .iter()
+                    .map(|c| (c.id, c.connected_drone_ids.iter().copied().collect())),
+            )
+            .chain(
+                config
+                    .server
+                    .iter()
+                    .map(|s| (s.id, s.connected_drone_ids.iter().copied().collect())),
+            )
+            .collect();
+        Self {
+            drones: split(&network.drones, &mut packets),
+            web_clients: split(&network.web_clients, &mut packets),
+            chat_clients: split(&network.chat_clients, &mut packets),
+            servers: split(&network.servers, &mut packets),
+            packets,
+            neighbours: Mutex::new(neighbours),
+            crashed: Mutex::default(),
+            pdrs: Mutex::new(config.drone.iter().map(|d| (d.id, d.pdr)).collect()),
+            config: config.clone(),
+            subscribers: Mutex::default(),
+            sessions: AtomicUsize::new(0),
+            respawner: None,
+        }
+    }
+
+    /// lets `respawn` bring back the crashed drones of `respawner`
+    pub(super) fn with_respawner(mut self, respawner: Option<Arc<Respawner>>) -> Self {
+        self.respawner = respawner;
+        self
+    }
+
+    fn kind(&self, node: NodeId) -> Option<NodeKind> {
+        if self.drones.contains_key(&node) {
+            Some(NodeKind::Drone)
+        } else if self.web_clients.contains_key(&node) {
+            Some(NodeKind::WebClient)
+        } else if self.chat_clients.contains_key(&node) {
+            Some(NodeKind::ChatClient)
+        } else if self.servers.contains_key(&node) {
+            Some(NodeKind::Server)
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn list_nodes(&self) -> Vec<NodeInfo> {
+        let mut nodes: Vec<NodeInfo> = self
+            .packets
+            .keys()
+            .filter_map(|id| self.kind(*id).map(|kind| NodeInfo { id: *id, kind }))
+            .collect();
+        nodes.sort_by_key(|n| n.id);
+        nodes
+    }
+
+    pub(super) fn neighbours(&self, node: NodeId) -> Result<Vec<NodeId>, RpcError> {
+        self.neighbours
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&node)
+            .map(|n| n.iter().copied().collect())
+            .ok_or_else(|| RpcError::new(NETWORK_ERROR, format!("unknown node {node}")))
+    }
+
+    /// sends `command` to `node`, keeping track of its neighbours
+    pub(super) fn send_command(
+        &self,
+        node: NodeId,
+        command: &RecordedCommand,
+    ) -> Result<(), RpcError> {
+        // a new link goes through the tap, like the ones built at startup
+        let links: HashMap<NodeId, Sender<Packet>>;
+        let packets: &HashMap<NodeId, Sender<Packet>> = match (command, &self.respawner) {
+            (RecordedCommand::AddSender { neighbour }, Some(r)) => {
+                links = r.links(node, &[*neighbour]);
+                &links
+            }
+            _ => &self.packets,
+        };
+        issue(&self.drones, node, command, packets)
+            .or_else(|| issue(&self.web_clients, node, command, packets))
+            .or_else(|| issue(&self.chat_clients, node, command, packets))
+            .or_else(|| issue(&self.servers, node, command, packets))
+            .unwrap_or_else(|| Err(RpcError::new(NETWORK_ERROR, format!("unknown node {node}"))))?;
+        self.apply(node, command);
+        Ok(())
+    }
+
+    /// updates the topology with `command`, sent to `node`
+    fn apply(&self, node: NodeId, command: &RecordedCommand) {
+        let mut neighbours = self
+            .neighbours
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match *command {
+            RecordedCommand::AddSender { neighbour } => {
+                neighbours.entry(node).or_default().insert(neighbour);
+            }
+            RecordedCommand::RemoveSender { neighbour } => {
+                neighbours.entry(node).or_default().remove(&neighbour);
+            }
+            RecordedCommand::Crash => {
+                if let Some(links) = neighbours.remove(&node) {
+                    let mut crashed = self.crashed.lock().unwrap_or_else(PoisonError::into_inner);
+                    crashed.insert(node, links);
+                }
+            }
+            RecordedCommand::SetPacketDropRate { pdr } => {
+                let mut pdrs = self.pdrs.lock().unwrap_or_else(PoisonError::into_inner);
+                pdrs.insert(node, pdr);
+            }
+            _ => {}
+        }
+    }
+
+    /// returns a sender whose commands update the topology, then go to
+    /// `commands`
+    fn track<C: Replayable>(self: &Arc<Self>, node: NodeId, commands: Sender<C>) -> Sender<C> {
+        let (wrapped, recv) = crossbeam_channel::unbounded::<C>();
+        let rpc: Arc<Rpc> = Arc::clone(self);
+        thread::spawn(move || {
+            for c in recv {
+                rpc.apply(node, &c.record());
+                if commands.send(c).is_err() {
+                    break;
+                }
+            }
+        });
+        wrapped
+    }
+
+    /// keeps the topology up to date with the commands sent to the nodes of
+    /// `network` by anyone else, the controller among them
+    pub(super) fn track_network(self: &Arc<Self>, network: Network) -> Network {
+        fn track_all<C: Replayable, E, P, R>(
+            rpc: &Arc<Rpc>,
+            channels: HashMap<NodeId, (Sender<C>, E, P, R)>,
+        ) -> HashMap<NodeId, (Sender<C>, E, P, R)> {
+            channels
+                .into_iter()
+                .map(|(id, (cmd, events, send, recv))| {
+                    (id, (rpc.track(id, cmd), events, send, recv))
+                })
+                .collect()
+        }
+
+        Network {
+            drones: track_all(self, network.drones),
+            web_clients: track_all(self, network.web_clients),
+            chat_clients: track_all(self, network.chat_clients),
+            servers: track_all(self, network.servers),
+        }
+    }
+
+    /// the topology as changed by the commands sent so far: crashed drones
+    /// and the links to them are left out
+    pub(super) fn current_config(&self) -> Config {
+        let neighbours = self
+            .neighbours
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let pdrs = self.pdrs.lock().unwrap_or_else(PoisonError::into_inner);
+        let links = |id: NodeId| -> Option<Vec<NodeId>> {
+            let links = neighbours.get(&id)?;
+            Some(
+                links
+                    .iter()
+                    .filter(|n| neighbours.contains_key(n))
+                    .copied()
+                    .collect(),
+            )
+        };
+        Config {
+            drone: self
+                .config
+                .drone
+                .iter()
+                .filter_map(|d| {
+                    Some(Drone {
+                        id: d.id,
+                        connected_node_ids: links(d.id)?,
+                        pdr: pdrs.get(&d.id).copied().unwrap_or(d.pdr),
+                    })
+                })
+                .collect(),
+            client: self
+                .config
+                .client
+                .iter()
+                .filter_map(|c| {
+                    Some(Client {
+                        id: c.id,
+                        connected_drone_ids: links(c.id)?,
+                    })
+                })
+                .collect(),
+            server: self
+                .config
+                .server
+                .iter()
+                .filter_map(|s| {
+                    Some(Server {
+                        id: s.id,
+                        connected_drone_ids: links(s.id)?,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// writes the current topology to `path` as a config file, if it's
+    /// still a valid one
+    pub(super) fn save_config(&self, path: &Path) -> Result<(), RpcError> {
+        let Config {
+            drone,
+            client,
+            server,
+        } = self.current_config();
+        if let Some(e) = topology_error(&drone, &client, &server) {
+            return Err(RpcError::new(
+                NETWORK_ERROR,
+                format!("the current topology isn't a valid config: {e}"),
+            ));
+        }
+        std::fs::write(path, config_to_toml(&drone, &client, &server)).map_err(|e| {
+            RpcError::new(
+                NETWORK_ERROR,
+                format!("unable to write {}: {e}", path.display()),
+            )
+        })
+    }
+
+    /// starts crashed drone `node` again, linked to the neighbours it had
+    /// that are still up, and reconnects them; returns its neighbours
+    pub(super) fn respawn(
+        &self,
+        node: NodeId,
+        implementation: Option<usize>,
+    ) -> Result<Vec<NodeId>, RpcError> {
+        let respawner: &Respawner = self
+            .respawner
+            .as_deref()
+            .ok_or_else(|| RpcError::new(NETWORK_ERROR, "respawning isn't available"))?;
+        let alive: Vec<NodeId> = {
+            let neighbours = self
+                .neighbours
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let crashed = self.crashed.lock().unwrap_or_else(PoisonError::into_inner);
+            // a drone that panicked was never told to crash
+            crashed
+                .get(&node)
+                .or_else(|| neighbours.get(&node))
+                .into_iter()
+                .flatten()
+                .filter(|n| neighbours.contains_key(n))
+                .copied()
+                .collect()
+        };
+        respawner
+            .respawn(node, implementation, &alive)
+            .map_err(|e| RpcError::new(NETWORK_ERROR, e))?;
+        self.crashed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&node);
+        self.neighbours
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(node, alive.iter().copied().collect());
+        for n in &alive {
+            self.send_command(*n, &RecordedCommand::AddSender { neighbour: node })?;
+        }
+        if let Some(d) = self.config.drone.iter().find(|d| d.id == node) {
+            let mut pdrs = self.pdrs.lock().unwrap_or_else(PoisonError::into_inner);
+            pdrs.insert(node, d.pdr);
+        }
+        Ok(alive)
+    }
+
+    fn call(
+        &self,
+        session: usize,
+        out: &Sender<String>,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, RpcError> {
+        fn parse<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+            serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+        }
+
+        match method {
+            "list_nodes" => Ok(json!(self.list_nodes())),
+            "neighbours" => {
+                let p: NodeParams = parse(params)?;
+                Ok(json!(self.neighbours(p.node)?))
+            }
+            "send_command" => {
+                let p: CommandParams = parse(params)?;
+                self.send_command(p.node, &p.command)?;
+                Ok(Value::Bool(true))
+            }
+            "respawn" => {
+                let p: RespawnParams = parse(params)?;
+                let implementation: Option<usize> = p
+                    .implementation
+                    .as_deref()
+                    .map(implementation_index)
+                    .transpose()
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                Ok(json!(self.respawn(p.node, implementation)?))
+            }
+            "save_config" => {
+                let p: SaveParams = parse(params)?;
+                self.save_config(&p.path)?;
+                Ok(Value::Bool(true))
+            }
+            "subscribe" => {
+                let mut subscribers = self
+                    .subscribers
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                if !subscribers.iter().any(|(s, _)| *s == session) {
+                    subscribers.push((session, out.clone()));
+                }
+                Ok(Value::Bool(true))
+            }
+            "unsubscribe" => {
+                self.unsubscribe(session);
+                Ok(Value::Bool(true))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {method}"),
+            )),
+        }
+    }
+
+    fn unsubscribe(&self, session: usize) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(s, _)| *s != session);
+    }
+
+    /// answers a line of `session`, `None` for notifications; `out` is where
+    /// the events go if the session subscribes
+    pub(super) fn handle(
+        &self,
+        session: usize,
+        out: &Sender<String>,
+        line: &str,
+    ) -> Option<String> {
+        let (id, outcome): (Value, Result<Value, RpcError>) =
+            match serde_json::from_str::<Value>(line) {
+                Err(e) => (Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+                Ok(v) => match serde_json::from_value::<Request>(v) {
+                    Err(e) => (
+                        Value::Null,
+                        Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                    ),
+                    Ok(r) => {
+                        let outcome = self.call(session, out, &r.method, r.params);
+                        (r.id?, outcome)
+                    }
+                },
+            };
+        let (result, error) = match outcome {
+            Ok(v) => (Some(v), None),
+            Err(e) => (None, Some(e)),
+        };
+        serde_json::to_string(&Response {
+            jsonrpc: "2.0",
+            id: &id,
+            result,
+            error,
+        })
+        .ok()
+    }
+
+    /// sends `record` to every subscriber, forgetting the ones that are gone
+    fn notify(&self, record: &EventRecord) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if subscribers.is_empty() {
+            return;
+        }
+        let notification = json!({"jsonrpc": "2.0", "method": "event", "params": record});
+        let text: String = notification.to_string();
+        subscribers.retain(|(_, out)| out.send(text.clone()).is_ok());
+    }
+
+    /// an event recorder notifying the subscribers of the events of the
+    /// nodes it wraps
+    pub(super) fn recorder(self: &Arc<Self>) -> EventRecorder {
+        let (sink, records) = crossbeam_channel::unbounded::<EventRecord>();
+        let rpc: Arc<Rpc> = Arc::clone(self);
+        thread::spawn(move || {
+            for r in records {
+                rpc.notify(&r);
+            }
+        });
+        EventRecorder::with_sink(sink)
+    }
+
+    /// runs a session, one request per line, until `input` ends
+    pub(super) fn session(&self, input: impl BufRead, mut output: impl Write + Send + 'static) {
+        let session: usize = self.sessions.fetch_add(1, Ordering::Relaxed);
+        let (out, lines) = crossbeam_channel::unbounded::<String>();
+        let writer = thread::spawn(move || {
+            for line in lines {
+                if let Err(e) = writeln!(output, "{line}").and_then(|()| output.flush()) {
+                    warn!("RPC session gone: {e}");
+                    return;
+                }
+            }
+        });
+        for line in input.lines() {
+            let line: String = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    warn!("RPC session gone: {e}");
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(session, &out, &line) {
+                let _ = out.send(response);
+            }
+        }
+        self.unsubscribe(session);
+        drop(out);
+        let _ = writer.join();
+    }
+}
+
+/// serves the API of `rpc` on `endpoint` on threads of their own; the
+/// returned network copies its events to the subscribers
+pub(super) fn start_rpc(endpoint: &RpcEndpoint, network: Network, rpc: Arc<Rpc>) -> Network {
+    let network: Network = rpc.recorder().wrap_network(network);
+    match endpoint {
+        RpcEndpoint::Stdio => {
+            info!("JSON-RPC API available on stdio");
+            thread::spawn(move || rpc.session(io::stdin().lock(), io::stdout()));
+        }
+        RpcEndpoint::Unix(path) => {
+            if let Err(e) = serve_unix(rpc, path) {
+                error!(
+                    "Unable to serve the JSON-RPC API on {}: {e}",
+                    path.display()
+                );
+            }
+        }
+    }
+    network
+}
+
+#[cfg(unix)]
+fn serve_unix(rpc: Arc<Rpc>, path: &Path) -> io::Result<()> {
+    use std::{io::BufReader, os::unix::net::UnixListener};
+
+    let listener = UnixListener::bind(path)?;
+    info!("JSON-RPC API available on {}", path.display());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("RPC connection failed: {e}");
+                    continue;
+                }
+            };
+            let rpc: Arc<Rpc> = Arc::clone(&rpc);
+            thread::spawn(move || match stream.try_clone() {
+                Ok(output) => rpc.session(BufReader::new(stream), output),
+                Err(e) => warn!("RPC connection failed: {e}"),
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_rpc: Arc<Rpc>, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not available on this platform",
+    ))
+}
@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf};
+
+use serde_json::{json, Value};
+
+/// options of the `schema` subcommand
+#[derive(Debug, Default)]
+pub(super) struct SchemaOptions {
+    /// write the schema there instead of stdout
+    pub(super) output: Option<PathBuf>,
+}
+
+fn node_id(description: &str) -> Value {
+    json!({
+        "description": description,
+        "type": "integer",
+        "minimum": 0,
+        "maximum": 255
+    })
+}
+
+fn neighbours(description: &str, min: usize, max: Option<usize>) -> Value {
+    let mut list: Value = json!({
+        "description": description,
+        "type": "array",
+        "items": node_id("id of a neighbour"),
+        "uniqueItems": true,
+        "minItems": min
+    });
+    if let Some(max) = max {
+        list["maxItems"] = json!(max);
+    }
+    list
+}
+
+/// one `[[kind]]` table, every key is required and no other is allowed
+fn table(description: &str, properties: Value) -> Value {
+    let required: Vec<&String> = properties
+        .as_object()
+        .map(|p| p.keys().collect())
+        .unwrap_or_default();
+    json!({
+        "description": description,
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false
+        }
+    })
+}
+
+/// JSON Schema of the topology files read by the initializer; it checks
+/// their structure and the bounds of each value, the constraints across
+/// nodes are left to the initializer
+pub(super) fn topology_schema() -> Value {
+    let drone = table(
+        "a drone, running one of the drone implementations",
+        json!({
+            "id": node_id("unique id of the node"),
+            "connected_node_ids": neighbours("drones, clients and servers it's linked to", 0, None),
+            "pdr": {
+                "description": "probability of dropping a fragment",
+                "type": "number",
+                "minimum": 0,
+                "maximum": 1
+            }
+        }),
+    );
+    let client = table(
+        "a client, web browser if its id is even and chat client if odd",
+        json!({
+            "id": node_id("unique id of the node"),
+            "connected_drone_ids": neighbours("drones it's linked to", 1, Some(2))
+        }),
+    );
+    let server = table(
+        "a server, text, media or chat server depending on its id",
+        json!({
+            "id": node_id("unique id of the node"),
+            "connected_drone_ids": neighbours("drones it's linked to", 2, None)
+        }),
+    );
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Network topology",
+        "description": "nodes of a network spawned by the network initializer",
+        "type": "object",
+        "properties": {
+            "drone": drone,
+            "client": client,
+            "server": server
+        },
+        "required": ["drone", "client", "server"],
+        "additionalProperties": false
+    })
+}
+
+/// prints the schema, or writes it to the output of `opts`
+pub(super) fn run_schema(opts: &SchemaOptions) -> Result<(), String> {
+    let mut schema: String =
+        serde_json::to_string_pretty(&topology_schema()).map_err(|e| e.to_string())?;
+    schema.push('\n');
+    match &opts.output {
+        Some(path) => {
+            fs::write(path, schema).map_err(|e| format!("Unable to write {}: {e}", path.display()))
+        }
+        None => {
+            print!("{schema}");
+            Ok(())
+        }
+    }
+}
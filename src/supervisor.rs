@@ -0,0 +1,455 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env, io,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{select, Receiver, Sender};
+use log::{debug, error, info, warn};
+use wg_2024::{
+    controller::{DroneCommand, DroneEvent},
+    network::NodeId,
+    packet::Packet,
+};
+
+use crate::{
+    ipc::{read_frame, send_frame, Frame, SharedWriter},
+    network::drone_factory,
+};
+
+/// time between two checks of the children
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// frames kept for a drone whose process isn't connected, the oldest are
+/// dropped past this
+const PENDING_LIMIT: usize = 4096;
+
+/// how drones are run in multi-process mode
+#[derive(Debug, Default)]
+pub(super) struct ProcessOptions {
+    /// spawn a new process for a drone that died without being crashed
+    pub(super) restart: bool,
+}
+
+/// socket of the supervisor of this process
+pub(super) fn default_socket() -> PathBuf {
+    env::temp_dir().join(format!("wg-{}.sock", std::process::id()))
+}
+
+/// a drone as handed over by the network initializer
+pub(super) struct HostedDrone {
+    pub(super) id: NodeId,
+    /// index in the drone factory
+    pub(super) implementation: usize,
+    pub(super) pdr: f32,
+    pub(super) neighbours: HashMap<NodeId, Sender<Packet>>,
+    pub(super) events: Sender<DroneEvent>,
+    pub(super) commands: Receiver<DroneCommand>,
+    pub(super) packets: Receiver<Packet>,
+}
+
+/// connection of a drone process
+struct Link {
+    /// tells apart the connections of a restarted drone
+    generation: u64,
+    out: SharedWriter,
+}
+
+/// the channels of a drone on this side of the socket
+struct Bridge {
+    id: NodeId,
+    implementation: usize,
+    /// where the packets forwarded by the drone go
+    neighbours: Mutex<HashMap<NodeId, Sender<Packet>>>,
+    pdr: Mutex<f32>,
+    events: Sender<DroneEvent>,
+    link: Mutex<Option<Link>>,
+    /// frames waiting for the process to connect
+    pending: Mutex<VecDeque<Frame>>,
+    /// the controller crashed the drone, its exit is expected
+    crashed: AtomicBool,
+}
+
+impl Bridge {
+    /// writes `frame` to the drone, keeping it for later if the drone isn't
+    /// connected
+    fn send(&self, frame: Frame) {
+        let mut link = self.link.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(l) = link.as_ref() {
+            match send_frame(&l.out, &frame) {
+                Ok(()) => return,
+                Err(e) => {
+                    debug!("Drone {}: connection lost ({e})", self.id);
+                    *link = None;
+                }
+            }
+        }
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if pending.len() == PENDING_LIMIT {
+            pending.pop_front();
+        }
+        pending.push_back(frame);
+    }
+
+    /// the only writer of the connection: merges the commands and the
+    /// packets of the drone into one ordered stream of frames, a command
+    /// sent before a packet reaches the drone first
+    fn pump(&self, mut commands: Receiver<DroneCommand>, mut packets: Receiver<Packet>) {
+        let mut open: usize = 2;
+        while open > 0 {
+            select! {
+                recv(commands) -> c => match c {
+                    Ok(c) => self.command(c),
+                    Err(_) => {
+                        commands = crossbeam_channel::never();
+                        open -= 1;
+                    }
+                },
+                recv(packets) -> p => match p {
+                    Ok(p) => {
+                        // the commands sent before the packet are already queued
+                        for c in commands.try_iter() {
+                            self.command(c);
+                        }
+                        self.send(Frame::Packet(p));
+                    }
+                    Err(_) => {
+                        packets = crossbeam_channel::never();
+                        open -= 1;
+                    }
+                },
+            }
+        }
+    }
+
+    /// the neighbour ids, sorted
+    fn neighbour_ids(&self) -> Vec<NodeId> {
+        let neighbours = self
+            .neighbours
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut ids: Vec<NodeId> = neighbours.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// applies a command of the controller and passes it on to the drone
+    fn command(&self, command: DroneCommand) {
+        let frame: Frame = match command {
+            DroneCommand::AddSender(id, sender) => {
+                let mut neighbours = self
+                    .neighbours
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                neighbours.insert(id, sender);
+                Frame::AddSender(id)
+            }
+            DroneCommand::RemoveSender(id) => {
+                let mut neighbours = self
+                    .neighbours
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                neighbours.remove(&id);
+                Frame::RemoveSender(id)
+            }
+            DroneCommand::SetPacketDropRate(pdr) => {
+                *self.pdr.lock().unwrap_or_else(PoisonError::into_inner) = pdr;
+                Frame::SetPacketDropRate(pdr)
+            }
+            DroneCommand::Crash => {
+                self.crashed.store(true, Ordering::SeqCst);
+                Frame::Crash
+            }
+        };
+        self.send(frame);
+    }
+
+    /// handles what the drone sends until the connection is closed
+    fn serve(&self, generation: u64, mut input: impl Read) {
+        loop {
+            match read_frame(&mut input) {
+                Ok(Some(Frame::Forward(to, p))) => {
+                    let neighbours = self
+                        .neighbours
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    match neighbours.get(&to) {
+                        Some(s) => {
+                            let _ = s.send(p);
+                        }
+                        None => debug!("Drone {}: packet for unknown neighbour {to}", self.id),
+                    }
+                }
+                Ok(Some(Frame::Event(e))) => {
+                    let _ = self.events.send(e);
+                }
+                Ok(Some(f)) => debug!("Drone {}: unexpected {f:?}", self.id),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Drone {}: bad frame ({e})", self.id);
+                    break;
+                }
+            }
+        }
+        let mut link = self.link.lock().unwrap_or_else(PoisonError::into_inner);
+        if link.as_ref().is_some_and(|l| l.generation == generation) {
+            *link = None;
+        }
+    }
+}
+
+struct Inner {
+    socket: PathBuf,
+    restart: bool,
+    bridges: Mutex<HashMap<NodeId, Arc<Bridge>>>,
+    children: Mutex<HashMap<NodeId, Child>>,
+    generations: AtomicU64,
+}
+
+impl Inner {
+    /// starts the process of drone `id` with its current pdr and neighbours
+    fn launch(&self, bridge: &Bridge) -> io::Result<()> {
+        let neighbours: Vec<String> = bridge
+            .neighbour_ids()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let pdr: f32 = *bridge.pdr.lock().unwrap_or_else(PoisonError::into_inner);
+        let factory = drone_factory();
+        let (implementation, _) = factory[bridge.implementation % factory.len()];
+        let child: Child = Command::new(env::current_exe()?)
+            .arg("node")
+            .arg("--socket")
+            .arg(&self.socket)
+            .args(["--id", &bridge.id.to_string()])
+            .args(["--implementation", implementation])
+            .args(["--pdr", &pdr.to_string()])
+            .args(["--neighbours", &neighbours.join(",")])
+            .stdin(Stdio::null())
+            .spawn()?;
+        info!("Drone {} running as process {}", bridge.id, child.id());
+        let mut children = self.children.lock().unwrap_or_else(PoisonError::into_inner);
+        children.insert(bridge.id, child);
+        Ok(())
+    }
+
+    /// maps a new connection to its drone with the first frame, then serves it
+    fn attach(&self, mut input: impl Read, output: impl Write + Send + 'static) {
+        let id: NodeId = match read_frame(&mut input) {
+            Ok(Some(Frame::Hello(id))) => id,
+            Ok(f) => {
+                warn!("Drone process connected without saying hello: {f:?}");
+                return;
+            }
+            Err(e) => {
+                warn!("Drone process connection failed: {e}");
+                return;
+            }
+        };
+        let bridges = self.bridges.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(bridge) = bridges.get(&id).map(Arc::clone) else {
+            warn!("Unknown drone {id} connected");
+            return;
+        };
+        drop(bridges);
+
+        let generation: u64 = self.generations.fetch_add(1, Ordering::SeqCst);
+        let out: SharedWriter = Arc::new(Mutex::new(Box::new(output)));
+        {
+            let mut link = bridge.link.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut pending = bridge
+                .pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for frame in pending.drain(..) {
+                if let Err(e) = send_frame(&out, &frame) {
+                    warn!("Drone {id}: connection lost ({e})");
+                    return;
+                }
+            }
+            *link = Some(Link { generation, out });
+        }
+        debug!("Drone {id} connected");
+        bridge.serve(generation, input);
+    }
+
+    /// reports the children that exited, restarting the ones that died
+    /// on their own when asked to
+    fn supervise(&self) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let mut exited: Vec<(NodeId, String)> = Vec::new();
+            {
+                let mut children = self.children.lock().unwrap_or_else(PoisonError::into_inner);
+                children.retain(|id, child| match child.try_wait() {
+                    Ok(None) => true,
+                    Ok(Some(status)) => {
+                        exited.push((*id, status.to_string()));
+                        false
+                    }
+                    Err(e) => {
+                        exited.push((*id, e.to_string()));
+                        false
+                    }
+                });
+            }
+            for (id, status) in exited {
+                let bridges = self.bridges.lock().unwrap_or_else(PoisonError::into_inner);
+                let Some(bridge) = bridges.get(&id).map(Arc::clone) else {
+                    continue;
+                };
+                drop(bridges);
+                if bridge.crashed.load(Ordering::SeqCst) {
+                    info!("Drone {id} exited after its crash ({status})");
+                    continue;
+                }
+                warn!("Drone {id} died ({status})");
+                if self.restart {
+                    match self.launch(&bridge) {
+                        Ok(()) => info!("Drone {id} restarted"),
+                        Err(e) => error!("Unable to restart drone {id}: {e}"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// runs drones in child processes, bridging their channels over a Unix
+/// domain socket; children are killed once dropped
+pub(super) struct Supervisor {
+    inner: Arc<Inner>,
+}
+
+impl Supervisor {
+    /// listens for drone processes on `socket`
+    pub(super) fn bind(socket: &Path, opts: &ProcessOptions) -> io::Result<Self> {
+        let inner = Arc::new(Inner {
+            socket: socket.to_path_buf(),
+            restart: opts.restart,
+            bridges: Mutex::default(),
+            children: Mutex::default(),
+            generations: AtomicU64::new(0),
+        });
+        listen(&inner)?;
+        let supervised: Arc<Inner> = Arc::clone(&inner);
+        thread::spawn(move || supervised.supervise());
+        Ok(Self { inner })
+    }
+
+    /// bridges the channels of `drone`, which is expected to connect later
+    pub(super) fn register(&self, drone: HostedDrone) {
+        let HostedDrone {
+            id,
+            implementation,
+            pdr,
+            neighbours,
+            events,
+            commands,
+            packets,
+        } = drone;
+        let bridge = Arc::new(Bridge {
+            id,
+            implementation,
+            neighbours: Mutex::new(neighbours),
+            pdr: Mutex::new(pdr),
+            events,
+            link: Mutex::default(),
+            pending: Mutex::default(),
+            crashed: AtomicBool::new(false),
+        });
+        let b: Arc<Bridge> = Arc::clone(&bridge);
+        thread::spawn(move || b.pump(commands, packets));
+        let mut bridges = self
+            .inner
+            .bridges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        bridges.insert(id, bridge);
+    }
+
+    /// id of the process running drone `id`, `None` while it has none
+    pub(super) fn pid(&self, id: NodeId) -> Option<u32> {
+        let children = self
+            .inner
+            .children
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        children.get(&id).map(Child::id)
+    }
+
+    /// bridges the channels of `drone` and starts its process
+    pub(super) fn host(&self, drone: HostedDrone) {
+        let id: NodeId = drone.id;
+        self.register(drone);
+        let bridges = self
+            .inner
+            .bridges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let bridge: Arc<Bridge> = Arc::clone(&bridges[&id]);
+        drop(bridges);
+        if let Err(e) = self.inner.launch(&bridge) {
+            error!("Unable to spawn the process of drone {id}: {e}");
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        let mut children = self
+            .inner
+            .children
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for child in children.values_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        children.clear();
+        let _ = std::fs::remove_file(&self.inner.socket);
+    }
+}
+
+#[cfg(unix)]
+fn listen(inner: &Arc<Inner>) -> io::Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    // a socket left behind by a previous run with the same pid
+    let _ = std::fs::remove_file(&inner.socket);
+    let listener = UnixListener::bind(&inner.socket)?;
+    let inner: Arc<Inner> = Arc::clone(inner);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Drone process connection failed: {e}");
+                    continue;
+                }
+            };
+            let inner: Arc<Inner> = Arc::clone(&inner);
+            thread::spawn(move || match stream.try_clone() {
+                Ok(output) => inner.attach(stream, output),
+                Err(e) => warn!("Drone process connection failed: {e}"),
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen(_inner: &Arc<Inner>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not available on this platform",
+    ))
+}
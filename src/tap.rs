@@ -0,0 +1,229 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use log::{error, info};
+use wg_2024::{
+    network::NodeId,
+    packet::{Packet, PacketType},
+};
+
+/// copy of a packet that crossed the link `from -> to`
+#[derive(Debug, Clone)]
+pub(super) struct TapRecord {
+    pub(super) timestamp: SystemTime,
+    pub(super) from: NodeId,
+    pub(super) to: NodeId,
+    pub(super) packet: Packet,
+}
+
+impl TapRecord {
+    pub(super) fn new(from: NodeId, to: NodeId, packet: Packet) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            from,
+            to,
+            packet,
+        }
+    }
+
+    /// microseconds since the UNIX epoch
+    pub(super) fn micros(&self) -> u64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| u64::try_from(d.as_micros()).unwrap_or(u64::MAX))
+    }
+
+    pub(super) fn packet_type(&self) -> &'static str {
+        packet_type_name(&self.packet.pack_type)
+    }
+
+    pub(super) fn session_id(&self) -> u64 {
+        self.packet.session_id
+    }
+
+    /// fragment index of fragments, acks and nacks
+    pub(super) fn fragment_index(&self) -> Option<u64> {
+        fragment_index(&self.packet.pack_type)
+    }
+}
+
+/// short name of the packet variant, used both for printing and filtering
+pub(super) fn packet_type_name(pack_type: &PacketType) -> &'static str {
+    match pack_type {
+        PacketType::MsgFragment(_) => "fragment",
+        PacketType::Ack(_) => "ack",
+        PacketType::Nack(_) => "nack",
+        PacketType::FloodRequest(_) => "flood_request",
+        PacketType::FloodResponse(_) => "flood_response",
+    }
+}
+
+pub(super) fn fragment_index(pack_type: &PacketType) -> Option<u64> {
+    match pack_type {
+        PacketType::MsgFragment(f) => Some(f.fragment_index),
+        PacketType::Ack(a) => Some(a.fragment_index),
+        PacketType::Nack(n) => Some(n.fragment_index),
+        PacketType::FloodRequest(_) | PacketType::FloodResponse(_) => None,
+    }
+}
+
+/// selects which records reach the sinks, an empty list matches everything
+#[derive(Debug, Clone, Default)]
+pub(super) struct TapFilter {
+    pub(super) nodes: Vec<NodeId>,
+    pub(super) sessions: Vec<u64>,
+    pub(super) types: Vec<String>,
+}
+
+impl TapFilter {
+    pub(super) fn matches(&self, record: &TapRecord) -> bool {
+        (self.nodes.is_empty()
+            || self.nodes.contains(&record.from)
+            || self.nodes.contains(&record.to))
+            && (self.sessions.is_empty() || self.sessions.contains(&record.session_id()))
+            && (self.types.is_empty() || self.types.iter().any(|t| t == record.packet_type()))
+    }
+}
+
+/// destination of the tapped packets
+pub(super) trait TapSink: Send {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// one line per packet, same layout for the log and for text files
+fn describe(record: &TapRecord) -> String {
+    let fragment: String = record
+        .fragment_index()
+        .map_or_else(|| "-".to_owned(), |i| i.to_string());
+    format!(
+        "{} {}->{} {} session={} fragment={}",
+        record.micros(),
+        record.from,
+        record.to,
+        record.packet_type(),
+        record.session_id(),
+        fragment
+    )
+}
+
+/// prints every packet through the logger
+pub(super) struct LogSink;
+
+impl TapSink for LogSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        info!(target: "tap", "{}", describe(record));
+        Ok(())
+    }
+}
+
+/// writes every packet as a line of text
+pub(super) struct TextSink<W: Write + Send> {
+    out: W,
+}
+
+impl TextSink<BufWriter<File>> {
+    pub(super) fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl<W: Write + Send> TapSink for TextSink<W> {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        writeln!(self.out, "{}", describe(record))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// what the links and `Tap::drain` hand to the collector
+enum Message {
+    Record(TapRecord),
+    /// answered once every message sent before has been handled
+    Drain(Sender<()>),
+}
+
+/// central collector of the packets crossing the wrapped links
+#[derive(Clone)]
+pub(super) struct Tap {
+    sink: Sender<Message>,
+}
+
+impl Tap {
+    /// starts the collector thread, which stops once every link is gone
+    pub(super) fn start(filter: TapFilter, sinks: Vec<Box<dyn TapSink>>) -> Self {
+        let (sink, records) = crossbeam_channel::unbounded();
+        thread::spawn(move || collect(&records, &filter, sinks));
+        Self { sink }
+    }
+
+    /// blocks until the sinks got every record of the packets that already
+    /// crossed a link
+    pub(super) fn drain(&self) {
+        let (done, wait) = crossbeam_channel::bounded(1);
+        if self.sink.send(Message::Drain(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// wraps the link `from -> to`: the returned sender must be handed to
+    /// `from` in place of `dest`
+    pub(super) fn link(&self, from: NodeId, to: NodeId, dest: Sender<Packet>) -> Sender<Packet> {
+        let (link_send, link_recv) = crossbeam_channel::unbounded::<Packet>();
+        let sink: Sender<Message> = self.sink.clone();
+        thread::spawn(move || {
+            for packet in link_recv {
+                let _ = sink.send(Message::Record(TapRecord::new(from, to, packet.clone())));
+                if dest.send(packet).is_err() {
+                    break;
+                }
+            }
+        });
+        link_send
+    }
+}
+
+fn collect(records: &Receiver<Message>, filter: &TapFilter, mut sinks: Vec<Box<dyn TapSink>>) {
+    for message in records.iter() {
+        let record: TapRecord = match message {
+            Message::Record(r) if filter.matches(&r) => r,
+            Message::Record(_) => continue,
+            Message::Drain(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+        sinks.retain_mut(|s| match s.record(&record) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Tap sink failed, dropping it: {e}");
+                false
+            }
+        });
+        if sinks.is_empty() {
+            return;
+        }
+        // records come in bursts, flush once the burst is over
+        if records.is_empty() {
+            for s in &mut sinks {
+                let _ = s.flush();
+            }
+        }
+    }
+    for s in &mut sinks {
+        let _ = s.flush();
+    }
+}
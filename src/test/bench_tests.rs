@@ -0,0 +1,122 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use wg_2024::{
+    network::SourceRoutingHeader,
+    packet::{Ack, Fragment, Packet, PacketType, FRAGMENT_DSIZE},
+};
+
+use crate::{
+    bench::{percentile, run_bench_case, BenchOptions, FragmentStats},
+    cli::{parse_command, Command},
+    matrix::Implementations,
+    tap::TapRecord,
+};
+
+fn fragment(hops: Vec<u8>, fragment_index: u64) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id: 3,
+        pack_type: PacketType::MsgFragment(Fragment {
+            fragment_index,
+            total_n_fragments: 2,
+            length: 0,
+            data: [0; FRAGMENT_DSIZE],
+        }),
+    }
+}
+
+#[test]
+fn test_percentile() {
+    let values: Vec<Duration> = (1..=10).map(Duration::from_millis).collect();
+    assert_eq!(percentile(&values, 50), Some(Duration::from_millis(5)));
+    assert_eq!(percentile(&values, 90), Some(Duration::from_millis(9)));
+    assert_eq!(percentile(&values, 99), Some(Duration::from_millis(10)));
+    assert_eq!(percentile(&values, 0), Some(Duration::from_millis(1)));
+    assert_eq!(percentile(&[], 50), None);
+}
+
+#[test]
+fn test_fragment_stats() {
+    let mut stats = FragmentStats::default();
+    let route: Vec<u8> = vec![11, 0, 1, 12];
+    // fragment 0 goes through, fragment 1 is sent twice
+    for (from, to, index) in [
+        (11, 0, 0),
+        (0, 1, 0),
+        (1, 12, 0),
+        (11, 0, 1),
+        (11, 0, 1),
+        (0, 1, 1),
+        (1, 12, 1),
+    ] {
+        stats.add(&TapRecord::new(from, to, fragment(route.clone(), index)));
+    }
+    // only fragments are counted
+    let ack = Packet {
+        pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+        ..fragment(vec![12, 1, 0, 11], 0)
+    };
+    stats.add(&TapRecord::new(12, 1, ack));
+
+    assert_eq!(stats.sent, 3);
+    assert_eq!(stats.delivered, 2);
+    assert!((stats.overhead() - 0.5).abs() < f64::EPSILON);
+    assert!(stats.first_fragment(12, UNIX_EPOCH).is_some());
+    assert!(stats.first_fragment(1, UNIX_EPOCH).is_none());
+    // only the fragments after the request count
+    let later: SystemTime = SystemTime::now() + Duration::from_secs(1);
+    assert!(stats.first_fragment(12, later).is_none());
+}
+
+#[test]
+fn test_bench_options() {
+    let args = [
+        "bench",
+        "--implementation",
+        "rust_roveri",
+        "--pdr",
+        "0.1",
+        "--pdr",
+        "0.3",
+        "--requests",
+        "4",
+    ]
+    .map(String::from);
+    let Ok(Command::Bench(opts)) = parse_command(args) else {
+        panic!("bench options not parsed");
+    };
+    assert_eq!(opts.implementations, vec![2]);
+    assert_eq!(opts.pdrs, vec![0.1, 0.3]);
+    assert_eq!(opts.requests, 4);
+    assert!(opts.output.is_none());
+}
+
+#[test]
+fn test_bench_case_args() {
+    let opts = BenchOptions {
+        requests: 4,
+        timeout: Duration::from_secs(30),
+        ..BenchOptions::default()
+    };
+    let Ok(Command::BenchCase(case)) = parse_command(opts.case_args(2, 0.3)) else {
+        panic!("bench case options not parsed");
+    };
+    assert_eq!(case.implementation, 2);
+    assert!((case.pdr - 0.3).abs() < f32::EPSILON);
+    assert_eq!(case.bench.topology, opts.topology);
+    assert_eq!(case.bench.requests, 4);
+    assert_eq!(case.bench.timeout, opts.timeout);
+}
+
+#[test]
+fn test_bench_no_requests() {
+    let opts = BenchOptions {
+        requests: 0,
+        timeout: Duration::from_secs(10),
+        ..BenchOptions::default()
+    };
+    let result = run_bench_case(&opts, Implementations::Single(0), 0.).unwrap();
+    assert_eq!(result.requested, 0);
+    assert_eq!(result.completed, 0);
+    assert_eq!(result.failed, 0);
+}
@@ -0,0 +1,159 @@
+use std::io::Cursor;
+
+use wg_2024::{
+    network::SourceRoutingHeader,
+    packet::{
+        Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
+        FRAGMENT_DSIZE,
+    },
+};
+
+use crate::{
+    capture::{decode_packet, encode_packet, encode_record, CaptureReader, CAPTURE_MAGIC},
+    inspect::{nack_summary, reassemble, NackSummary},
+    tap::TapRecord,
+};
+
+fn packet(hops: Vec<u8>, session_id: u64, pack_type: PacketType) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id,
+        pack_type,
+    }
+}
+
+fn fragment(fragment_index: u64, total_n_fragments: u64, text: &str) -> PacketType {
+    let mut data = [0; FRAGMENT_DSIZE];
+    data[..text.len()].copy_from_slice(text.as_bytes());
+    PacketType::MsgFragment(Fragment {
+        fragment_index,
+        total_n_fragments,
+        length: u8::try_from(text.len()).unwrap(),
+        data,
+    })
+}
+
+fn nack(nack_type: NackType) -> PacketType {
+    PacketType::Nack(Nack {
+        fragment_index: 2,
+        nack_type,
+    })
+}
+
+fn every_packet_type() -> Vec<Packet> {
+    vec![
+        packet(vec![11, 0, 1, 12], 1, fragment(0, 2, "hello")),
+        packet(
+            vec![12, 1, 0, 11],
+            1,
+            PacketType::Ack(Ack { fragment_index: 0 }),
+        ),
+        packet(vec![1, 0, 11], 1, nack(NackType::Dropped)),
+        packet(vec![1, 0, 11], 1, nack(NackType::ErrorInRouting(3))),
+        packet(vec![1, 0, 11], 1, nack(NackType::DestinationIsDrone)),
+        packet(vec![1, 0, 11], 1, nack(NackType::UnexpectedRecipient(4))),
+        packet(
+            vec![],
+            9,
+            PacketType::FloodRequest(FloodRequest {
+                flood_id: 5,
+                initiator_id: 11,
+                path_trace: vec![(11, NodeType::Client), (0, NodeType::Drone)],
+            }),
+        ),
+        packet(
+            vec![0, 11],
+            9,
+            PacketType::FloodResponse(FloodResponse {
+                flood_id: 5,
+                path_trace: vec![(11, NodeType::Client), (12, NodeType::Server)],
+            }),
+        ),
+    ]
+}
+
+#[test]
+fn test_packet_roundtrip() {
+    for p in every_packet_type() {
+        let mut buf: Vec<u8> = Vec::new();
+        encode_packet(&p, &mut buf).unwrap();
+        let decoded: Packet = decode_packet(&buf).unwrap();
+        assert_eq!(format!("{p:?}"), format!("{decoded:?}"));
+        assert!(decode_packet(&buf[..buf.len() - 1]).is_err());
+    }
+}
+
+#[test]
+fn test_capture_reader() {
+    let records: Vec<TapRecord> = every_packet_type()
+        .into_iter()
+        .map(|p| TapRecord::new(p.routing_header.hops.first().copied().unwrap_or(11), 0, p))
+        .collect();
+    let mut file: Vec<u8> = CAPTURE_MAGIC.to_vec();
+    file.push(1);
+    for r in &records {
+        encode_record(r, &mut file).unwrap();
+    }
+
+    let read: Vec<TapRecord> = CaptureReader::new(Cursor::new(file))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read.len(), records.len());
+    for (a, b) in read.iter().zip(&records) {
+        assert_eq!(a.micros(), b.micros());
+        assert_eq!((a.from, a.to), (b.from, b.to));
+        assert_eq!(format!("{:?}", a.packet), format!("{:?}", b.packet));
+    }
+
+    assert!(CaptureReader::new(Cursor::new(b"garbage".to_vec())).is_err());
+
+    // a corrupt length is rejected before anything is allocated
+    let mut corrupt: Vec<u8> = CAPTURE_MAGIC.to_vec();
+    corrupt.push(1);
+    corrupt.extend_from_slice(&[0; 10]);
+    corrupt.extend_from_slice(&u32::MAX.to_be_bytes());
+    let mut reader = CaptureReader::new(Cursor::new(corrupt)).unwrap();
+    assert!(reader.next().unwrap().is_err());
+}
+
+#[test]
+fn test_reassemble() {
+    let records: Vec<TapRecord> = vec![
+        TapRecord::new(11, 0, packet(vec![11, 0, 12], 3, fragment(1, 2, " world"))),
+        TapRecord::new(0, 12, packet(vec![11, 0, 12], 3, fragment(1, 2, " world"))),
+        TapRecord::new(11, 0, packet(vec![11, 0, 12], 3, fragment(0, 2, "hello"))),
+        TapRecord::new(12, 0, packet(vec![12, 0, 11], 4, fragment(0, 3, "x"))),
+    ];
+    let messages = reassemble(&records);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].session_id, 3);
+    assert_eq!(messages[0].destination, Some(12));
+    assert!(messages[0].is_complete());
+    assert_eq!(messages[0].data(), b"hello world");
+    assert!(!messages[1].is_complete());
+}
+
+#[test]
+fn test_nack_summary() {
+    let records: Vec<TapRecord> = vec![
+        TapRecord::new(1, 0, packet(vec![1, 0, 11], 1, nack(NackType::Dropped))),
+        // same nack on the next hop
+        TapRecord::new(0, 11, packet(vec![1, 0, 11], 1, nack(NackType::Dropped))),
+        TapRecord::new(1, 0, packet(vec![1, 0, 11], 2, nack(NackType::Dropped))),
+        TapRecord::new(
+            0,
+            11,
+            packet(vec![0, 11], 2, nack(NackType::ErrorInRouting(5))),
+        ),
+    ];
+    let summary = nack_summary(&records);
+    assert_eq!(
+        summary[&1],
+        NackSummary {
+            dropped: 2,
+            ..NackSummary::default()
+        }
+    );
+    assert_eq!(summary[&0].error_in_routing, 1);
+}
@@ -0,0 +1,69 @@
+use wg_2024::config::Config;
+
+use crate::{
+    chaos::{apply, ChaosState, Fault},
+    cli::{parse_command, Command},
+    random::rng_for,
+    test::{correct_config, test_seed},
+    topology_utils::topology_error,
+};
+
+#[test]
+fn test_apply_crash() {
+    let mut config: Config = correct_config();
+    apply(&mut config, &Fault::Crash { drone: 1 });
+    assert!(config.drone.iter().all(|d| d.id != 1));
+    assert!(config
+        .drone
+        .iter()
+        .all(|d| !d.connected_node_ids.contains(&1)));
+    assert!(config
+        .client
+        .iter()
+        .all(|c| !c.connected_drone_ids.contains(&1)));
+    assert!(config
+        .server
+        .iter()
+        .all(|s| !s.connected_drone_ids.contains(&1)));
+}
+
+#[test]
+fn test_topology_stays_valid() {
+    let mut state = ChaosState::new(correct_config(), 0.5);
+    let mut rng = rng_for(test_seed(), "chaos");
+    let mut down: Vec<(u8, u8)> = Vec::new();
+    for _ in 0..100 {
+        let faults: Vec<Fault> = state.tick(&mut rng);
+        let config: &Config = &state.config;
+        assert_eq!(
+            topology_error(&config.drone, &config.client, &config.server),
+            None
+        );
+        // links taken down come back on the next tick, if both ends survived
+        for (a, b) in down.drain(..) {
+            let alive = |id: u8| config.drone.iter().any(|d| d.id == id);
+            if alive(a) && alive(b) {
+                assert!(faults.contains(&Fault::LinkUp { a, b }));
+            }
+        }
+        for f in faults {
+            match f {
+                Fault::LinkDown { a, b } => down.push((a, b)),
+                Fault::SetPacketDropRate { pdr, .. } => assert!((0.0..0.5).contains(&pdr)),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn test_chaos_options() {
+    let args = ["--chaos", "2.5", "--chaos-log", "faults.jsonl"].map(String::from);
+    let Ok(Command::Run(opts)) = parse_command(args) else {
+        panic!("run options not parsed");
+    };
+    let chaos = opts.chaos.unwrap();
+    assert_eq!(chaos.interval.as_millis(), 2500);
+    assert!((chaos.max_pdr - 0.5).abs() < f32::EPSILON);
+    assert!(chaos.log.is_some());
+}
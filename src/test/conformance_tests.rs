@@ -0,0 +1,29 @@
+use crate::{
+    cli::{parse_command, Command},
+    conformance::CHECKS,
+    network::drone_factory,
+};
+
+/// the basics every implementation gets right, the full report is
+/// given by the `conformance` subcommand
+#[test]
+fn test_forwarding() {
+    for (name, factory) in drone_factory() {
+        for (check, run) in CHECKS {
+            if matches!(check, "forward_fragment" | "forward_ack") {
+                assert_eq!(run(factory), Ok(()), "{name} fails {check}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_conformance_options() {
+    let args = ["conformance", "--implementation", "rusteze"].map(String::from);
+    let Ok(Command::Conformance(opts)) = parse_command(args) else {
+        panic!("conformance options not parsed");
+    };
+    assert_eq!(opts.implementations, vec![5]);
+    let args = ["conformance", "--implementation", "nope"].map(String::from);
+    assert!(parse_command(args).is_err());
+}
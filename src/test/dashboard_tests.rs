@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tungstenite::Message;
+use wg_2024::{
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use super::correct_config;
+use crate::{
+    dashboard::{serve, Dashboard, DashboardSink, Topology},
+    network::{drone_factory, Network, NodeKind},
+    tap::{TapRecord, TapSink},
+};
+
+fn empty_network() -> Network {
+    Network {
+        drones: HashMap::new(),
+        web_clients: HashMap::new(),
+        chat_clients: HashMap::new(),
+        servers: HashMap::new(),
+    }
+}
+
+#[test]
+fn test_topology() {
+    let implementations: HashMap<u8, usize> = HashMap::from([(0, 0), (1, 1), (2, 2), (3, 3)]);
+    let topology = Topology::new(&correct_config(), &empty_network(), &implementations);
+    assert_eq!(
+        topology.links,
+        vec![(0, 1), (0, 3), (0, 11), (1, 2), (2, 3), (2, 12), (3, 12)]
+    );
+    assert_eq!(topology.nodes.len(), 6);
+    let drone = topology.nodes.iter().find(|n| n.id == 1).unwrap();
+    assert_eq!(drone.kind, NodeKind::Drone.as_str());
+    assert_eq!(drone.implementation, Some(drone_factory()[1].0));
+    assert_eq!(drone.pdr, Some(0.8));
+    let client = topology.nodes.iter().find(|n| n.id == 11).unwrap();
+    assert_eq!(client.kind, NodeKind::WebClient.as_str());
+    assert_eq!(client.pdr, None);
+}
+
+fn next_update(socket: &mut tungstenite::WebSocket<impl Read + Write>) -> Value {
+    match socket.read().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        m => panic!("unexpected message {m:?}"),
+    }
+}
+
+#[test]
+fn test_dashboard_stream() {
+    let dashboard: Arc<Dashboard> = Arc::default();
+    dashboard.set_topology(Topology::new(
+        &correct_config(),
+        &empty_network(),
+        &HashMap::new(),
+    ));
+    let port: u16 = serve(Arc::clone(&dashboard), 0).unwrap();
+
+    // a browser that never sends its request doesn't hold up the others
+    let _idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET /topology HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("\"links\":[[0,1]"));
+
+    let url: String = format!("ws://127.0.0.1:{port}/ws");
+    let (mut socket, _) = tungstenite::connect(url.as_str()).unwrap();
+    let topology: Value = next_update(&mut socket);
+    assert_eq!(topology["type"], "topology");
+    assert_eq!(topology["nodes"].as_array().unwrap().len(), 6);
+
+    let packet = Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![11, 0],
+        },
+        session_id: 9,
+        pack_type: PacketType::Ack(Ack { fragment_index: 4 }),
+    };
+    DashboardSink(Arc::clone(&dashboard))
+        .record(&TapRecord::new(11, 0, packet))
+        .unwrap();
+    let update: Value = next_update(&mut socket);
+    assert_eq!(update["type"], "packet");
+    assert_eq!(update["from"], 11);
+    assert_eq!(update["to"], 0);
+    assert_eq!(update["session"], 9);
+}
@@ -0,0 +1,64 @@
+use wg_2024::config::{Client, Config, Drone};
+
+use super::correct_config;
+use crate::{
+    diff::{diff_configs, PdrChange},
+    network::NodeKind,
+    random::Assignment,
+};
+
+#[test]
+fn test_diff_same_config() {
+    let diff = diff_configs(&correct_config(), &correct_config(), Assignment::ById, 0);
+    assert!(diff.is_empty());
+    assert_eq!(diff.human(), "no differences\n");
+}
+
+#[test]
+fn test_diff_changes() {
+    let old: Config = correct_config();
+    let mut new: Config = correct_config();
+    // 0 - 2 link added on one side only, it still counts
+    new.drone[0].connected_node_ids.push(2);
+    new.drone[1].pdr = 0.3;
+    // 3 becomes a client of 0 and 2
+    let three = new.drone.remove(3);
+    new.client.push(Client {
+        id: 3,
+        connected_drone_ids: vec![0, 2],
+    });
+    new.server[0].connected_drone_ids.retain(|&n| n != 3);
+    new.drone.push(Drone {
+        id: 7,
+        connected_node_ids: vec![three.id],
+        pdr: 0.,
+    });
+
+    let diff = diff_configs(&old, &new, Assignment::ById, 0);
+    assert_eq!(diff.added_nodes.len(), 1);
+    assert_eq!(diff.added_nodes[0].id, 7);
+    assert!(diff.removed_nodes.is_empty());
+    assert_eq!(diff.changed_nodes.len(), 1);
+    assert_eq!(diff.changed_nodes[0].old.kind, NodeKind::Drone);
+    assert_eq!(diff.changed_nodes[0].new.kind, NodeKind::ChatClient);
+    assert_eq!(diff.added_links, vec![(0, 2), (3, 7)]);
+    assert_eq!(diff.removed_links, vec![(3, 12)]);
+    assert_eq!(
+        diff.pdr_changes,
+        vec![PdrChange {
+            id: 1,
+            old: 0.8,
+            new: 0.3
+        }]
+    );
+
+    let human: String = diff.human();
+    assert!(human.contains("+ node 7 drone (getdroned)"));
+    assert!(human.contains("~ node 3: drone (rolling_drone) -> chat_client (chat_client)"));
+    assert!(human.contains("- link 3-12"));
+    assert!(human.contains("~ pdr 1: 0.8 -> 0.3"));
+
+    let json = serde_json::to_value(&diff).unwrap();
+    assert_eq!(json["added_links"], serde_json::json!([[0, 2], [3, 7]]));
+    assert_eq!(json["changed_nodes"][0]["new"]["kind"], "chat_client");
+}
@@ -0,0 +1,74 @@
+use std::fs;
+
+use crate::formatter::format_config;
+
+const MESSY: &str = "\
+# Two drones and their friends
+
+[[server]]
+id = 4
+connected_drone_ids = [2,1]
+
+# the first drone
+[[drone]]
+pdr=0.1   # flaky
+id = 2
+connected_node_ids = [
+    4,  # the server
+    1,
+    3,
+]
+[[client]]
+id = 3
+connected_drone_ids = [2]
+
+
+[[ drone ]]
+id = 1
+connected_node_ids = [4, 2]
+pdr = 0
+# the end
+";
+
+const FORMATTED: &str = "\
+# Two drones and their friends
+
+[[drone]]
+id = 1
+connected_node_ids = [2, 4]
+pdr = 0
+
+# the first drone
+[[drone]]
+id = 2
+# the server
+connected_node_ids = [1, 3, 4]
+pdr = 0.1 # flaky
+
+[[client]]
+id = 3
+connected_drone_ids = [2]
+
+[[server]]
+id = 4
+connected_drone_ids = [1, 2]
+
+# the end
+";
+
+#[test]
+fn test_format_config() {
+    assert_eq!(format_config(MESSY).unwrap(), FORMATTED);
+    assert_eq!(format_config(FORMATTED).unwrap(), FORMATTED);
+    assert!(format_config("[[drone]]\nid = 1\n").is_err());
+}
+
+#[test]
+fn test_format_repo_configs() {
+    for entry in fs::read_dir("config").unwrap() {
+        let path = entry.unwrap().path();
+        let formatted: String = format_config(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        assert_eq!(format_config(&formatted).unwrap(), formatted);
+    }
+}
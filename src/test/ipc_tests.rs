@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::Cursor,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use wg_2024::{
+    controller::{DroneCommand, DroneEvent},
+    network::SourceRoutingHeader,
+    packet::{Fragment, Packet, PacketType, FRAGMENT_DSIZE},
+};
+
+use crate::{
+    ipc::{read_frame, run_node_process, write_frame, Frame, NodeOptions},
+    supervisor::{HostedDrone, ProcessOptions, Supervisor},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn packet(session_id: u64) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![0, 1, 2],
+        },
+        session_id,
+        pack_type: PacketType::MsgFragment(Fragment {
+            fragment_index: 0,
+            total_n_fragments: 1,
+            length: 3,
+            data: [7; FRAGMENT_DSIZE],
+        }),
+    }
+}
+
+#[test]
+fn test_frame_round_trip() {
+    let frames = vec![
+        Frame::Hello(4),
+        Frame::Packet(packet(1)),
+        Frame::Forward(2, packet(2)),
+        Frame::Event(DroneEvent::PacketDropped(packet(3))),
+        Frame::Event(DroneEvent::ControllerShortcut(packet(4))),
+        Frame::AddSender(9),
+        Frame::RemoveSender(9),
+        Frame::SetPacketDropRate(0.25),
+        Frame::Crash,
+    ];
+    let mut buf: Vec<u8> = Vec::new();
+    for f in &frames {
+        write_frame(&mut buf, f).unwrap();
+    }
+    let mut input = Cursor::new(&buf);
+    for f in &frames {
+        let read: Frame = read_frame(&mut input).unwrap().unwrap();
+        assert_eq!(format!("{f:?}"), format!("{read:?}"));
+    }
+    assert!(read_frame(&mut input).unwrap().is_none());
+    // a frame cut in the middle is an error, not the end of the stream
+    assert!(read_frame(&mut Cursor::new(&buf[..buf.len() - 1])).is_err());
+}
+
+#[test]
+fn test_supervised_drone() {
+    let socket = env::temp_dir().join(format!("wg-test-{}-ipc.sock", std::process::id()));
+    let supervisor = Supervisor::bind(&socket, &ProcessOptions::default()).unwrap();
+    let (to_neighbour, neighbour): (Sender<Packet>, Receiver<Packet>) =
+        crossbeam_channel::unbounded();
+    let (events_send, events) = crossbeam_channel::unbounded::<DroneEvent>();
+    let (commands, commands_recv) = crossbeam_channel::unbounded::<DroneCommand>();
+    let (packets, packets_recv) = crossbeam_channel::unbounded::<Packet>();
+    supervisor.register(HostedDrone {
+        id: 1,
+        implementation: 0,
+        pdr: 0.,
+        neighbours: HashMap::from([(2, to_neighbour)]),
+        events: events_send,
+        commands: commands_recv,
+        packets: packets_recv,
+    });
+
+    // sent before the drone connects, kept until it does
+    packets.send(packet(1)).unwrap();
+    let opts = NodeOptions {
+        socket: socket.clone(),
+        id: 1,
+        implementation: 0,
+        pdr: 0.,
+        neighbours: vec![2],
+    };
+    thread::spawn(move || run_node_process(&opts));
+
+    let forwarded: Packet = neighbour.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(forwarded.session_id, 1);
+    assert_eq!(forwarded.routing_header.hop_index, 2);
+    assert!(matches!(
+        events.recv_timeout(TIMEOUT).unwrap(),
+        DroneEvent::PacketSent(p) if p.session_id == 1
+    ));
+
+    // a neighbour added by the controller is reachable through the bridge
+    let (to_new, new) = crossbeam_channel::unbounded::<Packet>();
+    commands.send(DroneCommand::AddSender(3, to_new)).unwrap();
+    commands.send(DroneCommand::RemoveSender(2)).unwrap();
+    // the packet reaches the drone after the commands sent before it
+    let mut p: Packet = packet(2);
+    p.routing_header.hops = vec![0, 1, 3];
+    packets.send(p).unwrap();
+    assert_eq!(new.recv_timeout(TIMEOUT).unwrap().session_id, 2);
+    assert!(neighbour.try_recv().is_err());
+
+    drop(supervisor);
+    assert!(!socket.exists());
+}
+
+#[test]
+fn test_restart_dead_drone() {
+    let socket = env::temp_dir().join(format!("wg-test-{}-restart.sock", std::process::id()));
+    let supervisor = Supervisor::bind(&socket, &ProcessOptions { restart: true }).unwrap();
+    let (events, _events) = crossbeam_channel::unbounded::<DroneEvent>();
+    let (commands, commands_recv) = crossbeam_channel::unbounded::<DroneCommand>();
+    let (_packets, packets_recv) = crossbeam_channel::unbounded::<Packet>();
+    // the test binary has no `node` subcommand, every process it starts
+    // exits at once like a drone dying on its own
+    supervisor.host(HostedDrone {
+        id: 1,
+        implementation: 0,
+        pdr: 0.,
+        neighbours: HashMap::new(),
+        events,
+        commands: commands_recv,
+        packets: packets_recv,
+    });
+    let first: u32 = supervisor.pid(1).unwrap();
+
+    let start = Instant::now();
+    while !matches!(supervisor.pid(1), Some(pid) if pid != first) {
+        assert!(start.elapsed() < TIMEOUT, "drone not restarted");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // a crashed drone is expected to exit and stays down
+    commands.send(DroneCommand::Crash).unwrap();
+    let start = Instant::now();
+    while supervisor.pid(1).is_some() {
+        assert!(start.elapsed() < TIMEOUT, "crashed drone restarted");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    drop(supervisor);
+    assert!(!socket.exists());
+}
@@ -0,0 +1,111 @@
+use std::env;
+
+use flexi_logger::LogSpecification;
+use log::LevelFilter;
+
+use crate::{
+    cli::split_logging_options,
+    logging::{log_dir, module_level, thread_node, tracing_directives, LogFormat, LoggingOptions},
+    network::{node_thread_name, NodeKind},
+};
+
+#[test]
+fn test_thread_node() {
+    for kind in NodeKind::ALL {
+        assert_eq!(thread_node(&node_thread_name(kind, 12)), Some(12));
+    }
+    assert_eq!(thread_node("main"), None);
+    assert_eq!(thread_node("tap-1"), None);
+    assert_eq!(thread_node("drone-x"), None);
+}
+
+#[test]
+fn test_module_level() {
+    let spec = LogSpecification::parse("warn,rusty_drones=debug,rusty_drones::x=error").unwrap();
+    assert_eq!(module_level(&spec, Some("web_client")), LevelFilter::Warn);
+    assert_eq!(
+        module_level(&spec, Some("rusty_drones::drone")),
+        LevelFilter::Debug
+    );
+    assert_eq!(
+        module_level(&spec, Some("rusty_drones::x::y")),
+        LevelFilter::Error
+    );
+    assert_eq!(module_level(&spec, None), LevelFilter::Warn);
+}
+
+#[test]
+fn test_split_logging_options() {
+    let args = [
+        "matrix",
+        "--log-node",
+        "3=debug",
+        "--pdr",
+        "0.1",
+        "--log-dir",
+        "logs",
+        "--log-per-node",
+        "--log-rotate",
+        "2",
+    ]
+    .map(String::from);
+    let (opts, rest) = split_logging_options(args).unwrap();
+    assert_eq!(rest, vec!["matrix", "--pdr", "0.1"]);
+    assert_eq!(opts.nodes.get(&3), Some(&LevelFilter::Debug));
+    assert!(opts.per_node);
+    assert_eq!(opts.rotate, Some(2 * 1024 * 1024));
+
+    assert!(split_logging_options(["--log-per-node".to_owned()]).is_err());
+    assert!(split_logging_options(["--log-node".to_owned(), "3".to_owned()]).is_err());
+    // the size in bytes doesn't fit in a u64
+    let rotate = ["--log-rotate", "17592186044416"].map(String::from);
+    assert_eq!(
+        split_logging_options(rotate).err().as_deref(),
+        Some("Invalid value for --log-rotate: 17592186044416")
+    );
+}
+
+#[test]
+fn test_tracing_directives() {
+    let (opts, _) = split_logging_options(
+        [
+            "--log-format",
+            "json",
+            "--log",
+            "warn",
+            "--log-node",
+            "7=trace",
+            "--log-node",
+            "3=debug",
+        ]
+        .map(String::from),
+    )
+    .unwrap();
+    assert_eq!(opts.format, LogFormat::Json);
+    assert_eq!(
+        tracing_directives(&opts),
+        "warn,[node{id=3}]=debug,[node{id=7}]=trace"
+    );
+
+    let opts = LoggingOptions {
+        spec: Some("info,rusty_drones=debug".to_owned()),
+        ..LoggingOptions::default()
+    };
+    assert_eq!(tracing_directives(&opts), "info,rusty_drones=debug");
+    assert!("xml".parse::<LogFormat>().is_err());
+}
+
+#[test]
+fn test_log_dir() {
+    assert_eq!(log_dir(&LoggingOptions::default()), None);
+    let quiet = LoggingOptions {
+        quiet: true,
+        ..LoggingOptions::default()
+    };
+    assert!(log_dir(&quiet).unwrap().starts_with(env::temp_dir()));
+    let opts = LoggingOptions {
+        dir: Some("logs".into()),
+        ..quiet
+    };
+    assert_eq!(log_dir(&opts), Some("logs".into()));
+}
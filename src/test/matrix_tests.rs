@@ -0,0 +1,66 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    cli::{implementation_index, parse_command, Command},
+    matrix::{
+        run_matrix, run_scenario, Content, Implementations, MatrixOptions, DEFAULT_TOPOLOGIES,
+    },
+    network::drone_factory,
+};
+
+#[test]
+fn test_scenarios() {
+    let opts = MatrixOptions::default();
+    let scenarios = opts.scenarios();
+    // every implementation plus a mixed run, for each topology, PDR and content
+    assert_eq!(
+        scenarios.len(),
+        (drone_factory().len() + 1)
+            * DEFAULT_TOPOLOGIES.len()
+            * opts.pdrs.len()
+            * Content::ALL.len()
+    );
+    assert!(scenarios
+        .iter()
+        .any(|s| s.implementations == Implementations::Mixed(0) && s.content == Content::Media));
+
+    let opts = MatrixOptions {
+        topologies: vec![PathBuf::from("config/tree.toml")],
+        pdrs: vec![0.],
+        implementations: vec![implementation_index("rusty_drones").unwrap()],
+        mixed: Vec::new(),
+        ..MatrixOptions::default()
+    };
+    assert_eq!(opts.scenarios().len(), 3);
+    assert!(implementation_index("not_a_drone").is_err());
+    assert_eq!(implementation_index("3"), Ok(3));
+}
+
+#[test]
+fn test_scenario_args() {
+    for s in MatrixOptions::default().scenarios() {
+        let Ok(Command::Scenario(opts)) = parse_command(s.args()) else {
+            panic!("{s:?} not parsed");
+        };
+        assert_eq!(opts.scenario.implementations, s.implementations);
+        assert_eq!(opts.scenario.topology, s.topology);
+        assert_eq!(opts.scenario.pdr, s.pdr);
+        assert_eq!(opts.scenario.content, s.content);
+    }
+    assert_eq!("chat".parse(), Ok(Content::Chat));
+    assert!("video".parse::<Content>().is_err());
+}
+
+/// the whole matrix takes a while: `cargo test -- --ignored full_matrix`
+#[test]
+#[ignore]
+fn full_matrix() {
+    let opts = MatrixOptions {
+        timeout: Duration::from_secs(20),
+        ..MatrixOptions::default()
+    };
+    // `run_isolated` would start the test binary, the nodes of every
+    // scenario stay around until the end of the tests
+    let (passed, total) = run_matrix(&opts, run_scenario);
+    assert_eq!(passed, total);
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use common::slc_commands::WebClientEvent;
+use wg_2024::{
+    controller::DroneEvent,
+    network::SourceRoutingHeader,
+    packet::{Ack, Nack, NackType, Packet, PacketType},
+};
+
+use crate::{
+    metrics::{serve, Metrics, NodeMetrics},
+    network::{Network, NodeKind},
+};
+
+fn packet(pack_type: PacketType) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2],
+        },
+        session_id: 7,
+        pack_type,
+    }
+}
+
+#[test]
+fn test_count_drone_events() {
+    let mut m = NodeMetrics::default();
+    m.count(&DroneEvent::PacketSent(packet(PacketType::Ack(Ack {
+        fragment_index: 0,
+    }))));
+    m.count(&DroneEvent::PacketSent(packet(PacketType::Nack(Nack {
+        fragment_index: 0,
+        nack_type: NackType::Dropped,
+    }))));
+    m.count(&DroneEvent::PacketDropped(packet(PacketType::Ack(Ack {
+        fragment_index: 0,
+    }))));
+    m.count(&DroneEvent::ControllerShortcut(packet(PacketType::Ack(
+        Ack { fragment_index: 0 },
+    ))));
+    assert_eq!(
+        m,
+        NodeMetrics {
+            packets_sent: 2,
+            packets_dropped: 1,
+            shortcuts: 1,
+            nacks_sent: 1,
+            events: 4,
+            ..NodeMetrics::default()
+        }
+    );
+}
+
+#[test]
+fn test_count_client_events() {
+    let mut m = NodeMetrics::default();
+    m.count(&WebClientEvent::PacketSent(packet(PacketType::Nack(
+        Nack {
+            fragment_index: 0,
+            nack_type: NackType::Dropped,
+        },
+    ))));
+    m.count(&WebClientEvent::Shortcut(packet(PacketType::Ack(Ack {
+        fragment_index: 0,
+    }))));
+    m.count(&WebClientEvent::UnsupportedRequest);
+    assert_eq!(
+        m,
+        NodeMetrics {
+            packets_sent: 1,
+            shortcuts: 1,
+            nacks_sent: 1,
+            events: 3,
+            ..NodeMetrics::default()
+        }
+    );
+}
+
+#[test]
+fn test_metrics_endpoint() {
+    let metrics: Arc<Metrics> = Arc::default();
+    let (cmd, _) = crossbeam_channel::unbounded();
+    let (event_send, event_recv) = crossbeam_channel::unbounded();
+    let (packet_send, packet_recv) = crossbeam_channel::unbounded();
+    let network = Network {
+        drones: HashMap::from([(1, (cmd, event_recv, packet_send, packet_recv))]),
+        web_clients: HashMap::new(),
+        chat_clients: HashMap::new(),
+        servers: HashMap::new(),
+    };
+    metrics.register(&network);
+    let network = metrics.wrap_network(network);
+
+    event_send
+        .send(DroneEvent::PacketDropped(packet(PacketType::Ack(Ack {
+            fragment_index: 0,
+        }))))
+        .unwrap();
+    let forwarded = network.drones[&1].1.recv_timeout(Duration::from_secs(1));
+    assert!(matches!(forwarded, Ok(DroneEvent::PacketDropped(_))));
+    assert_eq!(metrics.get(1).unwrap().packets_dropped, 1);
+
+    let port: u16 = serve(Arc::clone(&metrics), 0).unwrap();
+    // a scraper that never sends its request doesn't hold up the others
+    let _idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("# TYPE wg_packets_dropped_total counter"));
+    assert!(response.contains(&format!(
+        "wg_packets_dropped_total{{node=\"1\",kind=\"{}\"}} 1",
+        NodeKind::Drone.as_str()
+    )));
+}
@@ -1,10 +1,4 @@
-use std::{
-    collections::HashMap,
-    iter::repeat_with,
-    thread::{self, sleep},
-    time::Duration,
-    vec,
-};
+use std::{collections::HashMap, iter::repeat_with, thread, time::Duration, vec};
 
 use ap2024_unitn_cppenjoyers_webservers::{
     servers::{Media, Text},
@@ -12,26 +6,92 @@ use ap2024_unitn_cppenjoyers_webservers::{
 };
 use common::{
     slc_commands::{
-        self, ServerCommand, ServerEvent, TextMediaResponse, WebClientCommand, WebClientEvent,
+        self, ChatClientCommand, ChatClientEvent, ServerCommand, ServerEvent, TextMediaResponse,
+        WebClientCommand, WebClientEvent,
     },
     Client, Server,
 };
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{never, Receiver, Sender};
 use rand::{thread_rng, Rng};
 use web_client::web_client::WebBrowser;
 use wg_2024::{
     config::{Client as ClientConfig, Config, Drone as DroneConfig, Server as ServerConfig},
     controller::{DroneCommand, DroneEvent},
     drone::Drone as DroneTrait,
-    packet::Packet,
+    network::{NodeId, SourceRoutingHeader},
+    packet::{Ack, Packet, PacketType},
+};
+
+use crate::{
+    network::{client_kind, Network, NodeKind},
+    random::rng_for,
+    router::{NodeEvent, ShortcutRouter},
 };
 
+/// how long a test waits for a requested file
+const FILE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[cfg(test)]
+mod bench_tests;
+#[cfg(test)]
+mod capture_tests;
+#[cfg(test)]
+mod chaos_tests;
 #[cfg(test)]
 mod config_tests;
 #[cfg(test)]
+mod conformance_tests;
+#[cfg(test)]
+mod dashboard_tests;
+#[cfg(test)]
+mod diff_tests;
+#[cfg(test)]
 mod drone_tests;
 #[cfg(test)]
+mod formatter_tests;
+#[cfg(test)]
+mod ipc_tests;
+#[cfg(test)]
+mod logging_tests;
+#[cfg(test)]
+mod matrix_tests;
+#[cfg(test)]
+mod metrics_tests;
+#[cfg(test)]
+mod pcap_tests;
+#[cfg(test)]
+mod random_tests;
+#[cfg(test)]
+mod recorder_tests;
+#[cfg(test)]
+mod repl_tests;
+#[cfg(test)]
+mod replay_tests;
+#[cfg(test)]
+mod respawn_tests;
+#[cfg(test)]
+mod router_tests;
+#[cfg(test)]
+mod rpc_tests;
+#[cfg(test)]
+mod schema_tests;
+#[cfg(test)]
+mod tap_tests;
+#[cfg(test)]
 mod topology_tests;
+#[cfg(test)]
+mod tui_tests;
+
+/// seed of the randomness of a test, taken from `WG_SEED` when set;
+/// it's printed so that a failing run can be reproduced
+fn test_seed() -> u64 {
+    let seed: u64 = std::env::var("WG_SEED")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| thread_rng().gen());
+    println!("test seed: {seed} (rerun with WG_SEED={seed})");
+    seed
+}
 
 /// get a coorectr configuration for the network
 fn correct_config() -> Config {
@@ -71,6 +131,69 @@ fn correct_config() -> Config {
     }
 }
 
+/// command receiver and event sender of each node of a `test_network`
+type NodeEnds<C, E> = HashMap<NodeId, (Receiver<C>, Sender<E>)>;
+
+/// the ends of the channels of a `test_network` held by the test
+#[derive(Default)]
+struct NetworkEnds {
+    drones: NodeEnds<DroneCommand, DroneEvent>,
+    web_clients: NodeEnds<WebClientCommand, WebClientEvent>,
+    chat_clients: NodeEnds<ChatClientCommand, ChatClientEvent>,
+    servers: NodeEnds<ServerCommand, ServerEvent>,
+}
+
+/// channels of the nodes of `config` without any node running behind
+/// them, clients are web or chat clients as when spawned
+fn test_network(config: &Config) -> (Network, NetworkEnds) {
+    fn add<C, E>(
+        id: NodeId,
+        channels: &mut HashMap<NodeId, (Sender<C>, Receiver<E>, Sender<Packet>, Receiver<Packet>)>,
+        ends: &mut NodeEnds<C, E>,
+    ) {
+        let (cmd_send, cmd_recv) = crossbeam_channel::unbounded();
+        let (event_send, event_recv) = crossbeam_channel::unbounded();
+        let (packet_send, packet_recv) = crossbeam_channel::unbounded();
+        channels.insert(id, (cmd_send, event_recv, packet_send, packet_recv));
+        ends.insert(id, (cmd_recv, event_send));
+    }
+
+    let mut network = Network {
+        drones: HashMap::new(),
+        web_clients: HashMap::new(),
+        chat_clients: HashMap::new(),
+        servers: HashMap::new(),
+    };
+    let mut ends = NetworkEnds::default();
+    for d in &config.drone {
+        add(d.id, &mut network.drones, &mut ends.drones);
+    }
+    for c in &config.client {
+        if client_kind(c.id) == NodeKind::ChatClient {
+            add(c.id, &mut network.chat_clients, &mut ends.chat_clients);
+        } else {
+            add(c.id, &mut network.web_clients, &mut ends.web_clients);
+        }
+    }
+    for s in &config.server {
+        add(s.id, &mut network.servers, &mut ends.servers);
+    }
+    (network, ends)
+}
+
+/// a packet along `hops`, at the second of them
+fn packet(hops: Vec<NodeId>, session_id: u64, pack_type: PacketType) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id,
+        pack_type,
+    }
+}
+
+fn ack(fragment_index: u64) -> PacketType {
+    PacketType::Ack(Ack { fragment_index })
+}
+
 /// instanciates a double chain topology for testing
 fn instanciate_testing_topology<T: DroneTrait + 'static>() -> (
     Vec<Sender<DroneCommand>>,
@@ -82,9 +205,6 @@ fn instanciate_testing_topology<T: DroneTrait + 'static>() -> (
     Sender<WebClientCommand>,
     Receiver<WebClientEvent>,
 ) {
-    // env::set_var("RUST_LOG", "info");
-    // let _ = env_logger::try_init();
-
     let (st_events, st_eventr) = crossbeam_channel::unbounded();
     let (st_ctrls, st_ctrlr) = crossbeam_channel::unbounded();
     let (sm_events, sm_eventr) = crossbeam_channel::unbounded();
@@ -103,6 +223,7 @@ fn instanciate_testing_topology<T: DroneTrait + 'static>() -> (
         repeat_with(|| crossbeam_channel::unbounded())
             .take(10)
             .collect();
+    let mut rng = rng_for(test_seed(), "pdr");
     let drone_nbrs: [Vec<u8>; 10] = [
         vec![1, 5],
         vec![0, 2, 6],
@@ -133,7 +254,7 @@ fn instanciate_testing_topology<T: DroneTrait + 'static>() -> (
             drone_channels[i as usize].1.clone(),
             map,
             // if too high, the test might fail for no reason besides me being unlucky
-            Rng::gen_range(&mut thread_rng(), 0., 0.5),
+            rng.gen_range(0., 0.5),
         );
         thread::spawn(move || drone.run());
     }
@@ -199,51 +320,41 @@ fn generic_full_file_request(
     file: String,
     check_file: impl Fn(TextMediaResponse) -> (),
 ) {
-    sleep(Duration::from_secs(1));
+    let router = ShortcutRouter::default()
+        .drone(devents)
+        .web_client(12, cctrl.clone(), cevents)
+        .server(11, stctrl, never())
+        .server(13, smctrl, never());
+    // let the clients discover the topology
+    router.wait_for(|_| false, Duration::from_secs(1));
     let _ = cctrl.send(WebClientCommand::AskServersTypes);
-    let mut _flag: bool = false;
-    loop {
-        if let Ok(e) = devents.try_recv() {
-            match e {
-                DroneEvent::ControllerShortcut(p) => {
-                    let &idx = p.routing_header.hops.last().unwrap();
-                    if idx == 11 {
-                        let _ = stctrl.send(ServerCommand::Shortcut(p));
-                    } else if idx == 12 {
-                        let _ = cctrl.send(WebClientCommand::Shortcut(p));
-                    } else if idx == 13 {
-                        let _ = smctrl.send(ServerCommand::Shortcut(p));
-                    }
+    let event: Option<NodeEvent> = router.wait_for(
+        |e: &NodeEvent| match e {
+            NodeEvent::WebClient(_, WebClientEvent::ServersTypes(list)) => {
+                if *list
+                    == HashMap::from([
+                        (11, slc_commands::ServerType::FileServer),
+                        (13, slc_commands::ServerType::MediaServer),
+                    ])
+                {
+                    let _ = cctrl.send(WebClientCommand::AskListOfFiles(11));
                 }
-                _ => {}
+                false
             }
-        }
-        if let Ok(e) = cevents.try_recv() {
-            match e {
-                WebClientEvent::FileFromClient(r, _) => {
-                    check_file(r);
-                    _flag = true;
-                    break;
-                }
-                WebClientEvent::ServersTypes(list) => {
-                    if list
-                        == HashMap::from([
-                            (11, slc_commands::ServerType::FileServer),
-                            (13, slc_commands::ServerType::MediaServer),
-                        ])
-                    {
-                        let _ = cctrl.send(WebClientCommand::AskListOfFiles(11));
-                    }
-                }
-                WebClientEvent::ListOfFiles(_, _) => {
-                    let _ = cctrl.send(WebClientCommand::RequestFile(file.clone(), 11));
-                }
-                WebClientEvent::UnsupportedRequest => {
-                    panic!();
-                }
-                _ => {}
+            NodeEvent::WebClient(_, WebClientEvent::ListOfFiles(_, _)) => {
+                let _ = cctrl.send(WebClientCommand::RequestFile(file.clone(), 11));
+                false
             }
-        }
+            NodeEvent::WebClient(
+                _,
+                WebClientEvent::FileFromClient(..) | WebClientEvent::UnsupportedRequest,
+            ) => true,
+            _ => false,
+        },
+        FILE_TIMEOUT,
+    );
+    match event {
+        Some(NodeEvent::WebClient(_, WebClientEvent::FileFromClient(r, _))) => check_file(r),
+        e => panic!("no file received: {e:?}"),
     }
-    assert!(_flag);
 }
@@ -0,0 +1,58 @@
+use wg_2024::{
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use crate::{
+    pcap::{udp_frame, Encapsulation, PcapWriter, LINKTYPE_USER0, WG_UDP_PORT},
+    tap::{TapRecord, TapSink},
+};
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_udp_frame() {
+    let frame: Vec<u8> = udp_frame(3, 12, &[1, 2, 3]).unwrap();
+    assert_eq!(frame.len(), 20 + 8 + 3);
+    assert_eq!(&frame[12..16], &[10, 0, 0, 3]);
+    assert_eq!(&frame[16..20], &[10, 0, 0, 12]);
+    assert_eq!(&frame[20..22], &WG_UDP_PORT.to_be_bytes());
+    // a correct header sums to 0xFFFF, checksum included
+    let sum: u32 = frame[..20]
+        .chunks(2)
+        .map(|w| u32::from(u16::from_be_bytes([w[0], w[1]])))
+        .sum();
+    assert_eq!((sum & 0xFFFF) + (sum >> 16), 0xFFFF);
+}
+
+#[test]
+fn test_pcapng_blocks() {
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let mut w = PcapWriter::new(&mut out, Encapsulation::User).unwrap();
+        let packet = Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![1, 2],
+            },
+            session_id: 7,
+            pack_type: PacketType::Ack(Ack { fragment_index: 3 }),
+        };
+        w.record(&TapRecord::new(1, 2, packet)).unwrap();
+    }
+
+    // walk the blocks checking that both length fields agree
+    let mut types: Vec<u32> = Vec::new();
+    let mut off: usize = 0;
+    while off < out.len() {
+        let len: usize = u32_at(&out, off + 4) as usize;
+        assert_eq!(len % 4, 0);
+        assert_eq!(u32_at(&out, off + len - 4) as usize, len);
+        types.push(u32_at(&out, off));
+        off += len;
+    }
+    assert_eq!(types, [0x0A0D_0D0A, 1, 6]);
+    assert_eq!(u16::from_le_bytes([out[36], out[37]]), LINKTYPE_USER0);
+}
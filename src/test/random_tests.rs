@@ -0,0 +1,49 @@
+use rand::Rng;
+use wg_2024::config::Config;
+
+use crate::{
+    random::{assign_implementations, randomize_pdr, rng_for, Assignment},
+    test::correct_config,
+};
+
+#[test]
+fn test_same_seed_same_choices() {
+    let (mut a, mut b) = (rng_for(42, "pdr"), rng_for(42, "pdr"));
+    let a: Vec<u32> = (0..10).map(|_| a.gen()).collect();
+    let b: Vec<u32> = (0..10).map(|_| b.gen()).collect();
+    assert_eq!(a, b);
+    // a sequence, not the same value over and over
+    assert!(a.iter().any(|&v| v != a[0]));
+
+    let mut x = rng_for(42, "pdr");
+    let mut y = rng_for(42, "implementations");
+    let mut z = rng_for(43, "pdr");
+    let (x, y, z): (u64, u64, u64) = (x.gen(), y.gen(), z.gen());
+    assert_ne!(x, y);
+    assert_ne!(x, z);
+}
+
+#[test]
+fn test_assign_implementations() {
+    let Config { drone, .. } = correct_config();
+
+    let by_id = assign_implementations(&drone, 3, Assignment::ById, &mut rng_for(0, "impl"));
+    assert_eq!(by_id[&0], 0);
+    assert_eq!(by_id[&3], 0);
+    assert_eq!(by_id[&2], 2);
+
+    let r1 = assign_implementations(&drone, 10, Assignment::Random, &mut rng_for(7, "impl"));
+    let r2 = assign_implementations(&drone, 10, Assignment::Random, &mut rng_for(7, "impl"));
+    assert_eq!(r1, r2);
+    assert!(r1.values().all(|&i| i < 10));
+}
+
+#[test]
+fn test_randomize_pdr() {
+    let Config { mut drone, .. } = correct_config();
+    randomize_pdr(&mut drone, 0.3, &mut rng_for(1, "pdr"));
+    assert!(drone.iter().all(|d| (0.0..0.3).contains(&d.pdr)));
+
+    randomize_pdr(&mut drone, 0., &mut rng_for(1, "pdr"));
+    assert!(drone.iter().all(|d| d.pdr == 0.));
+}
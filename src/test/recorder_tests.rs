@@ -0,0 +1,55 @@
+use std::{env, fs, time::Duration};
+
+use wg_2024::{
+    controller::DroneEvent,
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use crate::{
+    network::NodeKind,
+    recorder::{EventRecorder, EventSummary},
+};
+
+fn ack() -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2],
+        },
+        session_id: 7,
+        pack_type: PacketType::Ack(Ack { fragment_index: 3 }),
+    }
+}
+
+#[test]
+fn test_event_summary() {
+    let e = DroneEvent::PacketDropped(ack());
+    assert_eq!(e.variant(), "PacketDropped");
+    assert_eq!(e.summary(), "ack session=7 fragment=3 hops=[1, 2]");
+}
+
+#[test]
+fn test_recorder_forwards_and_logs() {
+    let path = env::temp_dir().join(format!("wg_events_{}.jsonl", std::process::id()));
+    let (recorder, writer) = EventRecorder::start(&path).unwrap();
+    let (send, recv) = crossbeam_channel::unbounded();
+    let wrapped = recorder.wrap(4, NodeKind::Drone, recv);
+
+    send.send(DroneEvent::PacketSent(ack())).unwrap();
+    assert!(matches!(
+        wrapped.recv_timeout(Duration::from_secs(1)),
+        Ok(DroneEvent::PacketSent(_))
+    ));
+
+    // every handle to the writer is gone once the node stops
+    drop(send);
+    drop(recorder);
+    writer.join().unwrap();
+    let log: String = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    let line: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    assert_eq!(line["node"], 4);
+    assert_eq!(line["kind"], "drone");
+    assert_eq!(line["event"], "PacketSent");
+}
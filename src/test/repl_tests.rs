@@ -0,0 +1,142 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use common::slc_commands::ChatClientCommand;
+use crossbeam_channel::Receiver;
+use wg_2024::controller::DroneCommand;
+
+use super::correct_config;
+use crate::{
+    network::Network,
+    repl::{complete, parse_line, Console, ReplCommand},
+    rpc::Rpc,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+#[test]
+fn test_parse_line() {
+    assert_eq!(parse_line("  "), Ok(None));
+    assert_eq!(parse_line("crash 3"), Ok(Some(ReplCommand::Crash(3))));
+    assert_eq!(
+        parse_line("respawn 3 rusty_drones"),
+        Ok(Some(ReplCommand::Respawn(3, Some(6))))
+    );
+    assert_eq!(
+        parse_line("respawn 3"),
+        Ok(Some(ReplCommand::Respawn(3, None)))
+    );
+    assert_eq!(parse_line("pdr 2 0.4"), Ok(Some(ReplCommand::Pdr(2, 0.4))));
+    assert_eq!(
+        parse_line("unlink 1 5"),
+        Ok(Some(ReplCommand::Unlink(1, 5)))
+    );
+    assert_eq!(
+        parse_line("web 4 get 6 file.html"),
+        Ok(Some(ReplCommand::WebGet(4, 6, "file.html".to_owned())))
+    );
+    assert_eq!(
+        parse_line("chat 5 register 6"),
+        Ok(Some(ReplCommand::ChatRegister(5, 6)))
+    );
+    assert_eq!(
+        parse_line("events tail"),
+        Ok(Some(ReplCommand::EventsTail(20)))
+    );
+    assert_eq!(
+        parse_line("events tail 5"),
+        Ok(Some(ReplCommand::EventsTail(5)))
+    );
+    assert!(parse_line("crash").is_err());
+    assert!(parse_line("pdr 2 high").is_err());
+    assert!(parse_line("respawn 3 paper_plane").is_err());
+    assert!(parse_line("web 4 fly").is_err());
+    assert!(parse_line("dance").is_err());
+}
+
+#[test]
+fn test_complete() {
+    let ids = [1, 12, 3];
+    assert_eq!(complete("un", &ids), (0, vec!["unlink".to_owned()]));
+    assert_eq!(
+        complete("crash 1", &ids),
+        (6, vec!["1".to_owned(), "12".to_owned()])
+    );
+    assert_eq!(complete("web 12 f", &ids), (7, vec!["files".to_owned()]));
+    assert_eq!(complete("web 12 files ", &ids).1.len(), 3);
+    assert_eq!(complete("show t", &ids), (5, vec!["topology".to_owned()]));
+    assert!(complete("pdr 3 ", &ids).1.is_empty());
+}
+
+#[test]
+fn test_console_commands() {
+    let mut network = Network {
+        drones: HashMap::new(),
+        web_clients: HashMap::new(),
+        chat_clients: HashMap::new(),
+        servers: HashMap::new(),
+    };
+    let mut commands: HashMap<u8, Receiver<DroneCommand>> = HashMap::new();
+    for id in 0..4 {
+        let (cmd_send, cmd_recv) = crossbeam_channel::unbounded();
+        let (_, event_recv) = crossbeam_channel::unbounded();
+        let (packet_send, packet_recv) = crossbeam_channel::unbounded();
+        network
+            .drones
+            .insert(id, (cmd_send, event_recv, packet_send, packet_recv));
+        commands.insert(id, cmd_recv);
+    }
+    let (cmd_send, chat_commands) = crossbeam_channel::unbounded();
+    let (_, event_recv) = crossbeam_channel::unbounded();
+    let (packet_send, packet_recv) = crossbeam_channel::unbounded();
+    network
+        .chat_clients
+        .insert(11, (cmd_send, event_recv, packet_send, packet_recv));
+    let rpc: Arc<Rpc> = Arc::new(Rpc::new(&network, &correct_config()));
+    let console = Console::new(Arc::clone(&rpc));
+
+    console.execute(&ReplCommand::Link(1, 3)).unwrap();
+    assert!(matches!(
+        commands[&1].recv_timeout(TIMEOUT),
+        Ok(DroneCommand::AddSender(3, _))
+    ));
+    assert!(matches!(
+        commands[&3].recv_timeout(TIMEOUT),
+        Ok(DroneCommand::AddSender(1, _))
+    ));
+
+    console.execute(&ReplCommand::Crash(0)).unwrap();
+    assert!(matches!(
+        commands[&0].recv_timeout(TIMEOUT),
+        Ok(DroneCommand::Crash)
+    ));
+    for n in [1, 3] {
+        assert!(matches!(
+            commands[&n].recv_timeout(TIMEOUT),
+            Ok(DroneCommand::RemoveSender(0))
+        ));
+    }
+    let topology: String = console.execute(&ReplCommand::ShowTopology).unwrap();
+    assert!(topology.contains("crashed"));
+
+    console.execute(&ReplCommand::ChatRegister(11, 12)).unwrap();
+    assert!(matches!(
+        chat_commands.recv_timeout(TIMEOUT),
+        Ok(ChatClientCommand::RegisterToServer(12))
+    ));
+
+    assert!(console.execute(&ReplCommand::ChatRegister(2, 12)).is_err());
+    assert!(console.execute(&ReplCommand::WebServers(2)).is_err());
+    assert!(console.execute(&ReplCommand::Pdr(9, 0.1)).is_err());
+
+    // a crash sent by another controller shows up in the console
+    let network: Network = rpc.track_network(network);
+    network.drones[&2].0.send(DroneCommand::Crash).unwrap();
+    assert!(matches!(
+        commands[&2].recv_timeout(TIMEOUT),
+        Ok(DroneCommand::Crash)
+    ));
+    let topology: String = console.execute(&ReplCommand::ShowTopology).unwrap();
+    assert!(topology
+        .lines()
+        .any(|l| l.starts_with("  2 ") && l.ends_with("crashed")));
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, env, fs, time::Duration};
+
+use common::slc_commands::ChatClientCommand;
+use wg_2024::controller::DroneCommand;
+
+use crate::{
+    network::{NodeKind, Setup},
+    random::Assignment,
+    replay::{read_recording, CommandRecord, CommandRecorder, RecordedCommand, Replayable},
+};
+
+#[test]
+fn test_drone_command_roundtrip() {
+    let (packet_send, _packet_recv) = crossbeam_channel::unbounded();
+    let senders = HashMap::from([(3, packet_send.clone())]);
+    let commands = [
+        DroneCommand::AddSender(3, packet_send),
+        DroneCommand::RemoveSender(3),
+        DroneCommand::SetPacketDropRate(0.25),
+        DroneCommand::Crash,
+    ];
+    for c in commands {
+        let recorded: RecordedCommand = c.record();
+        let json: String = serde_json::to_string(&recorded).unwrap();
+        let parsed: RecordedCommand = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, recorded);
+        let replayed: DroneCommand = DroneCommand::replay(&parsed, &senders).unwrap();
+        assert_eq!(replayed.record(), recorded);
+    }
+
+    // the sender of an unknown node can't be rebuilt
+    let missing = RecordedCommand::AddSender { neighbour: 9 };
+    assert!(DroneCommand::replay(&missing, &senders).is_none());
+    assert!(DroneCommand::replay(&RecordedCommand::Shortcut, &senders).is_none());
+}
+
+#[test]
+fn test_chat_command_roundtrip() {
+    let (packet_send, _packet_recv) = crossbeam_channel::unbounded();
+    let senders = HashMap::from([(3, packet_send.clone())]);
+    let commands = [
+        ChatClientCommand::AddSender(3, packet_send),
+        ChatClientCommand::RemoveSender(3),
+        ChatClientCommand::AskServersTypes,
+        ChatClientCommand::RegisterToServer(6),
+        ChatClientCommand::AskListOfClients(6),
+        ChatClientCommand::SendMessage(6, 7, "hi".to_owned()),
+    ];
+    for c in commands {
+        let recorded: RecordedCommand = c.record();
+        let json: String = serde_json::to_string(&recorded).unwrap();
+        let parsed: RecordedCommand = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, recorded);
+        let replayed = ChatClientCommand::replay(&parsed, &senders).unwrap();
+        assert_eq!(replayed.record(), recorded);
+    }
+    assert_eq!(
+        serde_json::to_value(ChatClientCommand::SendMessage(6, 7, "hi".to_owned()).record())
+            .unwrap(),
+        serde_json::json!({"command": "send_message", "server": 6, "to": 7, "message": "hi"})
+    );
+}
+
+#[test]
+fn test_recording_file() {
+    let path = env::temp_dir().join(format!("wg_recording_{}.jsonl", std::process::id()));
+    let setup = Setup {
+        config: fs::read_to_string("config/config.toml").unwrap(),
+        seed: 42,
+        assignment: Assignment::Random,
+        random_pdr: Some(0.2),
+    };
+    let (recorder, writer) = CommandRecorder::start(&path, &setup).unwrap();
+    let (node_send, node_recv) = crossbeam_channel::unbounded();
+    let commands = recorder.wrap(2, NodeKind::Drone, node_send);
+
+    commands.send(DroneCommand::SetPacketDropRate(0.5)).unwrap();
+    commands.send(DroneCommand::Crash).unwrap();
+    assert!(matches!(
+        node_recv.recv_timeout(Duration::from_secs(1)),
+        Ok(DroneCommand::SetPacketDropRate(_))
+    ));
+    assert!(matches!(
+        node_recv.recv_timeout(Duration::from_secs(1)),
+        Ok(DroneCommand::Crash)
+    ));
+    drop(commands);
+    drop(recorder);
+    writer.join().unwrap();
+
+    let (read_setup, records): (Setup, Vec<CommandRecord>) = read_recording(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(read_setup.seed, 42);
+    assert_eq!(read_setup.assignment, Assignment::Random);
+    assert_eq!(read_setup.config, setup.config);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].node, 2);
+    assert_eq!(records[0].kind, NodeKind::Drone);
+    assert_eq!(records[1].command, RecordedCommand::Crash);
+    assert!(records[0].at <= records[1].at);
+
+    // same setup, same network
+    let (c1, i1) = read_setup.prepare().unwrap();
+    let (c2, i2) = setup.prepare().unwrap();
+    assert_eq!(i1, i2);
+    assert!(c1.drone.iter().zip(&c2.drone).all(|(a, b)| a.pdr == b.pdr));
+}
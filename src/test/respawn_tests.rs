@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use wg_2024::{
+    controller::DroneEvent,
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use crossbeam_channel::Sender;
+
+use super::correct_config;
+use crate::{
+    network::{spawn_network_on, DroneHost},
+    replay::RecordedCommand,
+    rpc::Rpc,
+    tap::{Tap, TapFilter, TapRecord, TapSink},
+};
+
+/// forwards every record to the test
+struct ChannelSink(Sender<TapRecord>);
+
+impl TapSink for ChannelSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        let _ = self.0.send(record.clone());
+        Ok(())
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn test_respawn_crashed_drone() {
+    let config = correct_config();
+    let implementations: HashMap<u8, usize> = config
+        .drone
+        .iter()
+        .map(|d| (d.id, usize::from(d.id)))
+        .collect();
+    let (network, respawner) =
+        spawn_network_on(&config, &implementations, None, DroneHost::Threads);
+    let rpc = Rpc::new(&network, &config).with_respawner(Some(Arc::new(respawner)));
+    let (_, events, packets, _) = &network.drones[&0];
+
+    assert!(rpc.respawn(0, None).is_err());
+    assert!(rpc.respawn(12, None).is_err());
+
+    rpc.send_command(0, &RecordedCommand::Crash).unwrap();
+    // 1 goes down while 0 is away, the new 0 doesn't link to it
+    rpc.send_command(1, &RecordedCommand::Crash).unwrap();
+    let start = Instant::now();
+    let neighbours: Vec<u8> = loop {
+        match rpc.respawn(0, Some(6)) {
+            Ok(n) => break n,
+            Err(e) if start.elapsed() > TIMEOUT => panic!("{}", e.message),
+            Err(_) => sleep(Duration::from_millis(20)),
+        }
+    };
+    assert_eq!(neighbours, vec![3, 11]);
+    assert!(rpc.neighbours(3).unwrap().contains(&0));
+
+    // the new drone answers on the channels the controller already has
+    let ack = Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![3, 0, 11],
+        },
+        session_id: 4242,
+        pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+    };
+    packets.send(ack).unwrap();
+    let start = Instant::now();
+    loop {
+        let event = events
+            .recv_timeout(TIMEOUT.saturating_sub(start.elapsed()))
+            .unwrap();
+        if matches!(event, DroneEvent::PacketSent(p) if p.session_id == 4242) {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_links_go_through_the_tap() {
+    let config = correct_config();
+    let implementations: HashMap<u8, usize> = config.drone.iter().map(|d| (d.id, 0)).collect();
+    let (records_send, records) = crossbeam_channel::unbounded();
+    let tap = Tap::start(
+        TapFilter::default(),
+        vec![Box::new(ChannelSink(records_send))],
+    );
+    let (_network, respawner) =
+        spawn_network_on(&config, &implementations, Some(&tap), DroneHost::Threads);
+
+    // unknown nodes get no link
+    let links = respawner.links(0, &[1, 99]);
+    assert_eq!(links.keys().copied().collect::<Vec<u8>>(), vec![1]);
+
+    let ack = Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![0, 1],
+        },
+        session_id: 4343,
+        pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+    };
+    links[&1].send(ack).unwrap();
+    let start = Instant::now();
+    loop {
+        let record: TapRecord = records
+            .recv_timeout(TIMEOUT.saturating_sub(start.elapsed()))
+            .unwrap();
+        if record.session_id() == 4343 {
+            assert_eq!((record.from, record.to), (0, 1));
+            break;
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use common::slc_commands::{ServerCommand, ServerEvent, WebClientCommand, WebClientEvent};
+use crossbeam_channel::unbounded;
+use wg_2024::{
+    controller::DroneEvent,
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use crate::router::{NodeEvent, ShortcutRouter};
+
+fn ack(hops: Vec<u8>) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id: 1,
+        pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+    }
+}
+
+#[test]
+fn test_route_shortcuts() {
+    let (dsend, devents) = unbounded::<DroneEvent>();
+    let (wcmd, wcmdr) = unbounded::<WebClientCommand>();
+    let (wsend, wevents) = unbounded::<WebClientEvent>();
+    let (scmd, scmdr) = unbounded::<ServerCommand>();
+    let (_ssend, sevents) = unbounded::<ServerEvent>();
+    let router = ShortcutRouter::default()
+        .drone(devents)
+        .web_client(12, wcmd, wevents)
+        .server(11, scmd, sevents);
+
+    dsend
+        .send(DroneEvent::ControllerShortcut(ack(vec![11, 0, 12])))
+        .unwrap();
+    dsend
+        .send(DroneEvent::ControllerShortcut(ack(vec![12, 0, 11])))
+        .unwrap();
+    wsend.send(WebClientEvent::UnsupportedRequest).unwrap();
+    let mut shortcuts: usize = 0;
+    let e: Option<NodeEvent> = router.wait_for(
+        |e: &NodeEvent| {
+            if matches!(e, NodeEvent::Drone(_)) {
+                shortcuts += 1;
+            }
+            shortcuts == 2
+        },
+        Duration::from_secs(1),
+    );
+    assert!(e.is_some());
+    assert!(matches!(
+        wcmdr.try_recv(),
+        Ok(WebClientCommand::Shortcut(_))
+    ));
+    assert!(matches!(scmdr.try_recv(), Ok(ServerCommand::Shortcut(_))));
+    assert!(!router.route(ack(vec![12, 0, 99])));
+    assert_eq!(router.counts(), BTreeMap::from([("ack", 2)]));
+}
+
+#[test]
+fn test_wait_for_timeout() {
+    let (_dsend, devents) = unbounded::<DroneEvent>();
+    let router = ShortcutRouter::default().drone(devents);
+    let start: Instant = Instant::now();
+    assert!(router
+        .wait_for(|_| true, Duration::from_millis(100))
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // every node gone, no need to wait for the timeout
+    let (dsend, devents) = unbounded::<DroneEvent>();
+    drop(dsend);
+    let router = ShortcutRouter::default().drone(devents);
+    let start: Instant = Instant::now();
+    assert!(router.wait_for(|_| true, Duration::from_secs(10)).is_none());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn test_spawn() {
+    let (dsend, devents) = unbounded::<DroneEvent>();
+    let (wcmd, wcmdr) = unbounded::<WebClientCommand>();
+    let (wsend, wevents) = unbounded::<WebClientEvent>();
+    let router = ShortcutRouter::default()
+        .drone(devents)
+        .web_client(12, wcmd, wevents);
+    router.spawn();
+
+    // other events are drained
+    wsend.send(WebClientEvent::UnsupportedRequest).unwrap();
+    dsend
+        .send(DroneEvent::ControllerShortcut(ack(vec![11, 0, 12])))
+        .unwrap();
+    let c = wcmdr.recv_timeout(Duration::from_secs(1));
+    assert!(matches!(c, Ok(WebClientCommand::Shortcut(_))));
+    assert_eq!(router.counts(), BTreeMap::from([("ack", 1)]));
+}
+
+#[test]
+fn test_route_node_shortcuts() {
+    let (wcmd, wcmdr) = unbounded::<WebClientCommand>();
+    let (wsend, wevents) = unbounded::<WebClientEvent>();
+    let (scmd, scmdr) = unbounded::<ServerCommand>();
+    let (ssend, sevents) = unbounded::<ServerEvent>();
+    let router = ShortcutRouter::default()
+        .web_client(12, wcmd, wevents)
+        .server(11, scmd, sevents);
+
+    // a server's ack to the client and the client's ack back, both given
+    // up on by the drones
+    ssend
+        .send(ServerEvent::Shortcut(ack(vec![11, 0, 12])))
+        .unwrap();
+    let e: Option<NodeEvent> = router.wait_for(
+        |e: &NodeEvent| matches!(e, NodeEvent::Server(..)),
+        Duration::from_secs(1),
+    );
+    assert!(e.is_some());
+    assert!(matches!(
+        wcmdr.try_recv(),
+        Ok(WebClientCommand::Shortcut(_))
+    ));
+
+    router.spawn();
+    wsend
+        .send(WebClientEvent::Shortcut(ack(vec![12, 0, 11])))
+        .unwrap();
+    let c = scmdr.recv_timeout(Duration::from_secs(1));
+    assert!(matches!(c, Ok(ServerCommand::Shortcut(_))));
+    assert_eq!(router.counts(), BTreeMap::from([("ack", 2)]));
+}
@@ -0,0 +1,222 @@
+use std::{sync::Arc, time::Duration};
+
+use common::slc_commands::ChatClientCommand;
+use crossbeam_channel::Sender;
+use serde_json::Value;
+use wg_2024::{
+    config::Config,
+    controller::{DroneCommand, DroneEvent},
+};
+
+use super::{ack, correct_config, packet, test_network};
+use crate::{
+    network::NodeKind,
+    recorder::EventSummary,
+    rpc::{Rpc, RpcEndpoint},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn call(rpc: &Rpc, out: &Sender<String>, request: &str) -> Value {
+    serde_json::from_str(&rpc.handle(0, out, request).unwrap()).unwrap()
+}
+
+#[test]
+fn test_rpc_endpoint() {
+    assert_eq!("stdio".parse(), Ok(RpcEndpoint::Stdio));
+    assert_eq!(
+        "/tmp/wg.sock".parse(),
+        Ok(RpcEndpoint::Unix("/tmp/wg.sock".into()))
+    );
+}
+
+#[test]
+fn test_rpc_methods() {
+    let (network, ends) = test_network(&correct_config());
+    let rpc = Rpc::new(&network, &correct_config());
+    let (out, _lines) = crossbeam_channel::unbounded();
+    let commands = &ends.drones[&0].0;
+
+    let nodes = call(
+        &rpc,
+        &out,
+        r#"{"jsonrpc":"2.0","id":1,"method":"list_nodes"}"#,
+    );
+    assert_eq!(nodes["id"], 1);
+    assert_eq!(nodes["result"].as_array().unwrap().len(), 6);
+    assert_eq!(nodes["result"][4]["kind"], NodeKind::ChatClient.as_str());
+
+    let request = r#"{"jsonrpc":"2.0","id":2,"method":"neighbours","params":{"node":0}}"#;
+    assert_eq!(
+        call(&rpc, &out, request)["result"],
+        serde_json::json!([1, 3, 11])
+    );
+
+    let request = r#"{"jsonrpc":"2.0","id":3,"method":"send_command",
+        "params":{"node":0,"command":"set_packet_drop_rate","pdr":0.5}}"#;
+    assert_eq!(call(&rpc, &out, request)["result"], true);
+    assert!(matches!(
+        commands.recv_timeout(TIMEOUT),
+        Ok(DroneCommand::SetPacketDropRate(pdr)) if (pdr - 0.5).abs() < f32::EPSILON
+    ));
+
+    let request = r#"{"jsonrpc":"2.0","id":4,"method":"send_command",
+        "params":{"node":0,"command":"add_sender","neighbour":12}}"#;
+    assert_eq!(call(&rpc, &out, request)["result"], true);
+    assert!(matches!(
+        commands.recv_timeout(TIMEOUT),
+        Ok(DroneCommand::AddSender(12, _))
+    ));
+    let request = r#"{"jsonrpc":"2.0","id":5,"method":"neighbours","params":{"node":0}}"#;
+    assert_eq!(
+        call(&rpc, &out, request)["result"],
+        serde_json::json!([1, 3, 11, 12])
+    );
+
+    // drones don't ask for files
+    let request = r#"{"jsonrpc":"2.0","id":6,"method":"send_command",
+        "params":{"node":0,"command":"ask_servers_types"}}"#;
+    assert_eq!(call(&rpc, &out, request)["error"]["code"], -32000);
+    let request = r#"{"jsonrpc":"2.0","id":7,"method":"send_command","params":{"node":0}}"#;
+    assert_eq!(call(&rpc, &out, request)["error"]["code"], -32602);
+    let request = r#"{"jsonrpc":"2.0","id":8,"method":"fly"}"#;
+    assert_eq!(call(&rpc, &out, request)["error"]["code"], -32601);
+    let parse_error = call(&rpc, &out, "{");
+    assert_eq!(parse_error["error"]["code"], -32700);
+    assert_eq!(parse_error["id"], Value::Null);
+
+    // notifications get no response
+    assert!(rpc
+        .handle(0, &out, r#"{"jsonrpc":"2.0","method":"list_nodes"}"#)
+        .is_none());
+}
+
+#[test]
+fn test_rpc_chat_command() {
+    let (network, ends) = test_network(&correct_config());
+    let rpc = Rpc::new(&network, &correct_config());
+    let (out, _lines) = crossbeam_channel::unbounded();
+
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"send_command",
+        "params":{"node":11,"command":"register_to_server","server":12}}"#;
+    assert_eq!(call(&rpc, &out, request)["result"], true);
+    assert!(matches!(
+        ends.chat_clients[&11].0.recv_timeout(TIMEOUT),
+        Ok(ChatClientCommand::RegisterToServer(12))
+    ));
+    let request = r#"{"jsonrpc":"2.0","id":2,"method":"send_command",
+        "params":{"node":11,"command":"send_message","server":12,"to":13,"message":"hi"}}"#;
+    assert_eq!(call(&rpc, &out, request)["result"], true);
+    assert!(matches!(
+        ends.chat_clients[&11].0.recv_timeout(TIMEOUT),
+        Ok(ChatClientCommand::SendMessage(12, 13, m)) if m == "hi"
+    ));
+}
+
+#[test]
+fn test_rpc_subscribe() {
+    let (network, ends) = test_network(&correct_config());
+    let events = &ends.drones[&0].1;
+    let rpc = Arc::new(Rpc::new(&network, &correct_config()));
+    let network = rpc.recorder().wrap_network(network);
+    let (out, lines) = crossbeam_channel::unbounded();
+    call(
+        &rpc,
+        &out,
+        r#"{"jsonrpc":"2.0","id":1,"method":"subscribe"}"#,
+    );
+
+    let event = DroneEvent::PacketSent(packet(vec![11, 0], 3, ack(0)));
+    let variant: String = event.variant();
+    events.send(event).unwrap();
+    // the controller still gets the event
+    assert!(network.drones[&0].1.recv_timeout(TIMEOUT).is_ok());
+    let notification: Value = serde_json::from_str(&lines.recv_timeout(TIMEOUT).unwrap()).unwrap();
+    assert_eq!(notification["method"], "event");
+    assert_eq!(notification["params"]["node"], 0);
+    assert_eq!(notification["params"]["event"], variant);
+
+    call(
+        &rpc,
+        &out,
+        r#"{"jsonrpc":"2.0","id":2,"method":"unsubscribe"}"#,
+    );
+    events
+        .send(DroneEvent::PacketSent(packet(vec![11, 0], 3, ack(0))))
+        .unwrap();
+    assert!(network.drones[&0].1.recv_timeout(TIMEOUT).is_ok());
+    assert!(lines.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn test_rpc_save_config() {
+    let (network, _ends) = test_network(&correct_config());
+    let rpc = Rpc::new(&network, &correct_config());
+    let path = std::env::temp_dir().join(format!("wg-test-{}-saved.toml", std::process::id()));
+    let (out, _lines) = crossbeam_channel::unbounded();
+    let save = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"save_config","params":{{"path":{:?}}}}}"#,
+        path.display().to_string()
+    );
+
+    let request = r#"{"jsonrpc":"2.0","id":2,"method":"send_command",
+        "params":{"node":0,"command":"set_packet_drop_rate","pdr":0.5}}"#;
+    call(&rpc, &out, request);
+    assert_eq!(call(&rpc, &out, &save)["result"], true);
+    let saved: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let mut expected: Config = correct_config();
+    expected.drone[0].pdr = 0.5;
+    assert_eq!(format!("{saved:?}"), format!("{expected:?}"));
+
+    // 3 still links to 0, the topology isn't bidirectional any more
+    let request = r#"{"jsonrpc":"2.0","id":3,"method":"send_command",
+        "params":{"node":0,"command":"remove_sender","neighbour":3}}"#;
+    call(&rpc, &out, request);
+    assert_eq!(call(&rpc, &out, &save)["error"]["code"], -32000);
+
+    let request = r#"{"jsonrpc":"2.0","id":4,"method":"send_command",
+        "params":{"node":0,"command":"crash"}}"#;
+    call(&rpc, &out, request);
+    let current: Config = rpc.current_config();
+    assert!(current.drone.iter().all(|d| d.id != 0));
+    assert_eq!(current.drone[0].connected_node_ids, vec![2]);
+    assert!(current.client[0].connected_drone_ids.is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+/// commands the controller sends on its own channels change the topology
+/// as much as those sent through the API
+#[test]
+fn test_rpc_tracks_controller_commands() {
+    let (network, ends) = test_network(&correct_config());
+    let rpc = Arc::new(Rpc::new(&network, &correct_config()));
+    let network = rpc.track_network(network);
+
+    network.drones[&3]
+        .0
+        .send(DroneCommand::SetPacketDropRate(0.25))
+        .unwrap();
+    network.drones[&1]
+        .0
+        .send(DroneCommand::RemoveSender(2))
+        .unwrap();
+    network.drones[&2]
+        .0
+        .send(DroneCommand::RemoveSender(1))
+        .unwrap();
+    network.drones[&1].0.send(DroneCommand::Crash).unwrap();
+    // the topology is updated before the command is forwarded
+    assert!(ends.drones[&3].0.recv_timeout(TIMEOUT).is_ok());
+    assert!(ends.drones[&2].0.recv_timeout(TIMEOUT).is_ok());
+    for _ in 0..2 {
+        assert!(ends.drones[&1].0.recv_timeout(TIMEOUT).is_ok());
+    }
+
+    let current: Config = rpc.current_config();
+    assert!(current.drone.iter().all(|d| d.id != 1));
+    let three = current.drone.iter().find(|d| d.id == 3).unwrap();
+    assert!((three.pdr - 0.25).abs() < f32::EPSILON);
+    let two = current.drone.iter().find(|d| d.id == 2).unwrap();
+    assert_eq!(two.connected_node_ids, vec![3, 12]);
+    assert_eq!(rpc.neighbours(0).unwrap(), vec![1, 3, 11]);
+}
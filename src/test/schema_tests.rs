@@ -0,0 +1,139 @@
+use std::fs;
+
+use serde_json::Value;
+use wg_2024::config::Config;
+
+use crate::{
+    cli::{parse_command, Command},
+    schema::topology_schema,
+    topology_utils::topology_error,
+};
+
+/// checks the keys and the bounds of `value` against `schema`, enough of
+/// JSON Schema for the one we produce
+fn conforms(value: &Value, schema: &Value) -> Result<(), String> {
+    match schema["type"].as_str() {
+        Some("object") => {
+            let object = value.as_object().ok_or("not an object")?;
+            for key in schema["required"].as_array().unwrap() {
+                if !object.contains_key(key.as_str().unwrap()) {
+                    return Err(format!("missing {key}"));
+                }
+            }
+            for (key, v) in object {
+                let property = schema["properties"]
+                    .get(key)
+                    .ok_or_else(|| format!("unexpected {key}"))?;
+                conforms(v, property).map_err(|e| format!("{key}: {e}"))?;
+            }
+        }
+        Some("array") => {
+            let items = value.as_array().ok_or("not an array")?;
+            let len = items.len() as u64;
+            if schema["minItems"].as_u64().is_some_and(|min| len < min)
+                || schema["maxItems"].as_u64().is_some_and(|max| len > max)
+            {
+                return Err(format!("{len} items"));
+            }
+            for item in items {
+                conforms(item, &schema["items"])?;
+            }
+        }
+        Some("integer" | "number") => {
+            let n: f64 = value.as_f64().ok_or("not a number")?;
+            if schema["type"] == "integer" && !value.is_u64() && !value.is_i64() {
+                return Err(format!("{n} isn't an integer"));
+            }
+            if n < schema["minimum"].as_f64().unwrap() || n > schema["maximum"].as_f64().unwrap() {
+                return Err(format!("{n} out of bounds"));
+            }
+        }
+        other => return Err(format!("unknown type {other:?}")),
+    }
+    Ok(())
+}
+
+fn toml_as_json(text: &str) -> Value {
+    serde_json::to_value(toml::from_str::<toml::Value>(text).unwrap()).unwrap()
+}
+
+#[test]
+fn test_schema_structure() {
+    let schema: Value = topology_schema();
+    assert_eq!(
+        schema["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    let drone = &schema["properties"]["drone"]["items"];
+    let mut required: Vec<&str> = drone["required"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|k| k.as_str().unwrap())
+        .collect();
+    required.sort_unstable();
+    assert_eq!(required, ["connected_node_ids", "id", "pdr"]);
+    assert_eq!(drone["additionalProperties"], false);
+    let client = &schema["properties"]["client"]["items"]["properties"];
+    assert_eq!(client["connected_drone_ids"]["maxItems"], 2);
+    let server = &schema["properties"]["server"]["items"]["properties"];
+    assert_eq!(server["connected_drone_ids"]["minItems"], 2);
+}
+
+/// the schema never rejects a topology the initializer accepts
+#[test]
+fn test_repo_configs_conform() {
+    let schema: Value = topology_schema();
+    for entry in fs::read_dir("config").unwrap() {
+        let path = entry.unwrap().path();
+        let text: String = fs::read_to_string(&path).unwrap();
+        let config: Config = toml::from_str(&text).unwrap();
+        if topology_error(&config.drone, &config.client, &config.server).is_some() {
+            continue;
+        }
+        conforms(&toml_as_json(&text), &schema)
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    }
+}
+
+#[test]
+fn test_schema_catches_mistakes() {
+    let schema: Value = topology_schema();
+    let valid = "\
+[[drone]]
+id = 1
+connected_node_ids = [2, 3]
+pdr = 0.1
+
+[[client]]
+id = 2
+connected_drone_ids = [1]
+
+[[server]]
+id = 3
+connected_drone_ids = [1, 4]
+";
+    assert!(conforms(&toml_as_json(valid), &schema).is_ok());
+    for mistake in [
+        valid.replace("pdr = 0.1", "pdr = 1.5"),
+        valid.replace("pdr = 0.1", "prd = 0.1"),
+        valid.replace("id = 2", "id = 256"),
+        valid.replace("[1, 4]", "[1]"),
+        valid.replace("[[client]]", "[[clients]]"),
+    ] {
+        assert!(
+            conforms(&toml_as_json(&mistake), &schema).is_err(),
+            "{mistake}"
+        );
+    }
+}
+
+#[test]
+fn test_parse_schema_command() {
+    let args = ["schema", "--output", "topology.schema.json"].map(String::from);
+    let Ok(Command::Schema(opts)) = parse_command(args) else {
+        panic!("schema not parsed");
+    };
+    assert_eq!(opts.output.unwrap().to_str(), Some("topology.schema.json"));
+    assert!(parse_command(["schema", "--json"].map(String::from)).is_err());
+}
@@ -0,0 +1,92 @@
+use std::{io, time::Duration};
+
+use crossbeam_channel::Sender;
+use wg_2024::{
+    network::SourceRoutingHeader,
+    packet::{Ack, Packet, PacketType},
+};
+
+use crate::tap::{Tap, TapFilter, TapRecord, TapSink};
+
+/// forwards every record to the test
+struct ChannelSink(Sender<TapRecord>);
+
+impl TapSink for ChannelSink {
+    fn record(&mut self, record: &TapRecord) -> io::Result<()> {
+        let _ = self.0.send(record.clone());
+        Ok(())
+    }
+}
+
+fn ack(session_id: u64, fragment_index: u64) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2],
+        },
+        session_id,
+        pack_type: PacketType::Ack(Ack { fragment_index }),
+    }
+}
+
+#[test]
+fn test_tap_link_forwards_and_records() {
+    let (records_send, records) = crossbeam_channel::unbounded();
+    let tap = Tap::start(
+        TapFilter::default(),
+        vec![Box::new(ChannelSink(records_send))],
+    );
+    let (dest_send, dest) = crossbeam_channel::unbounded();
+    let link = tap.link(1, 2, dest_send);
+
+    link.send(ack(7, 3)).unwrap();
+
+    let forwarded: Packet = dest.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(forwarded.session_id, 7);
+    let record: TapRecord = records.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!((record.from, record.to), (1, 2));
+    assert_eq!(record.packet_type(), "ack");
+    assert_eq!(record.session_id(), 7);
+    assert_eq!(record.fragment_index(), Some(3));
+}
+
+#[test]
+fn test_tap_filter() {
+    let record = TapRecord::new(1, 2, ack(7, 3));
+    assert!(TapFilter::default().matches(&record));
+
+    let mut filter = TapFilter {
+        nodes: vec![2],
+        ..TapFilter::default()
+    };
+    assert!(filter.matches(&record));
+    filter.sessions = vec![8];
+    assert!(!filter.matches(&record));
+    filter.sessions = vec![7];
+    filter.types = vec!["fragment".to_owned()];
+    assert!(!filter.matches(&record));
+    filter.types.push("ack".to_owned());
+    assert!(filter.matches(&record));
+}
+
+#[test]
+fn test_tap_drain() {
+    let (records_send, records) = crossbeam_channel::unbounded();
+    let tap = Tap::start(
+        TapFilter::default(),
+        vec![Box::new(ChannelSink(records_send))],
+    );
+    let (dest_send, dest) = crossbeam_channel::unbounded();
+    let link = tap.link(1, 2, dest_send);
+
+    for i in 0..100 {
+        link.send(ack(7, i)).unwrap();
+    }
+    for _ in 0..100 {
+        dest.recv_timeout(Duration::from_secs(1)).unwrap();
+    }
+    tap.drain();
+
+    // every forwarded packet was recorded before drain returned
+    assert_eq!(records.len(), 100);
+}
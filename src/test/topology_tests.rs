@@ -1,7 +1,7 @@
 use std::fs;
 use wg_2024::config::Config;
 
-use crate::check_topology_constraints;
+use crate::topology_utils::{check_topology_constraints, config_to_toml};
 
 #[test]
 fn double_chain() {
@@ -61,8 +61,6 @@ fn subnet2() {
 
 #[test]
 fn star() {
-    // env::set_var("RUST_LOG", "info");
-    // env_logger::init();
     let config_data: String =
         fs::read_to_string("config/star.toml").expect("Unable to read config file");
     // having our structs implement the Deserialize trait allows us to use the toml::from_str function to deserialize the config file into each of them
@@ -88,3 +86,27 @@ fn butterfly() {
 
     assert!(check_topology_constraints(&drone, &client, &server));
 }
+
+#[test]
+fn config_to_toml_is_canonical() {
+    let config_data: String =
+        fs::read_to_string("config/tree.toml").expect("Unable to read config file");
+    let Config {
+        drone,
+        client,
+        server,
+    }: Config = toml::from_str(&config_data).expect("Unable to parse TOML");
+
+    let written: String = config_to_toml(&drone, &client, &server);
+    let again: Config = toml::from_str(&written).expect("Unable to parse written TOML");
+    assert_eq!(
+        config_to_toml(&again.drone, &again.client, &again.server),
+        written
+    );
+    assert!(again.drone.windows(2).all(|w| w[0].id < w[1].id));
+    assert!(again
+        .drone
+        .iter()
+        .all(|d| d.connected_node_ids.windows(2).all(|w| w[0] < w[1])));
+    assert_eq!(again.drone.len(), drone.len());
+}
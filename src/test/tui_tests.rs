@@ -0,0 +1,85 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use ratatui::{
+    backend::TestBackend,
+    crossterm::event::{KeyCode, KeyEvent},
+    Terminal,
+};
+use wg_2024::controller::DroneCommand;
+
+use super::correct_config;
+use crate::{
+    metrics::Metrics,
+    network::Network,
+    repl::{Console, EVENT_HISTORY},
+    rpc::Rpc,
+    tui::{App, Flow},
+};
+
+fn type_line(app: &mut App, console: &Console, line: &str) -> Flow {
+    for c in line.chars() {
+        app.key(KeyEvent::from(KeyCode::Char(c)), console);
+    }
+    app.key(KeyEvent::from(KeyCode::Enter), console)
+}
+
+#[test]
+fn test_tui() {
+    let mut network = Network {
+        drones: HashMap::new(),
+        web_clients: HashMap::new(),
+        chat_clients: HashMap::new(),
+        servers: HashMap::new(),
+    };
+    let (cmd_send, commands) = crossbeam_channel::unbounded();
+    let (_, event_recv) = crossbeam_channel::unbounded();
+    let (packet_send, packet_recv) = crossbeam_channel::unbounded();
+    network
+        .drones
+        .insert(0, (cmd_send, event_recv, packet_send, packet_recv));
+    let console = Console::new(Arc::new(Rpc::new(&network, &correct_config())));
+    let metrics = Metrics::default();
+    metrics.register(&network);
+    let mut app = App::default();
+
+    assert_eq!(type_line(&mut app, &console, "pdr 0 0.5"), Flow::Continue);
+    assert!(matches!(
+        commands.recv_timeout(Duration::from_secs(1)),
+        Ok(DroneCommand::SetPacketDropRate(_))
+    ));
+    assert_eq!(app.output, "ok: pdr 0 0.5");
+    assert!(app.input.is_empty());
+
+    for c in "unl".chars() {
+        app.key(KeyEvent::from(KeyCode::Char(c)), &console);
+    }
+    app.key(KeyEvent::from(KeyCode::Tab), &console);
+    assert_eq!(app.input, "unlink ");
+
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    terminal
+        .draw(|frame| app.draw(frame, &console, &metrics))
+        .unwrap();
+    let screen: String = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect();
+    assert!(screen.contains("Topology"));
+    assert!(screen.contains("D0"));
+    assert!(screen.contains("Event log"));
+    assert!(screen.contains("> unlink"));
+
+    for _ in 0..200 {
+        app.key(KeyEvent::from(KeyCode::PageUp), &console);
+    }
+    assert_eq!(app.scroll, EVENT_HISTORY);
+    app.key(KeyEvent::from(KeyCode::Down), &console);
+    assert_eq!(app.scroll, EVENT_HISTORY - 1);
+
+    app.input.clear();
+    assert_eq!(type_line(&mut app, &console, "quit"), Flow::Quit);
+    assert_eq!(app.key(KeyEvent::from(KeyCode::Esc), &console), Flow::Quit);
+}
@@ -62,9 +62,10 @@ pub(super) fn check_server_connections(servers: &[Server], drones_ids: &[NodeId]
     })
 }
 
-pub(super) fn check_bidirectional(graph: &DiGraphMap<u8, u8>) -> bool {
-    for node in graph.nodes() {
-        if !graph
+/// a node whose incoming links differ from its outgoing ones, if any
+fn unidirectional_node(graph: &DiGraphMap<u8, u8>) -> Option<NodeId> {
+    graph.nodes().find(|&node| {
+        !graph
             .edges_directed(node, Incoming)
             .map(|(_, b, _)| b)
             .sorted()
@@ -72,12 +73,11 @@ pub(super) fn check_bidirectional(graph: &DiGraphMap<u8, u8>) -> bool {
                 .edges_directed(node, Outgoing)
                 .map(|(a, _, _)| a)
                 .sorted())
-        {
-            error!("Graph is not bidirectional, problematic node: {node}");
-            return false;
-        }
-    }
-    true
+    })
+}
+
+pub(super) fn check_bidirectional(graph: &DiGraphMap<u8, u8>) -> bool {
+    unidirectional_node(graph).is_none()
 }
 
 /// Checks that the topology respects all the necessary constraints
@@ -86,6 +86,21 @@ pub(super) fn check_topology_constraints(
     clients: &[Client],
     servers: &[Server],
 ) -> bool {
+    match topology_error(drones, clients, servers) {
+        Some(e) => {
+            error!("{e}");
+            false
+        }
+        None => true,
+    }
+}
+
+/// first constraint the topology breaks, without logging it
+pub(super) fn topology_error(
+    drones: &[Drone],
+    clients: &[Client],
+    servers: &[Server],
+) -> Option<String> {
     let drones_id: Vec<u8> = drones.iter().map(|drone| drone.id).collect();
     let client_id: Vec<u8> = clients.iter().map(|client| client.id).collect();
     let servers_id: Vec<u8> = servers.iter().map(|server| server.id).collect();
@@ -96,8 +111,7 @@ pub(super) fn check_topology_constraints(
         || !check_client_connections(clients, &drones_id)
         || !check_server_connections(servers, &drones_id)
     {
-        error!("Duplicates or self-loops in graph edges");
-        return false;
+        return Some("Duplicates or self-loops in graph edges".to_owned());
     }
 
     let graph_init: Vec<(u8, u8, u8)> = drones
@@ -122,10 +136,46 @@ pub(super) fn check_topology_constraints(
         }
     }
 
-    if components > 1 || !check_bidirectional(&graph) {
-        error!("Graph is not connected");
-        return false;
+    if let Some(node) = unidirectional_node(&graph) {
+        return Some(format!(
+            "Graph is not bidirectional, problematic node: {node}"
+        ));
+    }
+    if components > 1 {
+        return Some("Graph is not connected".to_owned());
     }
 
-    true
+    None
+}
+
+fn id_list(ids: &[NodeId]) -> String {
+    ids.iter().sorted().join(", ")
+}
+
+/// the TOML of a topology, nodes sorted by kind and id and neighbours
+/// sorted, in the layout of the files under `config/`
+pub(super) fn config_to_toml(drones: &[Drone], clients: &[Client], servers: &[Server]) -> String {
+    let drones = drones.iter().sorted_by_key(|d| d.id).map(|d| {
+        format!(
+            "[[drone]]\nid = {}\nconnected_node_ids = [{}]\npdr = {}\n",
+            d.id,
+            id_list(&d.connected_node_ids),
+            d.pdr
+        )
+    });
+    let clients = clients.iter().sorted_by_key(|c| c.id).map(|c| {
+        format!(
+            "[[client]]\nid = {}\nconnected_drone_ids = [{}]\n",
+            c.id,
+            id_list(&c.connected_drone_ids)
+        )
+    });
+    let servers = servers.iter().sorted_by_key(|s| s.id).map(|s| {
+        format!(
+            "[[server]]\nid = {}\nconnected_drone_ids = [{}]\n",
+            s.id,
+            id_list(&s.connected_drone_ids)
+        )
+    });
+    chain![drones, clients, servers].join("\n")
 }
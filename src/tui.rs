@@ -0,0 +1,254 @@
+use std::{sync::Arc, time::Duration};
+
+use log::error;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Table},
+    Frame,
+};
+
+use crate::{
+    metrics::Metrics,
+    network::{drain_events, Network, NodeKind},
+    repl::{complete, parse_line, Console, ReplCommand, EVENT_HISTORY},
+    rpc::Rpc,
+};
+
+/// time between two redraws when nothing is typed
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// lines of the output pane, borders included
+const OUTPUT_HEIGHT: u16 = 7;
+
+fn kind_color(kind: NodeKind) -> Color {
+    match kind {
+        NodeKind::Drone => Color::Cyan,
+        NodeKind::WebClient => Color::Green,
+        NodeKind::ChatClient => Color::Magenta,
+        NodeKind::Server => Color::Red,
+    }
+}
+
+/// one letter per kind, as drawn in the topology pane
+fn kind_letter(kind: NodeKind) -> char {
+    match kind {
+        NodeKind::Drone => 'D',
+        NodeKind::WebClient => 'W',
+        NodeKind::ChatClient => 'C',
+        NodeKind::Server => 'S',
+    }
+}
+
+/// whether the loop goes on after a key
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Flow {
+    Continue,
+    Quit,
+}
+
+/// what the terminal UI shows besides the network
+#[derive(Debug, Default)]
+pub(super) struct App {
+    /// text of the command bar
+    pub(super) input: String,
+    /// result of the last command
+    pub(super) output: String,
+    /// events hidden at the bottom of the log, 0 follows the newest; never
+    /// more than the console keeps
+    pub(super) scroll: usize,
+}
+
+impl App {
+    /// runs the command in the bar, with the syntax of the console
+    fn submit(&mut self, console: &Console) -> Flow {
+        let line: String = std::mem::take(&mut self.input);
+        self.output = match parse_line(&line) {
+            Ok(None) => String::new(),
+            Ok(Some(ReplCommand::Quit)) => return Flow::Quit,
+            Ok(Some(command)) => match console.execute(&command) {
+                Ok(out) if out.is_empty() => format!("ok: {line}"),
+                Ok(out) => out,
+                Err(e) => e,
+            },
+            Err(e) => e,
+        };
+        Flow::Continue
+    }
+
+    pub(super) fn key(&mut self, key: KeyEvent, console: &Console) -> Flow {
+        match key.code {
+            KeyCode::Esc => return Flow::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Flow::Quit;
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Tab => {
+                let (start, candidates) = complete(&self.input, &console.ids());
+                match candidates.as_slice() {
+                    [only] => {
+                        self.input.truncate(start);
+                        self.input.push_str(only);
+                        self.input.push(' ');
+                    }
+                    [] => {}
+                    many => self.output = many.join(" "),
+                }
+            }
+            KeyCode::Enter => return self.submit(console),
+            KeyCode::Up => self.scroll = (self.scroll + 1).min(EVENT_HISTORY),
+            KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageUp => self.scroll = (self.scroll + 10).min(EVENT_HISTORY),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::End => self.scroll = 0,
+            _ => {}
+        }
+        Flow::Continue
+    }
+
+    fn draw_topology(frame: &mut Frame, area: Rect, console: &Console) {
+        let lines: Vec<Line> = console
+            .adjacency()
+            .into_iter()
+            .map(|(node, neighbours)| {
+                let name = Span::styled(
+                    format!("{}{:<3}", kind_letter(node.kind), node.id),
+                    Style::new().fg(kind_color(node.kind)).bold(),
+                );
+                let links: String = neighbours.map_or_else(
+                    || " ✗ crashed".to_owned(),
+                    |n| {
+                        let ids: Vec<String> = n.iter().map(ToString::to_string).collect();
+                        format!(" ── {}", ids.join(" "))
+                    },
+                );
+                Line::from(vec![name, Span::raw(links)])
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Topology ")),
+            area,
+        );
+    }
+
+    fn draw_counts(frame: &mut Frame, area: Rect, metrics: &Metrics) {
+        let rows: Vec<Row> = metrics
+            .snapshot()
+            .into_iter()
+            .map(|(id, (kind, m))| {
+                Row::new(vec![
+                    format!("{}{id}", kind_letter(kind)),
+                    m.events.to_string(),
+                    m.packets_sent.to_string(),
+                    m.packets_dropped.to_string(),
+                    m.shortcuts.to_string(),
+                    m.files_delivered.to_string(),
+                ])
+                .style(Style::new().fg(kind_color(kind)))
+            })
+            .collect();
+        let header = Row::new(["node", "events", "sent", "dropped", "shortcuts", "files"])
+            .style(Style::new().bold());
+        let table = Table::new(rows, [Constraint::Length(9); 6])
+            .header(header)
+            .block(Block::bordered().title(" Events per node "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect, console: &Console) {
+        let visible: usize = usize::from(area.height.saturating_sub(2));
+        let events = console.recent(EVENT_HISTORY);
+        let end: usize = events.len().saturating_sub(self.scroll);
+        let lines: Vec<Line> = events[end.saturating_sub(visible)..end]
+            .iter()
+            .map(|e| Line::raw(format!("{} {} {} {}", e.kind, e.node, e.event, e.summary)))
+            .collect();
+        let title: String = if self.scroll == 0 {
+            " Event log ".to_owned()
+        } else {
+            format!(" Event log (-{}, End to follow) ", self.scroll)
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    pub(super) fn draw(&self, frame: &mut Frame, console: &Console, metrics: &Metrics) {
+        let [main, output, bar] = Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(OUTPUT_HEIGHT),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+        let [topology, right] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+        let [counts, log] =
+            Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(right);
+
+        Self::draw_topology(frame, topology, console);
+        Self::draw_counts(frame, counts, metrics);
+        self.draw_log(frame, log, console);
+        frame.render_widget(
+            Paragraph::new(self.output.as_str()).block(Block::bordered().title(" Output ")),
+            output,
+        );
+        frame.render_widget(
+            Paragraph::new(format!("> {}", self.input))
+                .block(Block::bordered().title(" Command (help, Tab completes, Esc quits) ")),
+            bar,
+        );
+        let cursor: u16 = u16::try_from(self.input.chars().count() + 3).unwrap_or(u16::MAX);
+        frame.set_cursor_position((bar.x.saturating_add(cursor), bar.y + 1));
+    }
+}
+
+/// replaces the simulation controller with a terminal UI until Esc or
+/// `quit`; shortcuts are routed in the background
+pub(super) fn run_tui(network: Network, rpc: Arc<Rpc>) {
+    let console = Console::new(rpc);
+    let metrics: Arc<Metrics> = Arc::default();
+    metrics.register(&network);
+    let network: Network = metrics.wrap_network(console.recorder().wrap_network(network));
+    let router = drain_events(&network);
+
+    let mut terminal = match ratatui::try_init() {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Unable to open the terminal: {e}");
+            return;
+        }
+    };
+    let mut app = App::default();
+    loop {
+        if let Err(e) = terminal.draw(|frame| app.draw(frame, &console, &metrics)) {
+            error!("Unable to draw the terminal: {e}");
+            break;
+        }
+        let key: KeyEvent = match event::poll(REFRESH).and_then(|ready| {
+            if ready {
+                event::read().map(Some)
+            } else {
+                Ok(None)
+            }
+        }) {
+            Ok(Some(Event::Key(key))) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => {
+                error!("Unable to read the terminal: {e}");
+                break;
+            }
+        };
+        if app.key(key, &console) == Flow::Quit {
+            break;
+        }
+    }
+    ratatui::restore();
+    router.log_counts();
+}